@@ -93,9 +93,12 @@ async fn main() -> Result<()> {
         println!("续传可节省费用: {} lamports", savings);
         
         // 成本效益分析
-        let analysis = fee_optimizer.cost_benefit_analysis(deployment, &network_stats).await?;
+        let ledger = state_manager.get_chunk_ledger(&deployment_id);
+        let analysis = fee_optimizer
+            .cost_benefit_analysis(deployment, &ledger, 0, &network_stats)
+            .await?;
         println!("建议操作: {}", analysis.recommended_action);
-        println!("盈亏平衡点: {} lamports", analysis.break_even_point);
+        println!("重新开始需要: {} lamports", analysis.restart_cost);
     }
 
     // Demo 5: 查找可续传的部署
@@ -31,6 +31,7 @@ async fn main() -> Result<()> {
         packet_loss_rate: 0.05,
         congestion_level: CongestionLevel::Medium,
         optimal_chunk_size: 4096,
+        ..NetworkStats::default()
     };
     
     let cost_stats = CostStats {
@@ -39,6 +40,7 @@ async fn main() -> Result<()> {
         saved_fees: 0,
         transaction_count: 50,
         retry_count: 0,
+        ..CostStats::default()
     };
     
     // 更新部署信息
@@ -124,6 +126,7 @@ async fn main() -> Result<()> {
             saved_fees: 50000,
             transaction_count: 25,
             retry_count: 2,
+            ..CostStats::default()
         };
         state_manager.update_deployment(updated)?;
     }
@@ -209,6 +212,7 @@ async fn main() -> Result<()> {
             max_retries: 2,
             auto_resume: true,
             fee_optimization: true,
+            ..ResumeConfig::default()
         },
         CongestionLevel::Medium => ResumeConfig {
             chunk_size: 4096,
@@ -217,6 +221,7 @@ async fn main() -> Result<()> {
             max_retries: 3,
             auto_resume: true,
             fee_optimization: true,
+            ..ResumeConfig::default()
         },
         CongestionLevel::High => ResumeConfig {
             chunk_size: 2048,
@@ -225,6 +230,7 @@ async fn main() -> Result<()> {
             max_retries: 5,
             auto_resume: true,
             fee_optimization: true,
+            ..ResumeConfig::default()
         },
         CongestionLevel::Critical => ResumeConfig {
             chunk_size: 1024,
@@ -233,6 +239,7 @@ async fn main() -> Result<()> {
             max_retries: 10,
             auto_resume: false,
             fee_optimization: true,
+            ..ResumeConfig::default()
         },
     };
     
@@ -1,6 +1,7 @@
 use warp::{Filter, Reply};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use crate::core::{config::ToolConfig, state::StateReader, FeeOptimizer, NetworkAnalyzer, RpcPool};
 use tokio::process::Command;
 use std::path::Path;
 use std::sync::Mutex;
@@ -88,6 +89,24 @@ pub struct DeploymentStatus {
     pub message: String,
 }
 
+/// 需要读取部署记录或访问RPC的路由共享的上下文
+#[derive(Clone)]
+pub struct ApiContext {
+    pub state: StateReader,
+    pub rpc_pool: RpcPool,
+    pub tool_config: ToolConfig,
+}
+
+impl ApiContext {
+    pub fn new(state: StateReader, rpc_pool: RpcPool, tool_config: ToolConfig) -> Self {
+        Self { state, rpc_pool, tool_config }
+    }
+}
+
+fn with_context(context: ApiContext) -> impl Filter<Extract = (ApiContext,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || context.clone())
+}
+
 // 部署统计API
 pub fn stats_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
     warp::path!("api" / "stats")
@@ -154,6 +173,14 @@ pub fn resume_deployment_route() -> impl Filter<Extract = impl Reply, Error = wa
         .and_then(resume_deployment)
 }
 
+// 续传建议API
+pub fn advice_route(context: ApiContext) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "deployments" / String / "advice")
+        .and(warp::get())
+        .and(with_context(context))
+        .and_then(get_deployment_advice)
+}
+
 // 实现处理函数
 async fn get_stats() -> Result<impl Reply, warp::Rejection> {
     // 这里应该从数据库获取真实统计数据
@@ -176,6 +203,40 @@ async fn get_network_status() -> Result<impl Reply, warp::Rejection> {
     Ok(warp::reply::json(&network_status))
 }
 
+async fn get_deployment_advice(deployment_id: String, context: ApiContext) -> Result<impl Reply, warp::Rejection> {
+    use warp::http::StatusCode;
+    
+    let error = |status: StatusCode, message: String| {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "success": false, "message": message })),
+            status,
+        ))
+    };
+    let Ok(id) = uuid::Uuid::parse_str(&deployment_id) else {
+        return error(StatusCode::BAD_REQUEST, format!("无效的部署ID: {}", deployment_id));
+    };
+    let Some(deployment) = context.state.get_deployment(&id) else {
+        return error(StatusCode::NOT_FOUND, "未找到部署记录".to_string());
+    };
+    
+    let mut network_analyzer = NetworkAnalyzer::with_pool(context.rpc_pool.clone());
+    network_analyzer.set_congestion_thresholds(context.tool_config.congestion.clone());
+    let network_stats = match network_analyzer.generate_network_stats().await {
+        Ok(stats) => stats,
+        Err(e) => return error(StatusCode::BAD_GATEWAY, format!("分析网络状况失败: {}", e)),
+    };
+    let ledger = context.state.get_chunk_ledger(&id);
+    let learned = context.state.get_upload_tuning(&context.rpc_pool.primary_url());
+    let mut fee_optimizer = FeeOptimizer::with_pool(context.rpc_pool.clone());
+    match fee_optimizer
+        .advise(&deployment, &ledger, learned.as_ref(), &network_stats, &context.tool_config.resume)
+        .await
+    {
+        Ok(advice) => Ok(warp::reply::with_status(warp::reply::json(&advice), StatusCode::OK)),
+        Err(e) => error(StatusCode::BAD_GATEWAY, format!("生成续传建议失败: {}", e)),
+    }
+}
+
 async fn scan_resumable_deployments() -> Result<impl Reply, warp::Rejection> {
     // 扫描可续传的部署
     let resumable: Vec<ResumableDeployment> = vec![
@@ -677,7 +738,7 @@ async fn resume_deployment(deployment_id: String) -> Result<impl Reply, warp::Re
 }
 
 // 创建所有路由的组合
-pub fn create_routes() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+pub fn create_routes(context: ApiContext) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
     stats_route()
         .or(network_route())
         .or(scan_route())
@@ -687,4 +748,5 @@ pub fn create_routes() -> impl Filter<Extract = impl Reply, Error = warp::Reject
         .or(test_failure_route())
         .or(deployment_status_route())
         .or(resume_deployment_route())
+        .or(advice_route(context))
 } 
\ No newline at end of file
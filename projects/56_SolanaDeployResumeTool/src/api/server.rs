@@ -1,13 +1,13 @@
 use warp::Filter;
 // use std::convert::Infallible;
-use crate::api::routes;
+use crate::api::routes::{self, ApiContext};
 
-pub async fn start_server(port: u16, rpc_url: String) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn start_server(port: u16, context: ApiContext) -> Result<(), Box<dyn std::error::Error>> {
     println!("🚀 启动Solana部署续传工具Web服务器...");
-    println!("🌐 RPC端点: {}", rpc_url);
+    println!("🌐 RPC端点: {}", context.rpc_pool.primary_url());
     
     // API路由
-    let api = routes::create_routes();
+    let api = routes::create_routes(context);
 
     // 静态文件服务 - 前端页面
     let static_files = warp::path("static")
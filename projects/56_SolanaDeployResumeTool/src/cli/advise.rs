@@ -0,0 +1,70 @@
+use crate::core::optimizer::DeploymentAdvice;
+use crate::core::{config::ToolConfig, FeeOptimizer, NetworkAnalyzer, RpcPool, StateManager};
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub async fn handle_advise(
+    matches: &clap::ArgMatches<'_>,
+    state_manager: &StateManager,
+    network_analyzer: &mut NetworkAnalyzer,
+    fee_optimizer: &mut FeeOptimizer,
+    rpc_pool: &RpcPool,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deployment_id = Uuid::from_str(matches.value_of("deployment_id").unwrap())?;
+    let json = matches.value_of("format") == Some("json");
+    let deployment = state_manager
+        .get_deployment(&deployment_id)
+        .ok_or_else(|| format!("部署不存在: {}", deployment_id))?;
+
+    let network_stats = network_analyzer.generate_network_stats().await?;
+    let ledger = state_manager.get_chunk_ledger(&deployment_id);
+    let learned = state_manager.get_upload_tuning(&rpc_pool.primary_url());
+    let advice = fee_optimizer
+        .advise(deployment, &ledger, learned.as_ref(), &network_stats, &tool_config.resume)
+        .await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&advice)?);
+    } else {
+        print_advice(&advice);
+    }
+    Ok(())
+}
+
+fn print_advice(advice: &DeploymentAdvice) {
+    let cost = &advice.cost;
+    println!("🆔 部署ID: {} ({:?})", advice.deployment_id, advice.status);
+    println!("📈 已确认 {} bytes，剩余 {} bytes", cost.confirmed_bytes, cost.remaining_bytes);
+    println!("💸 单笔写入交易费用: {} lamports (块大小 {}B)", cost.fee_per_transaction, advice.chunk_size);
+    println!("🔄 续传: {} 笔交易，{} lamports", cost.resume_transactions, cost.resume_cost);
+    println!(
+        "🆕 重新开始: {} 笔交易，{} lamports，另需存入租金 {} lamports",
+        cost.restart_transactions, cost.restart_cost, cost.restart_rent_lamports
+    );
+    println!("💰 续传可节省: {} lamports", cost.potential_savings);
+    println!("💡 {}", cost.recommended_action);
+
+    println!("\n🧱 上传账户租金: 共 {} lamports", advice.rent_held_lamports);
+    for buffer in &advice.buffers {
+        match buffer.lamports {
+            Some(lamports) => println!("  {} ({:?}): {} lamports", buffer.pubkey, buffer.status, lamports),
+            None => println!("  {} ({:?}): 账户已关闭", buffer.pubkey, buffer.status),
+        }
+    }
+
+    if advice.recommendations.is_empty() {
+        println!("\n✅ 当前配置无需调整");
+        return;
+    }
+    println!("\n📋 配置建议:");
+    for recommendation in &advice.recommendations {
+        println!("  [{:?}] {}: {}", recommendation.priority, recommendation.category, recommendation.description);
+        if let Some(ref change) = recommendation.config_change {
+            println!("     resume.{}: {} -> {}", change.field, change.current, change.suggested);
+        }
+        if let Some(savings) = recommendation.estimated_savings {
+            println!("     预计节省 {} lamports", savings);
+        }
+    }
+}
@@ -1,23 +1,155 @@
-use crate::core::NetworkAnalyzer;
+use crate::core::history::{self, DeploymentWindow};
+use crate::core::{cluster::Cluster, types::*, NetworkAnalyzer, RpcPool, StateManager};
+use chrono::Utc;
 
 pub async fn handle_analyze(
     matches: &clap::ArgMatches<'_>,
     network_analyzer: &mut NetworkAnalyzer,
+    rpc_pool: &RpcPool,
+    state_manager: &StateManager,
+    cluster: Cluster,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let duration_str = matches.value_of("duration").unwrap();
     let duration: u64 = duration_str.parse()?;
+    let window_count: usize = matches.value_of("windows").unwrap().parse()?;
+    let json = matches.value_of("format") == Some("json");
 
-    println!("🔍 开始网络分析，持续 {} 秒...", duration);
+    if !json {
+        println!("🔍 开始网络分析，持续 {} 秒...", duration);
+    }
     
     let stats_history = network_analyzer.monitor_network_changes(duration).await?;
     
+    // 保存本次样本，与历史样本一起按时段统计
+    let cluster_name = cluster.to_string();
+    for stats in &stats_history {
+        state_manager.record_network_sample(&NetworkSample::from_stats(&cluster_name, stats))?;
+    }
+    let samples = state_manager.get_network_samples(&cluster_name);
+    let windows = history::recommend_windows(&samples, Utc::now(), window_count);
+
+    if json {
+        let output = serde_json::json!({
+            "cluster": cluster_name,
+            "current": stats_history.last(),
+            "history_samples": samples.len(),
+            "recommended_windows": windows,
+            "rpc_endpoints": rpc_pool.status(),
+            "rate_limit": rpc_pool.limiter().usage(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    
     println!("\n📊 分析结果:");
     if let Some(recommendation) = network_analyzer.predict_best_deployment_time(&stats_history) {
         println!("💡 建议: {}", recommendation);
     }
 
-    let avg_latency: f64 = stats_history.iter().map(|s| s.latency_ms).sum::<f64>() / stats_history.len() as f64;
-    println!("📡 平均延迟: {:.1}ms", avg_latency);
+    if !stats_history.is_empty() {
+        let avg_latency: f64 = stats_history.iter().map(|s| s.latency_ms).sum::<f64>() / stats_history.len() as f64;
+        println!("📡 平均延迟: {:.1}ms", avg_latency);
+    }
+    if let Some(latest) = stats_history.last() {
+        let signals = &latest.congestion_signals;
+        println!("🚥 最近一次拥堵等级: {:?}", latest.congestion_level);
+        for reason in &latest.congestion_reasons {
+            println!("   - {}", reason);
+        }
+        if let (Some(slot_time), Some(tps)) = (signals.slot_time_ms, signals.tps) {
+            println!(
+                "⛓️  出块时间 {:.0}ms | TPS {:.0} (非投票 {})",
+                slot_time,
+                tps,
+                signals.non_vote_tps.map_or("未知".to_string(), |tps| format!("{:.0}", tps))
+            );
+        }
+        if let (Some(p50), Some(p75), Some(p90)) =
+            (signals.priority_fee_p50, signals.priority_fee_p75, signals.priority_fee_p90)
+        {
+            println!("💰 优先费 (micro-lamports/CU): p50 {} | p75 {} | p90 {}", p50, p75, p90);
+        }
+    }
+    match stats_history.last().map(|s| s.throughput_bps) {
+        Some(throughput) if throughput > 0.0 => println!("📶 吞吐量: {:.1} KB/s", throughput / 1024.0),
+        _ => println!("📶 吞吐量: 暂无实测数据 (可使用 --probe-throughput 主动探测)"),
+    }
+
+    println!("\n🌐 RPC节点排名:");
+    for (rank, endpoint) in rpc_pool.status().iter().enumerate() {
+        println!(
+            "  {}. {} 评分 {:.0} | 断路器 {} | 平均延迟 {:.1}ms | 成功率 {:.1}% | 健康 {}",
+            rank + 1,
+            endpoint.url,
+            endpoint.score,
+            endpoint.circuit_state,
+            endpoint.average_latency_ms,
+            endpoint.success_rate * 100.0,
+            match endpoint.healthy {
+                Some(true) => "是",
+                Some(false) => "否",
+                None => "未知",
+            }
+        );
+    }
+
+    let usage = rpc_pool.limiter().usage();
+    println!("\n🚦 RPC限速与重试预算:");
+    if usage.requests_per_second > 0.0 {
+        println!("  限速: {:.1} 请求/秒，突发 {}", usage.requests_per_second, usage.burst);
+    } else {
+        println!("  限速: 不限");
+    }
+    println!(
+        "  请求: {} 次，其中 {} 次被限速，累计等待 {:.1} 秒",
+        usage.total_requests,
+        usage.throttled_requests,
+        usage.total_wait_ms as f64 / 1000.0
+    );
+    println!(
+        "  重试预算: 最近 {} 秒内成功 {} 次、重试 {} 次 (上限 {:.0}%)，累计拒绝 {} 次重试",
+        usage.retry_budget_window_secs,
+        usage.window_successes,
+        usage.window_retries,
+        usage.retry_budget_ratio * 100.0,
+        usage.denied_retries
+    );
+
+    print_windows(&cluster_name, samples.len(), &windows);
 
     Ok(())
+}
+
+/// 以表格形式打印推荐的部署时间窗口
+fn print_windows(cluster: &str, sample_count: usize, windows: &[DeploymentWindow]) {
+    println!("\n🕒 推荐部署时间窗口 ({}，基于 {} 个历史样本，时间为UTC):", cluster, sample_count);
+    if windows.is_empty() {
+        println!(
+            "  历史样本不足（每个时段至少需要 {} 个样本），请在不同时间多次运行 analyze",
+            history::MIN_WINDOW_SAMPLES
+        );
+        return;
+    }
+    println!(
+        "  {:<4} {:<24} {:>6} {:<10} {:>16} {:>10}",
+        "排名", "时间窗口", "样本", "预期拥堵", "预期优先费p50", "预期成功率"
+    );
+    for (rank, window) in windows.iter().enumerate() {
+        println!(
+            "  {:<4} {:<24} {:>6} {:<10} {:>16} {:>9.1}%",
+            rank + 1,
+            format!(
+                "{} {}-{}",
+                window.weekday,
+                window.start.format("%m-%d %H:%M"),
+                window.end.format("%H:%M")
+            ),
+            window.samples,
+            format!("{:?}", window.expected_congestion),
+            window
+                .expected_priority_fee
+                .map_or("未知".to_string(), |fee| fee.to_string()),
+            window.expected_success_rate * 100.0
+        );
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,154 @@
+use crate::cli::confirm::confirm_immutable;
+use crate::cli::keys::read_signers;
+use crate::core::{
+    config::ToolConfig,
+    signers::{DeploymentSigners, KeyRole},
+    state::DEFAULT_LEASE_TTL_SECS,
+    types::*,
+    ResumeEngine, StateManager,
+};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub async fn handle_set_buffer_authority(
+    matches: &clap::ArgMatches<'_>,
+    mut state_manager: StateManager,
+    resume_engine: ResumeEngine,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deployment_id = Uuid::from_str(matches.value_of("deployment_id").unwrap())?;
+    let new_authority = Pubkey::from_str(matches.value_of("new_authority").unwrap())?;
+    let signers = read_signers(matches, tool_config, None)?;
+
+    // 持有租约，避免与正在进行的上传同时修改buffer
+    let lease = state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS)?;
+    let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
+    let result = set_buffer_authority(&mut state_manager, &resume_engine, &deployment_id, &new_authority, &signers).await;
+    state_manager.release_lease(&lease)?;
+    result
+}
+
+async fn set_buffer_authority(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    deployment_id: &Uuid,
+    new_authority: &Pubkey,
+    signers: &DeploymentSigners,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut deployment = state_manager
+        .get_deployment(deployment_id)
+        .cloned()
+        .ok_or_else(|| format!("部署不存在: {}", deployment_id))?;
+    let previous = deployment
+        .buffer_accounts
+        .last()
+        .and_then(|buffer| buffer.authority)
+        .or(deployment.keys.as_ref().map(|keys| keys.buffer_authority))
+        .unwrap_or(signers.buffer_authority().pubkey());
+    check_authority(KeyRole::BufferAuthority, &previous, signers)?;
+
+    println!("🔑 转移buffer授权: {} -> {}", previous, new_authority);
+    let signature = resume_engine
+        .set_buffer_authority(&mut deployment, new_authority, signers)
+        .await?;
+    state_manager.update_deployment(deployment.clone())?;
+
+    let buffer = deployment.buffer_accounts.last().cloned().ok_or("部署没有buffer")?;
+    // 复用表中的记录随之更新，之后只有新授权能用它完成部署
+    if let Some(ref fingerprint) = deployment.program_fingerprint {
+        for mut entry in state_manager.get_reusable_buffers(&fingerprint.sha256) {
+            if entry.buffer.pubkey == buffer.pubkey {
+                entry.buffer.authority = buffer.authority;
+                state_manager.register_reusable_buffer(&entry)?;
+            }
+        }
+    }
+    state_manager.log_event(
+        deployment_id,
+        EventType::Info,
+        "转移buffer授权".to_string(),
+        Some(serde_json::json!({
+            "buffer": buffer.pubkey.to_string(),
+            "previous_authority": previous.to_string(),
+            "new_authority": new_authority.to_string(),
+            "signature": signature.to_string(),
+        })),
+    )?;
+    println!("✅ buffer {} 的授权已转移，交易: {}", buffer.pubkey, signature);
+    println!("💡 新授权可使用 resume --deployment-id {} 完成部署", deployment_id);
+    Ok(())
+}
+
+pub async fn handle_set_upgrade_authority(
+    matches: &clap::ArgMatches<'_>,
+    mut state_manager: StateManager,
+    resume_engine: ResumeEngine,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deployment_id = Uuid::from_str(matches.value_of("deployment_id").unwrap())?;
+    let new_authority = matches.value_of("new_authority").map(Pubkey::from_str).transpose()?;
+    let mut deployment = state_manager
+        .get_deployment(&deployment_id)
+        .cloned()
+        .ok_or_else(|| format!("部署不存在: {}", deployment_id))?;
+    let program_id = deployment
+        .program_id
+        .filter(|_| deployment.status == DeploymentStatus::Completed)
+        .ok_or("部署尚未完成，没有可修改的程序")?;
+
+    if new_authority.is_none() {
+        confirm_immutable(&program_id, matches.value_of("confirm_program_id"))?;
+    }
+    let signers = read_signers(matches, tool_config, None)?;
+    let previous = deployment
+        .keys
+        .as_ref()
+        .map(|keys| keys.upgrade_authority)
+        .unwrap_or(signers.upgrade_authority().pubkey());
+    check_authority(KeyRole::UpgradeAuthority, &previous, &signers)?;
+
+    let lease = state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS)?;
+    let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
+    let result = resume_engine
+        .set_upgrade_authority(&mut deployment, &program_id, new_authority.as_ref(), &signers)
+        .await;
+    let signature = match result {
+        Ok(signature) => signature,
+        Err(e) => {
+            state_manager.release_lease(&lease)?;
+            return Err(e.into());
+        }
+    };
+    state_manager.update_deployment(deployment)?;
+    state_manager.log_event(
+        &deployment_id,
+        EventType::Info,
+        match new_authority {
+            Some(_) => "修改升级授权".to_string(),
+            None => "程序已不可升级".to_string(),
+        },
+        Some(serde_json::json!({
+            "program_id": program_id.to_string(),
+            "previous_authority": previous.to_string(),
+            "new_authority": new_authority.map(|pubkey| pubkey.to_string()),
+            "signature": signature.to_string(),
+        })),
+    )?;
+    state_manager.release_lease(&lease)?;
+
+    match new_authority {
+        Some(authority) => println!("✅ 程序 {} 的升级授权已改为 {}，交易: {}", program_id, authority, signature),
+        None => println!("🔒 程序 {} 已永久不可升级，交易: {}", program_id, signature),
+    }
+    Ok(())
+}
+
+/// 签名前确认提供的密钥就是当前授权，避免发出注定失败的交易
+fn check_authority(role: KeyRole, current: &Pubkey, signers: &DeploymentSigners) -> Result<(), Box<dyn std::error::Error>> {
+    match signers.get(role) {
+        Some(keypair) if keypair.pubkey() == *current => Ok(()),
+        _ => Err(format!("当前{}为 {}，请通过 {} 提供该密钥", role, current, role.flag()).into()),
+    }
+}
@@ -11,5 +11,10 @@ pub async fn handle_cleanup(
     let removed_count = state_manager.cleanup_completed(days)?;
     println!("✅ 已清理 {} 条记录", removed_count);
 
+    let expired_buffers = state_manager.cleanup_expired_buffers(days * 24)?;
+    if expired_buffers > 0 {
+        println!("🧱 已移除 {} 条过期的可复用buffer记录", expired_buffers);
+    }
+
     Ok(())
 } 
\ No newline at end of file
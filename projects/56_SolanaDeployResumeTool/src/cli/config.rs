@@ -0,0 +1,22 @@
+use crate::core::config::ToolConfig;
+
+pub fn handle_config(
+    matches: &clap::ArgMatches<'_>,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        ("show", Some(_)) => {
+            println!("⚙️  生效配置 (profile: {}):", tool_config.profile.as_deref().unwrap_or("无"));
+            println!("{}", toml::to_string_pretty(tool_config)?);
+            println!("📚 配置来源 (优先级从低到高):");
+            for source in &tool_config.sources {
+                println!("  - {}", source);
+            }
+        }
+        _ => {
+            println!("使用 config show 查看生效配置");
+        }
+    }
+
+    Ok(())
+}
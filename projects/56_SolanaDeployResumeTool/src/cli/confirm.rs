@@ -0,0 +1,42 @@
+use crate::core::cluster::Cluster;
+use solana_sdk::pubkey::Pubkey;
+use std::io::{self, BufRead, Write};
+
+/// 在需要确认的集群上执行写操作前询问用户
+pub fn confirm_cluster_operation(cluster: Cluster, assume_yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !cluster.requires_confirmation() || assume_yes {
+        return Ok(());
+    }
+
+    print!("⚠️  即将在 {} 上执行会产生真实费用的操作，确认继续? [y/N] ", cluster);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err("操作已取消".into())
+    }
+}
+
+/// 使程序不可升级前要求输入程序ID确认，该操作无法撤销，不受 --yes 影响
+pub fn confirm_immutable(program_id: &Pubkey, confirmed: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let expected = program_id.to_string();
+    let answer = match confirmed {
+        Some(confirmed) => confirmed.to_string(),
+        None => {
+            println!("⚠️  程序 {} 将永久不可升级，此操作无法撤销", expected);
+            print!("请输入程序ID确认: ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer)?;
+            answer
+        }
+    };
+    if answer.trim() == expected {
+        Ok(())
+    } else {
+        Err("程序ID不匹配，操作已取消".into())
+    }
+}
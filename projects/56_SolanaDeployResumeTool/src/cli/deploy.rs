@@ -1,6 +1,11 @@
-use crate::core::{types::*, StateManager, ResumeEngine, NetworkAnalyzer, FeeOptimizer};
-use solana_sdk::signature::{read_keypair_file, Signer};
+use crate::cli::keys::read_signers;
+use crate::core::{config::ToolConfig, planner::{ChunkPlan, ProgramDelta, WriteTransactionLayout}, resume::UploadSession, signers::DeploymentSigners, state::DEFAULT_LEASE_TTL_SECS, types::*, StateManager, ResumeEngine, NetworkAnalyzer, FeeOptimizer};
+use chrono::Utc;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
 use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
 
 pub async fn handle_deploy(
     matches: &clap::ArgMatches<'_>,
@@ -8,7 +13,7 @@ pub async fn handle_deploy(
     resume_engine: ResumeEngine,
     network_analyzer: &mut NetworkAnalyzer,
     fee_optimizer: &mut FeeOptimizer,
-    keypair_path: &str,
+    tool_config: &ToolConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let program_file = matches.value_of("program_file").unwrap();
     let loader_version_str = matches.value_of("loader_version").unwrap();
@@ -22,7 +27,7 @@ pub async fn handle_deploy(
     println!("🚀 开始新的程序部署...");
     println!("📄 程序文件: {}", program_file);
     println!("🔧 加载器版本: {:?}", loader_version);
-    println!("🔑 密钥对路径: {}", keypair_path);
+    println!("🔑 密钥对路径: {}", tool_config.keypair_path);
 
     // 检测程序文件
     let program_path = std::path::Path::new(program_file);
@@ -31,28 +36,31 @@ pub async fn handle_deploy(
     }
 
     // 展开密钥对路径
-    let expanded_keypair_path = if keypair_path.starts_with("~/") {
-        if let Some(home) = std::env::var("HOME").ok() {
-            keypair_path.replace("~", &home)
-        } else {
-            keypair_path.to_string()
-        }
-    } else {
-        keypair_path.to_string()
-    };
+    let expanded_keypair_path = tool_config.expanded_keypair_path();
 
     // 检查密钥对文件
-    if !Path::new(&expanded_keypair_path).exists() {
+    if !matches.is_present("fee_payer") && !Path::new(&expanded_keypair_path).exists() {
         return Err(format!("密钥对文件不存在: {}", expanded_keypair_path).into());
     }
 
-    // 读取密钥对
-    let payer_keypair = match read_keypair_file(&expanded_keypair_path) {
-        Ok(keypair) => keypair,
-        Err(e) => return Err(format!("无法读取密钥对文件: {}", e).into()),
-    };
+    // 读取付款账户和各授权的密钥对，未单独指定的授权由付款账户兼任
+    let signers = read_signers(matches, tool_config, None)?;
+    let keys = signers.keys();
 
-    println!("💰 付款账户: {}", payer_keypair.pubkey());
+    println!("💰 付款账户: {}", keys.fee_payer);
+    println!(
+        "🔗 浏览器: {}",
+        tool_config.cluster.explorer_address_url(&keys.fee_payer.to_string(), &tool_config.rpc_url)
+    );
+    if keys.buffer_authority != keys.fee_payer {
+        println!("🔑 buffer授权: {}", keys.buffer_authority);
+    }
+    if keys.upgrade_authority != keys.fee_payer {
+        println!("🔑 升级授权: {}", keys.upgrade_authority);
+    }
+    if let Some(program) = keys.program {
+        println!("🆔 程序地址: {}", program);
+    }
 
     // 读取程序数据
     let program_data = std::fs::read(program_path)?;
@@ -62,46 +70,435 @@ pub async fn handle_deploy(
     println!("🔍 分析网络状况...");
     let network_stats = network_analyzer.generate_network_stats().await?;
     println!("📡 网络延迟: {:.1}ms", network_stats.latency_ms);
+    if network_stats.throughput_bps > 0.0 {
+        println!("📶 吞吐量: {:.1} KB/s", network_stats.throughput_bps / 1024.0);
+    }
     println!("📊 拥堵等级: {:?}", network_stats.congestion_level);
+    for reason in &network_stats.congestion_reasons {
+        println!("   - {}", reason);
+    }
+    if let Err(e) = state_manager
+        .record_network_sample(&NetworkSample::from_stats(&tool_config.cluster.to_string(), &network_stats))
+    {
+        tracing::warn!("保存网络样本失败: {}", e);
+    }
+
+    // 估算费用，交易数按实际写入交易的结构计算
+    let use_nonce = matches.is_present("use_nonce") || matches.is_present("nonce_account") || tool_config.resume.durable_nonce;
+    let write_layout = WriteTransactionLayout {
+        durable_nonce: use_nonce,
+        separate_authority: keys.buffer_authority != keys.fee_payer,
+        ..WriteTransactionLayout::default()
+    };
+    fee_optimizer.set_write_layout(write_layout.clone());
+
+    // 获取推荐配置
+    let config = network_analyzer.recommend_deployment_strategy(&network_stats, &tool_config.resume);
+    let chunk_plan = ChunkPlan::new(loader_version.clone(), write_layout);
+    println!(
+        "⚙️  推荐配置: 块大小 {}B (单笔交易上限 {}B，固定开销 {}B), 并发数 {}",
+        chunk_plan.chunk_size(config.chunk_size),
+        chunk_plan.max_payload,
+        chunk_plan.overhead,
+        config.parallel_uploads
+    );
+
+    // 升级已部署的程序：按块比较链上数据，报告无需重新写入的部分
+    let delta_mode = matches.is_present("delta");
+    let upgrade = match matches.value_of("upgrade") {
+        Some(program_id) => {
+            let program_id = Pubkey::from_str(program_id)?;
+            println!("⬆️  升级程序: {}", program_id);
+            // v4 升级直接写入程序账户，写入与部署都由程序授权签名
+            if loader_version == LoaderVersion::V4 && keys.buffer_authority != keys.upgrade_authority {
+                return Err("Loader v4 升级时 --buffer-authority 必须与 --upgrade-authority 相同".into());
+            }
+            let current = resume_engine.fetch_upgradeable_program(&loader_version, &program_id, &keys.upgrade_authority).await?;
+            let delta = ProgramDelta::compute(&current, &program_data, chunk_plan.chunk_size(config.chunk_size));
+            print_delta(&delta, &loader_version, delta_mode);
+            Some(ProgramUpgrade {
+                program_id,
+                delta: delta_mode,
+                previous_size: current.len() as u64,
+                unchanged_bytes: delta.unchanged_bytes,
+            })
+        }
+        None => None,
+    };
+    let bytes_to_write = match &upgrade {
+        Some(upgrade) if upgrade.delta && loader_version == LoaderVersion::V4 => {
+            program_data.len() as u64 - upgrade.unchanged_bytes
+        }
+        _ => program_data.len() as u64,
+    };
 
-    // 估算费用
     let cost_stats = fee_optimizer.estimate_total_deployment_cost(
-        program_data.len() as u64,
+        bytes_to_write,
         &loader_version,
         &network_stats,
     ).await?;
     println!("💰 估算费用: {} lamports", cost_stats.estimated_remaining_fees);
+    if let Some(max_fee) = tool_config.resume.max_total_fee_lamports {
+        if cost_stats.estimated_remaining_fees > max_fee {
+            return Err(format!(
+                "估算费用 {} lamports 超过配置的上限 {} lamports",
+                cost_stats.estimated_remaining_fees, max_fee
+            )
+            .into());
+        }
+    }
 
     // 创建部署状态
-    let deployment_id = state_manager.create_deployment(program_file.to_string(), loader_version)?;
+    let deployment_id = state_manager.create_deployment(program_file.to_string(), loader_version.clone())?;
     println!("🆔 部署ID: {}", deployment_id);
+    let lease = state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS)?;
+    let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
 
-    // 获取推荐配置
-    let config = network_analyzer.recommend_deployment_strategy(&network_stats);
-    println!("⚙️  推荐配置: 块大小 {}B, 并发数 {}", config.chunk_size, config.parallel_uploads);
-
-    // 开始部署（这里是简化版本）
+    // 开始部署
     println!("📤 开始上传程序数据...");
     
     // 更新部署状态
     let mut deployment = state_manager.get_deployment(&deployment_id).unwrap().clone();
     deployment.total_size = program_data.len() as u64;
+    deployment.program_fingerprint = Some(ProgramFingerprint::compute(&program_data));
     deployment.status = DeploymentStatus::Uploading;
     deployment.network_stats = network_stats;
-    deployment.cost_stats = cost_stats;
+    deployment.cost_stats.estimated_remaining_fees = cost_stats.estimated_remaining_fees;
+    if let Some(ref upgrade) = upgrade {
+        deployment.program_id = Some(upgrade.program_id);
+    }
+    deployment.upgrade = upgrade;
+    deployment.keys = Some(keys);
+    
+    // 持久nonce模式：写入交易不受区块哈希有效期限制
+    if use_nonce {
+        let existing = matches.value_of("nonce_account").map(Pubkey::from_str).transpose()?;
+        if let Err(e) = resume_engine.prepare_nonce_account(&mut deployment, existing, signers.fee_payer()).await {
+            state_manager.update_deployment(deployment)?;
+            state_manager.add_error(&deployment_id, e.to_string())?;
+            state_manager.release_lease(&lease)?;
+            return Err(e.into());
+        }
+        if let Some(nonce_account) = deployment.nonce_account {
+            println!("🔐 持久nonce账户: {}", nonce_account);
+        }
+    }
     
     state_manager.update_deployment(deployment)?;
+    state_manager.log_event(&deployment_id, EventType::Started, "开始部署".to_string(), None)?;
 
-    // 模拟上传过程
-    for i in 0..5 {
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-        let progress = ((i + 1) * 20) as u64;
-        state_manager.update_upload_progress(&deployment_id, (program_data.len() as u64 * progress) / 100)?;
-        println!("📈 上传进度: {}%", progress);
-    }
+    let (deployment, lease) = upload_program(
+        &mut state_manager,
+        &resume_engine,
+        &deployment_id,
+        &program_data,
+        &signers,
+        &config,
+        lease,
+    )
+    .await?;
 
     println!("✅ 部署完成！");
-    state_manager.update_deployment_status(&deployment_id, DeploymentStatus::Completed)?;
+    if let Some(program_id) = deployment.program_id {
+        println!("🆔 程序ID: {}", program_id);
+        println!(
+            "🔗 浏览器: {}",
+            tool_config.cluster.explorer_address_url(&program_id.to_string(), &tool_config.rpc_url)
+        );
+    }
+    state_manager.log_event(&deployment_id, EventType::Completed, "部署完成".to_string(), None)?;
+    state_manager.release_lease(&lease)?;
+
+    Ok(())
+}
+
+/// 报告新程序与链上程序的块级差异
+fn print_delta(delta: &ProgramDelta, loader_version: &LoaderVersion, delta_mode: bool) {
+    println!(
+        "📉 与链上程序相比: {}/{} 个块未变化，{} bytes ({:.1}%) 的上传是冗余的",
+        delta.unchanged.len() - delta.changed_chunks(),
+        delta.unchanged.len(),
+        delta.unchanged_bytes,
+        delta.redundant_ratio() * 100.0
+    );
+    match (loader_version, delta_mode) {
+        (LoaderVersion::V4, true) => println!("✂️  增量模式: 只写入 {} 个变化的块", delta.changed_chunks()),
+        (LoaderVersion::V3, true) => println!("ℹ️  Loader v3 无法把ProgramData中的数据复制到新buffer，仍需完整上传"),
+        (_, false) if delta.unchanged_bytes > 0 => println!("💡 Loader v4 可使用 --delta 跳过未变化的块"),
+        _ => {}
+    }
+}
 
+/// 创建上传账户、上传尚未确认的数据块并完成部署；失败时把部署标记为失败以便之后续传
+pub(crate) async fn upload_program(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    deployment_id: &Uuid,
+    program_data: &[u8],
+    signers: &DeploymentSigners,
+    config: &ResumeConfig,
+    lease: DeploymentLease,
+) -> Result<(DeploymentState, DeploymentLease), Box<dyn std::error::Error>> {
+    let mut session = UploadSession::new(state_manager, lease);
+    match upload_and_finalize(&mut session, resume_engine, deployment_id, program_data, signers, config).await {
+        Ok(deployment) => Ok((deployment, session.lease)),
+        Err(e) => {
+            eprintln!("❌ 上传失败: {}", e);
+            session.state_manager.add_error(deployment_id, e.to_string())?;
+            session.state_manager.log_event(deployment_id, EventType::Failed, e.to_string(), None)?;
+            session.state_manager.release_lease(&session.lease)?;
+            println!("💡 可使用 resume --deployment-id {} 续传", deployment_id);
+            Err(e.into())
+        }
+    }
+}
+
+async fn upload_and_finalize(
+    session: &mut UploadSession<'_>,
+    resume_engine: &ResumeEngine,
+    deployment_id: &Uuid,
+    program_data: &[u8],
+    signers: &DeploymentSigners,
+    config: &ResumeConfig,
+) -> anyhow::Result<DeploymentState> {
+    let mut deployment = session
+        .state_manager
+        .get_deployment(deployment_id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("部署不存在: {}", deployment_id))?;
+
+    let program_hash = deployment
+        .program_fingerprint
+        .clone()
+        .unwrap_or_else(|| ProgramFingerprint::compute(program_data))
+        .sha256;
+    let needs_target = deployment
+        .buffer_accounts
+        .last()
+        .filter(|buffer| !buffer.status.is_abandoned())
+        .is_none();
+    // 早期版本创建的部署没有记录各角色公钥，由本次提供的密钥补上
+    if deployment.keys.is_none() {
+        deployment.keys = Some(signers.keys());
+        session.state_manager.update_deployment(deployment.clone())?;
+    }
+    // 剩余步骤需要的密钥必须与记录的公钥一致，buffer 授权转移后只有新授权能继续写入
+    let missing = signers.missing(&deployment);
+    if !missing.is_empty() {
+        let details: Vec<String> = missing
+            .iter()
+            .map(|(role, pubkey)| format!("{} {} ({})", role, pubkey, role.flag()))
+            .collect();
+        return Err(anyhow::anyhow!("缺少剩余步骤需要的密钥: {}", details.join(", ")));
+    }
+    if needs_target {
+        reuse_registered_buffer(session.state_manager, resume_engine, &mut deployment, &program_hash, program_data, signers).await?;
+    }
+    // Loader v4 增量升级直接写入撤回后的程序账户，须在撤回和调整大小之前读取链上数据
+    let delta = match &deployment.upgrade {
+        Some(upgrade) if needs_target && upgrade.delta && deployment.loader_version == LoaderVersion::V4 => {
+            let current = resume_engine.fetch_upgradeable_program(
+                &deployment.loader_version,
+                &upgrade.program_id,
+                &signers.upgrade_authority().pubkey(),
+            )
+            .await?;
+            let chunk_size = ResumeEngine::chunk_plan(&deployment).chunk_size(config.chunk_size);
+            Some(ProgramDelta::compute(&current, program_data, chunk_size))
+        }
+        _ => None,
+    };
+
+    if resume_engine.prepare_deployment(&mut deployment, program_data.len(), signers).await? {
+        let buffer = deployment.buffer_accounts.last().map(|buffer| buffer.pubkey);
+        println!("🧱 已创建上传账户: {}", buffer.map(|pubkey| pubkey.to_string()).unwrap_or_default());
+        session.state_manager.reset_chunk_ledger(deployment_id)?;
+        session.state_manager.update_deployment(deployment.clone())?;
+        session.state_manager.log_event(
+            deployment_id,
+            EventType::Info,
+            "创建上传账户".to_string(),
+            Some(serde_json::json!({ "account": buffer.map(|pubkey| pubkey.to_string()) })),
+        )?;
+
+        if let Some(delta) = delta {
+            let ledger = ResumeEngine::delta_ledger(&deployment, program_data, &delta);
+            session.state_manager.rewrite_chunk_ledger(deployment_id, &ledger)?;
+            deployment.uploaded_bytes = delta.unchanged_bytes;
+            session.state_manager.update_deployment(deployment.clone())?;
+            println!(
+                "📉 增量升级: {}/{} 个块与链上程序相同，只需写入 {} 个块",
+                delta.unchanged.len() - delta.changed_chunks(),
+                delta.unchanged.len(),
+                delta.changed_chunks()
+            );
+            session.state_manager.log_event(
+                deployment_id,
+                EventType::Info,
+                "增量升级只写入变化的块".to_string(),
+                Some(serde_json::json!({
+                    "changed_chunks": delta.changed_chunks(),
+                    "total_chunks": delta.unchanged.len(),
+                    "unchanged_bytes": delta.unchanged_bytes,
+                })),
+            )?;
+        }
+    }
+
+    let written = deployment
+        .buffer_accounts
+        .last()
+        .is_some_and(|buffer| buffer.status == BufferStatus::Completed);
+    if written {
+        println!("⏭️  上传账户已写满，跳过上传");
+    } else {
+        resume_engine
+            .resume_deployment(&deployment, program_data, signers, config, session)
+            .await?;
+    }
+
+    // 上传过程中进度和费用已经写回，重新读取最新状态
+    let mut deployment = session
+        .state_manager
+        .get_deployment(deployment_id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("部署不存在: {}", deployment_id))?;
+    if !written {
+        mark_buffer_written(session.state_manager, &mut deployment, &program_hash)?;
+    }
+
+    println!("🏁 部署程序...");
+    let signature = resume_engine.finalize_deployment(&mut deployment, signers).await?;
+    deployment.status = DeploymentStatus::Completed;
+    session.state_manager.update_deployment(deployment.clone())?;
+    // v3 部署会关闭buffer，登记记录随之失效
+    if let (LoaderVersion::V3, Some(buffer)) = (&deployment.loader_version, deployment.buffer_accounts.last()) {
+        session.state_manager.remove_reusable_buffer(&program_hash, &buffer.pubkey)?;
+    }
+    session.state_manager.log_event(
+        deployment_id,
+        EventType::Info,
+        "程序已部署".to_string(),
+        Some(serde_json::json!({
+            "program_id": deployment.program_id.map(|pubkey| pubkey.to_string()),
+            "signature": signature.to_string(),
+        })),
+    )?;
+    Ok(deployment)
+}
+
+/// 数据全部确认后把上传账户标记为已写满；v3 buffer 同时登记到复用表，
+/// 之后相同程序的部署可以直接使用它
+fn mark_buffer_written(
+    state_manager: &mut StateManager,
+    deployment: &mut DeploymentState,
+    program_hash: &str,
+) -> anyhow::Result<()> {
+    let Some(buffer) = deployment.buffer_accounts.last_mut() else {
+        return Ok(());
+    };
+    buffer.uploaded_size = buffer.size;
+    buffer.status = BufferStatus::Completed;
+    let buffer = buffer.clone();
+    state_manager.update_deployment(deployment.clone())?;
+
+    if deployment.loader_version == LoaderVersion::V3 {
+        state_manager.register_reusable_buffer(&ReusableBuffer {
+            program_hash: program_hash.to_string(),
+            loader_version: LoaderVersion::V3,
+            deployment_id: deployment.id,
+            buffer,
+            registered_at: Utc::now(),
+        })?;
+    }
+    Ok(())
+}
+
+/// 部署还没有可用的上传账户时，查找为相同程序登记过的 v3 buffer；
+/// 链上数据与程序逐字节相同则直接接管它，跳过整个上传过程。
+/// 接管时把来源部署中的记录标记为已转交，来源部署之后续传会新建buffer，
+/// 不会与本部署争用同一个buffer；来源部署仍在运行时不接管
+async fn reuse_registered_buffer(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    deployment: &mut DeploymentState,
+    program_hash: &str,
+    program_data: &[u8],
+    signers: &DeploymentSigners,
+) -> anyhow::Result<()> {
+    if deployment.loader_version != LoaderVersion::V3 {
+        return Ok(());
+    }
+
+    for entry in state_manager.get_reusable_buffers(program_hash) {
+        if entry.loader_version != LoaderVersion::V3 || entry.deployment_id == deployment.id {
+            continue;
+        }
+        let source = state_manager.get_deployment(&entry.deployment_id).cloned();
+        let source_running = source.as_ref().is_some_and(|source| {
+            matches!(source.status, DeploymentStatus::Initializing | DeploymentStatus::Uploading)
+                || state_manager.active_lease(&source.id).is_some()
+        });
+        if source_running {
+            continue;
+        }
+        let pubkey = entry.buffer.pubkey;
+        match resume_engine
+            .verify_reusable_buffer(&pubkey, program_data, &signers.buffer_authority().pubkey())
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                // buffer 已关闭、被改写或授权已变更
+                state_manager.remove_reusable_buffer(program_hash, &pubkey)?;
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!("无法检查可复用buffer {}: {}", pubkey, e);
+                continue;
+            }
+        }
+
+        // 先从来源部署摘下这个buffer，再登记到本部署
+        state_manager.remove_reusable_buffer(program_hash, &pubkey)?;
+        if let Some(mut source) = source {
+            let mut transferred = false;
+            for buffer in source.buffer_accounts.iter_mut().filter(|buffer| buffer.pubkey == pubkey) {
+                buffer.status = BufferStatus::Transferred;
+                transferred = true;
+            }
+            if transferred {
+                state_manager.update_deployment(source)?;
+            }
+        }
+        deployment.buffer_accounts.push(BufferInfo {
+            uploaded_size: entry.buffer.size,
+            status: BufferStatus::Completed,
+            ..entry.buffer.clone()
+        });
+        deployment.uploaded_bytes = deployment.total_size;
+        state_manager.reset_chunk_ledger(&deployment.id)?;
+        state_manager.update_deployment(deployment.clone())?;
+        state_manager.register_reusable_buffer(&ReusableBuffer {
+            deployment_id: deployment.id,
+            registered_at: Utc::now(),
+            ..entry.clone()
+        })?;
+
+        let data = serde_json::json!({
+            "buffer": pubkey.to_string(),
+            "source_deployment": entry.deployment_id.to_string(),
+            "reused_by": deployment.id.to_string(),
+        });
+        state_manager.log_event(&deployment.id, EventType::Info, "复用已写满的buffer".to_string(), Some(data.clone()))?;
+        state_manager.log_event(
+            &entry.deployment_id,
+            EventType::Info,
+            "buffer已转交给其他部署，续传时将新建buffer".to_string(),
+            Some(data),
+        )?;
+        println!("♻️  复用部署 {} 写满的buffer: {}", entry.deployment_id, pubkey);
+        return Ok(());
+    }
     Ok(())
-} 
\ No newline at end of file
+}
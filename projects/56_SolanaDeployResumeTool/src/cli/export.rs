@@ -0,0 +1,25 @@
+use crate::core::StateManager;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub async fn handle_export(
+    matches: &clap::ArgMatches<'_>,
+    state_manager: &StateManager,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deployment_id = Uuid::from_str(matches.value_of("deployment_id").unwrap())?;
+    let output = matches
+        .value_of("output")
+        .map(|path| path.to_string())
+        .unwrap_or_else(|| format!("{}.bundle.json", deployment_id));
+
+    let bundle = state_manager.export_bundle(&deployment_id)?;
+    std::fs::write(&output, serde_json::to_vec_pretty(&bundle)?)?;
+
+    println!("📦 已导出部署 {}", deployment_id);
+    println!("  数据块记录: {}", bundle.chunk_ledger.len());
+    println!("  事件记录: {}", bundle.events.len());
+    println!("  Buffer账户: {}", bundle.deployment.buffer_accounts.len());
+    println!("💾 导出文件: {}", output);
+
+    Ok(())
+}
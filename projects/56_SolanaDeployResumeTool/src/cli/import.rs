@@ -0,0 +1,65 @@
+use crate::core::{schema::MigrationRegistry, types::*, ResumeEngine, StateManager};
+
+pub async fn handle_import(
+    matches: &clap::ArgMatches<'_>,
+    mut state_manager: StateManager,
+    resume_engine: ResumeEngine,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = matches.value_of("file").unwrap();
+    let overwrite = matches.is_present("force");
+
+    let bytes = std::fs::read(file)?;
+    let mut bundle = MigrationRegistry::default().decode_bundle(&bytes)?;
+    println!("📦 导入部署 {}", bundle.deployment.id);
+
+    // 可以在新机器上指定程序文件路径，并用指纹确认是同一个程序
+    if let Some(program_file) = matches.value_of("program_file") {
+        let data = std::fs::read(program_file)?;
+        let fingerprint = ProgramFingerprint::compute(&data);
+        if let Some(ref expected) = bundle.program_fingerprint {
+            if expected != &fingerprint {
+                return Err(format!(
+                    "程序文件与导出记录不一致: 期望 sha256 {}，实际 {}",
+                    expected.sha256, fingerprint.sha256
+                )
+                .into());
+            }
+        }
+        bundle.deployment.program_path = program_file.to_string();
+        bundle.program_fingerprint = Some(fingerprint);
+    } else if !std::path::Path::new(&bundle.deployment.program_path).exists() {
+        println!(
+            "⚠️  程序文件 {} 在本机不存在，可使用 --program-file 指定",
+            bundle.deployment.program_path
+        );
+    }
+
+    // 检查引用的buffer账户是否仍然存在
+    println!("🔍 检查 {} 个Buffer账户...", bundle.deployment.buffer_accounts.len());
+    let missing = resume_engine
+        .find_missing_buffers(&bundle.deployment)
+        .await
+        .map_err(|e| format!("无法确认Buffer账户状态，已取消导入: {}", e))?;
+    for buffer in bundle.deployment.buffer_accounts.iter_mut() {
+        if missing.contains(&buffer.pubkey) {
+            println!("❌ Buffer不存在: {}", buffer.pubkey);
+            buffer.status = BufferStatus::Failed;
+        }
+    }
+
+    let deployment_id = state_manager.import_bundle(bundle, overwrite)?;
+    state_manager.log_event(
+        &deployment_id,
+        EventType::Info,
+        format!("从 {} 导入", file),
+        Some(serde_json::json!({ "missing_buffers": missing.len() })),
+    )?;
+
+    if missing.is_empty() {
+        println!("✅ 导入完成，可使用 resume --deployment-id {} 续传", deployment_id);
+    } else {
+        println!("⚠️  导入完成，但有 {} 个Buffer已不存在，对应部分需要重新上传", missing.len());
+    }
+
+    Ok(())
+}
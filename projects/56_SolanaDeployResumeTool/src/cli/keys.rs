@@ -0,0 +1,52 @@
+use crate::core::{
+    config::{expand_home, ToolConfig},
+    signers::{DeploymentSigners, KeyRole},
+};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+
+/// 按命令行参数读取各角色的密钥对，未指定 `--fee-payer` 时使用工具配置的密钥对。
+/// `required` 为续传剩余步骤需要的角色：只读取其中与付款账户不同的角色，
+/// 缺少对应参数时直接报错；为 `None` 时读取所有提供了的参数
+pub fn read_signers(
+    matches: &clap::ArgMatches<'_>,
+    tool_config: &ToolConfig,
+    required: Option<&[(KeyRole, Pubkey)]>,
+) -> Result<DeploymentSigners, Box<dyn std::error::Error>> {
+    let fee_payer = match matches.value_of(KeyRole::FeePayer.arg_name()) {
+        Some(path) => read_role_keypair(KeyRole::FeePayer, path)?,
+        None => read_keypair_file(tool_config.expanded_keypair_path())
+            .map_err(|e| format!("无法读取密钥对文件: {}", e))?,
+    };
+    let payer_pubkey = fee_payer.pubkey();
+    let mut signers = DeploymentSigners::new(fee_payer);
+
+    for role in [KeyRole::BufferAuthority, KeyRole::UpgradeAuthority, KeyRole::Program] {
+        let path = matches.value_of(role.arg_name());
+        let keypair = match required {
+            None => path.map(|path| read_role_keypair(role, path)).transpose()?,
+            Some(required) => {
+                let Some(&(_, pubkey)) = required.iter().find(|(required_role, _)| *required_role == role) else {
+                    continue;
+                };
+                if pubkey == payer_pubkey && path.is_none() {
+                    continue;
+                }
+                let path = path.ok_or_else(|| format!("需要{} {}，请通过 {} 提供", role, pubkey, role.flag()))?;
+                Some(read_role_keypair(role, path)?)
+            }
+        };
+        if let Some(keypair) = keypair {
+            signers = match role {
+                KeyRole::BufferAuthority => signers.with_buffer_authority(keypair),
+                KeyRole::UpgradeAuthority => signers.with_upgrade_authority(keypair),
+                _ => signers.with_program(keypair),
+            };
+        }
+    }
+    Ok(signers)
+}
+
+fn read_role_keypair(role: KeyRole, path: &str) -> Result<Keypair, Box<dyn std::error::Error>> {
+    read_keypair_file(expand_home(path)).map_err(|e| format!("无法读取{}密钥对文件 {}: {}", role, path, e).into())
+}
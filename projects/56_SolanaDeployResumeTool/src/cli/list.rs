@@ -35,5 +35,13 @@ pub async fn handle_list(
         }
     }
 
+    let quarantined = state_manager.quarantined_records();
+    if !quarantined.is_empty() {
+        println!("⚠️  有 {} 条无法解析的部署记录已被隔离:", quarantined.len());
+        for record in quarantined {
+            println!("  - {} ({}): {}", record.key, record.quarantined_at.format("%Y-%m-%d %H:%M:%S"), record.reason);
+        }
+    }
+
     Ok(())
 } 
\ No newline at end of file
@@ -4,4 +4,13 @@ pub mod status;
 pub mod list;
 pub mod cleanup;
 pub mod server;
-pub mod analyze; 
\ No newline at end of file
+pub mod analyze;
+pub mod advise;
+pub mod authority;
+pub mod keys;
+pub mod export;
+pub mod import;
+pub mod config;
+pub mod confirm;
+pub mod schedule;
+pub mod watch;
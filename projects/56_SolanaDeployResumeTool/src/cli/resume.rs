@@ -1,4 +1,6 @@
-use crate::core::{types::*, StateManager, ResumeEngine, NetworkAnalyzer};
+use crate::cli::deploy::upload_program;
+use crate::cli::keys::read_signers;
+use crate::core::{config::ToolConfig, signers::required_keys, state::DEFAULT_LEASE_TTL_SECS, types::*, StateManager, ResumeEngine, NetworkAnalyzer};
 use std::str::FromStr;
 use uuid::Uuid;
 
@@ -7,13 +9,17 @@ pub async fn handle_resume(
     mut state_manager: StateManager,
     resume_engine: ResumeEngine,
     network_analyzer: &mut NetworkAnalyzer,
-    _keypair_path: &str,
+    tool_config: &ToolConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let deployment_id_str = matches.value_of("deployment_id").unwrap();
     let deployment_id = Uuid::from_str(deployment_id_str)?;
 
     println!("🔄 续传部署: {}", deployment_id);
 
+    // 获取租约，防止多个进程同时续传同一个部署
+    let lease = state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS)?;
+    let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
+
     // 获取部署状态
     let deployment = state_manager.get_deployment(&deployment_id)
         .ok_or("部署不存在")?;
@@ -25,6 +31,9 @@ pub async fn handle_resume(
     println!("📊 部署信息:");
     println!("  程序文件: {}", deployment.program_path);
     println!("  总大小: {} bytes", deployment.total_size);
+    if let Some(nonce_account) = deployment.nonce_account {
+        println!("  持久nonce账户: {} (从当前nonce值继续)", nonce_account);
+    }
     println!("  已上传: {} bytes ({:.1}%)", 
         deployment.uploaded_bytes,
         (deployment.uploaded_bytes as f64 / deployment.total_size as f64) * 100.0
@@ -33,20 +42,74 @@ pub async fn handle_resume(
     // 分析网络状况
     let network_stats = network_analyzer.generate_network_stats().await?;
     println!("📡 当前网络状况: {:?}", network_stats.congestion_level);
+    for reason in &network_stats.congestion_reasons {
+        println!("   - {}", reason);
+    }
+    if let Err(e) = state_manager
+        .record_network_sample(&NetworkSample::from_stats(&tool_config.cluster.to_string(), &network_stats))
+    {
+        tracing::warn!("保存网络样本失败: {}", e);
+    }
 
-    // 计算续传点
-    let resume_point = resume_engine.calculate_resume_point(deployment)?;
+    // 读取程序文件并确认与部署记录一致
+    let program_data = std::fs::read(&deployment.program_path)
+        .map_err(|e| format!("无法读取程序文件 {}: {}", deployment.program_path, e))?;
+    if let Some(ref expected) = deployment.program_fingerprint {
+        let fingerprint = ProgramFingerprint::compute(&program_data);
+        if expected != &fingerprint {
+            return Err(format!(
+                "程序文件已变化: 期望 sha256 {}，实际 {}",
+                expected.sha256, fingerprint.sha256
+            )
+            .into());
+        }
+    }
+
+    // 只读取剩余步骤需要签名的密钥，已完成步骤用过的密钥不再要求提供
+    let required = deployment.keys.as_ref().map(|_| required_keys(deployment));
+    if let Some(ref required) = required {
+        println!("🔑 剩余步骤需要的密钥:");
+        for (role, pubkey) in required {
+            println!("  {}: {}", role, pubkey);
+        }
+    }
+    let signers = read_signers(matches, tool_config, required.as_deref())?;
+
+    // 计算续传点（账本中已确认的数据）
+    let resume_point: u64 = state_manager
+        .get_chunk_ledger(&deployment_id)
+        .iter()
+        .filter(|chunk| chunk.status == ChunkStatus::Confirmed)
+        .map(|chunk| chunk.size as u64)
+        .sum();
     println!("🎯 续传点: {} bytes", resume_point);
 
+    let config = network_analyzer.recommend_deployment_strategy(&network_stats, &tool_config.resume);
+
     // 开始续传
     println!("🚀 开始续传上传...");
     state_manager.update_deployment_status(&deployment_id, DeploymentStatus::Uploading)?;
+    state_manager.log_event(
+        &deployment_id,
+        EventType::Resumed,
+        format!("从 {} bytes 处续传", resume_point),
+        None,
+    )?;
 
-    // 模拟续传过程
-    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+    let (_, lease) = upload_program(
+        &mut state_manager,
+        &resume_engine,
+        &deployment_id,
+        &program_data,
+        &signers,
+        &config,
+        lease,
+    )
+    .await?;
     
     println!("✅ 续传完成！");
-    state_manager.update_deployment_status(&deployment_id, DeploymentStatus::Completed)?;
+    state_manager.log_event(&deployment_id, EventType::Completed, "续传完成".to_string(), None)?;
+    state_manager.release_lease(&lease)?;
 
     Ok(())
-} 
\ No newline at end of file
+}
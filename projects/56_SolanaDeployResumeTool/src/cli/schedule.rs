@@ -0,0 +1,74 @@
+use crate::core::{config::ToolConfig, types::*, StateManager};
+use chrono::{DateTime, Utc};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+pub async fn handle_schedule(
+    matches: &clap::ArgMatches<'_>,
+    mut state_manager: StateManager,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let program_file = matches.value_of("program_file").unwrap();
+    let loader_version = match matches.value_of("loader_version").unwrap() {
+        "v3" => LoaderVersion::V3,
+        _ => LoaderVersion::V4,
+    };
+
+    let not_before = matches
+        .value_of("not_before")
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|e| format!("无效的开始时间 {} (需要RFC3339格式，如 2024-01-01T02:00:00Z): {}", value, e))
+        })
+        .transpose()?;
+    let max_congestion = match matches.value_of("max_congestion").unwrap() {
+        "low" => CongestionLevel::Low,
+        "high" => CongestionLevel::High,
+        "critical" => CongestionLevel::Critical,
+        _ => CongestionLevel::Medium,
+    };
+    let max_priority_fee_p50 = matches.value_of("max_fee_p50").map(str::parse::<u64>).transpose()?;
+    let nonce_account = matches.value_of("nonce_account").map(Pubkey::from_str).transpose()?;
+
+    // 检测程序文件，执行时会再次校验指纹
+    let program_data = std::fs::read(program_file)
+        .map_err(|e| format!("无法读取程序文件 {}: {}", program_file, e))?;
+    let fingerprint = ProgramFingerprint::compute(&program_data);
+
+    let schedule = DeploymentSchedule {
+        not_before,
+        max_congestion,
+        max_priority_fee_p50,
+        use_nonce: matches.is_present("use_nonce") || tool_config.resume.durable_nonce,
+        nonce_account,
+        attempts: 0,
+        last_check: None,
+    };
+    let deployment_id = state_manager.schedule_deployment(
+        program_file.to_string(),
+        loader_version,
+        fingerprint,
+        schedule.clone(),
+    )?;
+    state_manager.log_event(
+        &deployment_id,
+        EventType::Info,
+        "已排队计划部署".to_string(),
+        Some(serde_json::to_value(&schedule)?),
+    )?;
+
+    println!("🗓️  已排队计划部署: {}", deployment_id);
+    println!("📄 程序文件: {} ({} bytes)", program_file, program_data.len());
+    match schedule.not_before {
+        Some(time) => println!("⏰ 最早开始: {}", time.to_rfc3339()),
+        None => println!("⏰ 最早开始: 立即"),
+    }
+    println!("📊 拥堵等级不高于: {:?}", schedule.max_congestion);
+    if let Some(fee) = schedule.max_priority_fee_p50 {
+        println!("💰 优先费 p50 低于: {} micro-lamports/CU", fee);
+    }
+    println!("💡 运行 watch 或 server --watch 后，条件满足时自动开始部署");
+
+    Ok(())
+}
@@ -1,8 +1,8 @@
-use crate::api;
+use crate::api::{self, routes::ApiContext};
 
 pub async fn handle_server(
     matches: &clap::ArgMatches<'_>,
-    rpc_url: String,
+    context: ApiContext,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let port_str = matches.value_of("port").unwrap();
     let port: u16 = port_str.parse()?;
@@ -11,7 +11,7 @@ pub async fn handle_server(
     println!("🔗 服务地址: http://localhost:{}", port);
     
     // 这里会调用API服务器模块
-    api::server::start_server(port, rpc_url).await?;
+    api::server::start_server(port, context).await?;
 
     Ok(())
 } 
\ No newline at end of file
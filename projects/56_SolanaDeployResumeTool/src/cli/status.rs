@@ -1,16 +1,23 @@
-use crate::core::{types::*, StateManager};
+use crate::core::{config::ToolConfig, types::*, StateManager};
 use std::str::FromStr;
 use uuid::Uuid;
 
 pub async fn handle_status(
     matches: &clap::ArgMatches<'_>,
     state_manager: &StateManager,
+    tool_config: &ToolConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if let Some(deployment_id_str) = matches.value_of("deployment_id") {
         let deployment_id = Uuid::from_str(deployment_id_str)?;
         
         if let Some(deployment) = state_manager.get_deployment(&deployment_id) {
             print_deployment_status(deployment);
+            if let Some(program_id) = deployment.program_id {
+                println!("🔗 浏览器: {}", tool_config.cluster.explorer_address_url(&program_id.to_string(), &tool_config.rpc_url));
+            }
+            if let Some(lease) = state_manager.active_lease(&deployment_id) {
+                println!("🔒 租约持有者: {} (到期 {})", lease.owner, lease.expires_at.format("%Y-%m-%d %H:%M:%S"));
+            }
         } else {
             println!("❌ 部署不存在: {}", deployment_id);
         }
@@ -42,6 +49,38 @@ fn print_deployment_status(deployment: &DeploymentState) {
             (deployment.uploaded_bytes as f64 / deployment.total_size as f64) * 100.0
         } else { 0.0 }
     );
+    if let Some(ref upgrade) = deployment.upgrade {
+        println!(
+            "⬆️  升级程序: {} (原大小 {} bytes，未变化 {} bytes{})",
+            upgrade.program_id,
+            upgrade.previous_size,
+            upgrade.unchanged_bytes,
+            if upgrade.delta { "，增量模式" } else { "" }
+        );
+    }
+    if let Some(ref keys) = deployment.keys {
+        println!("🔑 付款账户: {}", keys.fee_payer);
+        println!("🔑 buffer授权: {}", keys.buffer_authority);
+        println!("🔑 升级授权: {}", keys.upgrade_authority);
+        if let Some(program) = keys.program {
+            println!("🔑 程序地址: {}", program);
+        }
+    }
+    if let Some(nonce_account) = deployment.nonce_account {
+        println!("🔐 持久nonce账户: {}", nonce_account);
+    }
+    if let Some(ref schedule) = deployment.schedule {
+        println!("🗓️  计划条件: 拥堵不高于 {:?}, 已执行 {} 次", schedule.max_congestion, schedule.attempts);
+        if let Some(not_before) = schedule.not_before {
+            println!("   最早开始: {}", not_before.format("%Y-%m-%d %H:%M:%S"));
+        }
+        if let Some(fee) = schedule.max_priority_fee_p50 {
+            println!("   优先费 p50 低于: {} micro-lamports/CU", fee);
+        }
+        if let Some(ref waiting) = schedule.last_check {
+            println!("   等待原因: {}", waiting);
+        }
+    }
     println!("⏰ 创建时间: {}", deployment.created_at.format("%Y-%m-%d %H:%M:%S"));
     println!("🔄 更新时间: {}", deployment.updated_at.format("%Y-%m-%d %H:%M:%S"));
     if deployment.failure_count > 0 {
@@ -54,4 +93,17 @@ fn print_deployment_status(deployment: &DeploymentState) {
         deployment.cost_stats.total_fees_paid,
         deployment.cost_stats.estimated_remaining_fees
     );
+    let cost_stats = &deployment.cost_stats;
+    if cost_stats.transaction_count > 0 {
+        println!("🧾 链上明细: 基础费 {} + 优先费 {} lamports ({}/{} 笔取自交易元数据)",
+            cost_stats.base_fees_paid,
+            cost_stats.priority_fees_paid,
+            cost_stats.audited_transactions,
+            cost_stats.transaction_count
+        );
+        println!("🏦 租金: 存入 {} lamports, 退还 {} lamports", cost_stats.rent_deposited, cost_stats.rent_refunded);
+    }
+    if cost_stats.saved_fees > 0 {
+        println!("💡 续传节省: {} lamports (已确认块当初实际支付的费用)", cost_stats.saved_fees);
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,552 @@
+use crate::cli::deploy::upload_program;
+use crate::core::{
+    config::ToolConfig,
+    planner::WriteTransactionLayout,
+    retry::NetworkHealth,
+    signers::DeploymentSigners,
+    state::DEFAULT_LEASE_TTL_SECS,
+    types::*,
+    StateManager, ResumeEngine, NetworkAnalyzer, FeeOptimizer, HealthChecker, RetryHandler,
+};
+use chrono::Utc;
+use solana_sdk::signature::read_keypair_file;
+use std::time::Duration;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+pub async fn handle_watch(
+    matches: &clap::ArgMatches<'_>,
+    state_manager: StateManager,
+    resume_engine: ResumeEngine,
+    network_analyzer: &mut NetworkAnalyzer,
+    fee_optimizer: &mut FeeOptimizer,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interval = watch_interval(matches)?;
+    run_watch(state_manager, &resume_engine, network_analyzer, fee_optimizer, tool_config, interval).await
+}
+
+/// 读取 `--interval` 参数
+pub fn watch_interval(matches: &clap::ArgMatches<'_>) -> Result<Duration, Box<dyn std::error::Error>> {
+    let seconds: u64 = matches.value_of("interval").unwrap_or("60").parse()?;
+    Ok(Duration::from_secs(seconds.max(1)))
+}
+
+/// 自动续传失败部署的初始退避时间
+const AUTO_RESUME_BASE_DELAY: Duration = Duration::from_secs(60);
+
+/// 自动续传失败部署的最大退避时间
+const AUTO_RESUME_MAX_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// 守护循环：定期检查计划部署的触发条件，满足时自动开始或续传；
+/// 开启 auto_resume 时同时在网络健康的前提下自动续传失败的部署
+pub async fn run_watch(
+    mut state_manager: StateManager,
+    resume_engine: &ResumeEngine,
+    network_analyzer: &mut NetworkAnalyzer,
+    fee_optimizer: &mut FeeOptimizer,
+    tool_config: &ToolConfig,
+    interval: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("👀 守护进程已启动，每 {}s 检查一次计划部署", interval.as_secs());
+    if tool_config.resume.auto_resume {
+        println!(
+            "🔁 auto_resume 已开启，失败的部署最多自动续传 {} 次",
+            tool_config.resume.max_auto_resume_attempts
+        );
+    } else {
+        println!("ℹ️  auto_resume 已关闭，失败的部署不会自动续传");
+    }
+
+    let health_checker = HealthChecker::new(tool_config.rpc_url.clone())
+        .with_check_interval(interval)
+        .with_limiter(resume_engine.rpc_pool().limiter().clone());
+    let backoff = RetryHandler::new(&tool_config.resume).with_delay_bounds(AUTO_RESUME_BASE_DELAY, AUTO_RESUME_MAX_DELAY);
+    let (mut monitor, mut latest_health) = HealthMonitor::spawn(health_checker.clone());
+
+    loop {
+        // 健康监控在独立任务中运行，它出错退出不会打断正在进行的上传，下一轮检查时重新启动
+        if monitor.is_finished() {
+            match monitor.take_result().await {
+                Some(Err(e)) => eprintln!("⚠️  RPC健康监控出错退出，重新启动: {}", e),
+                _ => eprintln!("⚠️  RPC健康监控已停止，重新启动"),
+            }
+            (monitor, latest_health) = HealthMonitor::spawn(health_checker.clone());
+        }
+
+        if let Err(e) = run_scheduled_deployments(
+            &mut state_manager,
+            resume_engine,
+            network_analyzer,
+            fee_optimizer,
+            tool_config,
+        )
+        .await
+        {
+            eprintln!("⚠️  检查计划部署失败: {}", e);
+        }
+
+        if tool_config.resume.auto_resume {
+            let health = latest_health.borrow().clone();
+            if let Err(e) = resume_failed_deployments(
+                &mut state_manager,
+                resume_engine,
+                network_analyzer,
+                tool_config,
+                health,
+                &backoff,
+            )
+            .await
+            {
+                eprintln!("⚠️  自动续传失败的部署出错: {}", e);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// 在独立任务中运行的RPC健康监控，最新状态通过 watch 通道发给守护循环；
+/// 守护循环结束时任务随之停止
+struct HealthMonitor {
+    handle: Option<tokio::task::JoinHandle<anyhow::Result<()>>>,
+}
+
+impl HealthMonitor {
+    fn spawn(health_checker: HealthChecker) -> (Self, watch::Receiver<Option<NetworkHealth>>) {
+        let (sender, receiver) = watch::channel::<Option<NetworkHealth>>(None);
+        let handle = tokio::spawn(async move {
+            health_checker
+                .monitor_health(|health| {
+                    let was_healthy = sender.borrow().as_ref().map(|previous| previous.is_healthy);
+                    if was_healthy != Some(health.is_healthy) {
+                        if health.is_healthy {
+                            println!("💚 RPC节点健康 (延迟 {}ms)", health.latency.as_millis());
+                        } else {
+                            println!(
+                                "💔 RPC节点不健康: {}",
+                                health.error_message.as_deref().unwrap_or("未知原因")
+                            );
+                        }
+                    }
+                    sender.send_replace(Some(health));
+                })
+                .await
+        });
+        (Self { handle: Some(handle) }, receiver)
+    }
+
+    fn is_finished(&self) -> bool {
+        match self.handle {
+            Some(ref handle) => handle.is_finished(),
+            None => true,
+        }
+    }
+
+    /// 取出已结束任务的结果，任务异常终止时返回其错误
+    async fn take_result(&mut self) -> Option<anyhow::Result<()>> {
+        let handle = self.handle.take()?;
+        Some(handle.await.unwrap_or_else(|e| Err(e.into())))
+    }
+}
+
+impl Drop for HealthMonitor {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+async fn run_scheduled_deployments(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    network_analyzer: &mut NetworkAnalyzer,
+    fee_optimizer: &mut FeeOptimizer,
+    tool_config: &ToolConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduled: Vec<DeploymentState> = state_manager
+        .get_scheduled_deployments()
+        .into_iter()
+        .cloned()
+        .collect();
+    if scheduled.is_empty() {
+        return Ok(());
+    }
+
+    let network_stats = network_analyzer.generate_network_stats().await?;
+    if let Err(e) = state_manager
+        .record_network_sample(&NetworkSample::from_stats(&tool_config.cluster.to_string(), &network_stats))
+    {
+        tracing::warn!("保存网络样本失败: {}", e);
+    }
+
+    let now = Utc::now();
+    for mut deployment in scheduled {
+        let Some(mut schedule) = deployment.schedule.clone() else {
+            continue;
+        };
+
+        let unmet = schedule.unmet_conditions(now, &network_stats);
+        if !unmet.is_empty() {
+            // 只在等待原因变化时输出，避免每轮刷屏
+            let summary = unmet.join("；");
+            if schedule.last_check.as_deref() != Some(summary.as_str()) {
+                println!("⏳ 计划部署 {} 等待中: {}", deployment.id, summary);
+                schedule.last_check = Some(summary);
+                deployment.schedule = Some(schedule);
+                state_manager.update_deployment(deployment)?;
+            }
+            continue;
+        }
+
+        // 租约被占用说明其他进程正在执行，本轮跳过
+        let deployment_id = deployment.id;
+        let lease = match state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS) {
+            Ok(lease) => lease,
+            Err(e) => {
+                println!("⏭️  跳过计划部署 {}: {}", deployment_id, e);
+                continue;
+            }
+        };
+        let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
+
+        println!("⏰ 计划部署 {} 条件已满足，开始执行", deployment_id);
+        match run_scheduled(
+            state_manager,
+            resume_engine,
+            network_analyzer,
+            fee_optimizer,
+            tool_config,
+            &network_stats,
+            lease,
+        )
+        .await
+        {
+            Ok(()) => println!("✅ 计划部署 {} 已完成", deployment_id),
+            Err(e) => {
+                eprintln!("❌ 计划部署 {} 失败: {}", deployment_id, e);
+                requeue_after_failure(state_manager, tool_config, &deployment_id, e.to_string())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 执行一个条件已满足的计划部署；已有上传进度时从账本续传
+async fn run_scheduled(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    network_analyzer: &NetworkAnalyzer,
+    fee_optimizer: &mut FeeOptimizer,
+    tool_config: &ToolConfig,
+    network_stats: &NetworkStats,
+    lease: DeploymentLease,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let deployment_id = lease.deployment_id;
+    let (program_data, signers) = match start_scheduled(
+        state_manager,
+        resume_engine,
+        fee_optimizer,
+        tool_config,
+        &deployment_id,
+        network_stats,
+    )
+    .await
+    {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            state_manager.release_lease(&lease)?;
+            return Err(e);
+        }
+    };
+
+    let config = network_analyzer.recommend_deployment_strategy(network_stats, &tool_config.resume);
+    let (_, lease) = upload_program(
+        state_manager,
+        resume_engine,
+        &deployment_id,
+        &program_data,
+        &signers,
+        &config,
+        lease,
+    )
+    .await?;
+
+    state_manager.log_event(&deployment_id, EventType::Completed, "计划部署完成".to_string(), None)?;
+    state_manager.release_lease(&lease)?;
+    Ok(())
+}
+
+/// 记录执行次数、检查费用上限、按需准备nonce账户，并把部署切换为上传中
+async fn start_scheduled(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    fee_optimizer: &mut FeeOptimizer,
+    tool_config: &ToolConfig,
+    deployment_id: &Uuid,
+    network_stats: &NetworkStats,
+) -> Result<(Vec<u8>, DeploymentSigners), Box<dyn std::error::Error>> {
+    let mut deployment = state_manager.get_deployment(deployment_id).cloned().ok_or("部署不存在")?;
+    let mut schedule = deployment.schedule.clone().ok_or("部署没有计划条件")?;
+
+    // 先记下执行次数，执行中途失败也计入
+    schedule.attempts += 1;
+    schedule.last_check = None;
+    deployment.schedule = Some(schedule.clone());
+    state_manager.update_deployment(deployment.clone())?;
+
+    let (program_data, signers) = load_inputs(&deployment, tool_config)?;
+    let resuming = deployment.uploaded_bytes > 0;
+    if !resuming {
+        fee_optimizer.set_write_layout(WriteTransactionLayout {
+            durable_nonce: deployment.nonce_account.is_some() || schedule.use_nonce || schedule.nonce_account.is_some(),
+            ..WriteTransactionLayout::default()
+        });
+        let cost_stats = fee_optimizer
+            .estimate_total_deployment_cost(program_data.len() as u64, &deployment.loader_version, network_stats)
+            .await?;
+        if let Some(max_fee) = tool_config.resume.max_total_fee_lamports {
+            if cost_stats.estimated_remaining_fees > max_fee {
+                return Err(format!(
+                    "估算费用 {} lamports 超过配置的上限 {} lamports",
+                    cost_stats.estimated_remaining_fees, max_fee
+                )
+                .into());
+            }
+        }
+        // 已付费用来自链上记录，只更新预估部分
+        deployment.cost_stats.estimated_remaining_fees = cost_stats.estimated_remaining_fees;
+    }
+
+    deployment.status = DeploymentStatus::Uploading;
+    deployment.network_stats = network_stats.clone();
+    if deployment.nonce_account.is_none() && (schedule.use_nonce || schedule.nonce_account.is_some()) {
+        if let Err(e) = resume_engine
+            .prepare_nonce_account(&mut deployment, schedule.nonce_account, signers.fee_payer())
+            .await
+        {
+            state_manager.update_deployment(deployment)?;
+            return Err(e.into());
+        }
+    }
+
+    state_manager.update_deployment(deployment)?;
+    state_manager.log_event(
+        deployment_id,
+        if resuming { EventType::Resumed } else { EventType::Started },
+        format!("计划条件已满足，第 {} 次执行", schedule.attempts),
+        Some(serde_json::json!({
+            "congestion_level": network_stats.congestion_level,
+            "priority_fee_p50": network_stats.congestion_signals.priority_fee_p50,
+        })),
+    )?;
+    Ok((program_data, signers))
+}
+
+/// 续传 `find_resumable_deployments` 中失败的部署：网络不健康时暂缓，
+/// 每个部署按指数退避重试，达到 `max_auto_resume_attempts` 次后停止
+async fn resume_failed_deployments(
+    state_manager: &mut StateManager,
+    resume_engine: &ResumeEngine,
+    network_analyzer: &mut NetworkAnalyzer,
+    tool_config: &ToolConfig,
+    health: Option<NetworkHealth>,
+    backoff: &RetryHandler,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    // 计划部署的重试由计划条件控制，这里只处理普通部署
+    let candidates: Vec<DeploymentState> = state_manager
+        .find_resumable_deployments()
+        .into_iter()
+        .filter(|deployment| deployment.status == DeploymentStatus::Failed && deployment.schedule.is_none())
+        .filter(|deployment| match deployment.auto_resume {
+            Some(ref state) => state.is_due(now),
+            None => true,
+        })
+        .cloned()
+        .collect();
+    if candidates.is_empty() {
+        return Ok(());
+    }
+
+    match health {
+        Some(ref health) if health.is_healthy => {}
+        Some(_) | None => {
+            tracing::info!("网络尚未确认健康，暂缓自动续传 {} 个部署", candidates.len());
+            return Ok(());
+        }
+    }
+
+    let network_stats = network_analyzer.generate_network_stats().await?;
+    let config = network_analyzer.recommend_deployment_strategy(&network_stats, &tool_config.resume);
+    let max_attempts = tool_config.resume.max_auto_resume_attempts;
+
+    for deployment in candidates {
+        let deployment_id = deployment.id;
+        let lease = match state_manager.acquire_lease(&deployment_id, DEFAULT_LEASE_TTL_SECS) {
+            Ok(lease) => lease,
+            Err(e) => {
+                println!("⏭️  跳过自动续传 {}: {}", deployment_id, e);
+                continue;
+            }
+        };
+        let _lease_guard = state_manager.guard_lease(&lease, DEFAULT_LEASE_TTL_SECS);
+
+        let mut auto_resume = deployment.auto_resume.clone().unwrap_or_default();
+        auto_resume.begin_attempt(Utc::now());
+
+        let mut updated = deployment.clone();
+        updated.auto_resume = Some(auto_resume.clone());
+        updated.status = DeploymentStatus::Uploading;
+        updated.network_stats = network_stats.clone();
+        state_manager.update_deployment(updated)?;
+        state_manager.log_event(
+            &deployment_id,
+            EventType::Resumed,
+            format!("自动续传第 {}/{} 次", auto_resume.attempts, max_attempts),
+            Some(serde_json::json!({
+                "uploaded_bytes": deployment.uploaded_bytes,
+                "total_size": deployment.total_size,
+                "latency_ms": health.as_ref().map(|health| health.latency.as_millis() as u64),
+            })),
+        )?;
+        println!(
+            "🔁 自动续传部署 {} (第 {}/{} 次)",
+            deployment_id, auto_resume.attempts, max_attempts
+        );
+
+        let result = match load_inputs(&deployment, tool_config) {
+            Ok((program_data, signers)) => upload_program(
+                state_manager,
+                resume_engine,
+                &deployment_id,
+                &program_data,
+                &signers,
+                &config,
+                lease,
+            )
+            .await
+            .map(|(_, lease)| lease),
+            Err(e) => {
+                state_manager.add_error(&deployment_id, e.clone())?;
+                state_manager.log_event(&deployment_id, EventType::Failed, e.clone(), None)?;
+                state_manager.release_lease(&lease)?;
+                Err(e.into())
+            }
+        };
+
+        match result {
+            Ok(lease) => {
+                state_manager.log_event(
+                    &deployment_id,
+                    EventType::Completed,
+                    format!("自动续传完成，共 {} 次", auto_resume.attempts),
+                    None,
+                )?;
+                state_manager.release_lease(&lease)?;
+                println!("✅ 部署 {} 自动续传完成", deployment_id);
+            }
+            Err(e) => {
+                let Some(mut failed) = state_manager.get_deployment(&deployment_id).cloned() else {
+                    continue;
+                };
+                let delay = backoff.backoff_delay(auto_resume.attempts);
+                let retry_delay = chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+                if auto_resume.record_failure(max_attempts, Utc::now(), retry_delay) {
+                    state_manager.log_event(
+                        &deployment_id,
+                        EventType::Warning,
+                        format!("自动续传失败，{}s 后重试", delay.as_secs()),
+                        Some(serde_json::json!({
+                            "error": e.to_string(),
+                            "attempt": auto_resume.attempts,
+                            "max_attempts": max_attempts,
+                            "delay_ms": delay.as_millis() as u64,
+                        })),
+                    )?;
+                    println!("⏳ 部署 {} 将在 {}s 后再次自动续传", deployment_id, delay.as_secs());
+                } else {
+                    state_manager.log_event(
+                        &deployment_id,
+                        EventType::Warning,
+                        format!("自动续传已停止，已达到最大次数 {}", max_attempts),
+                        Some(serde_json::json!({ "error": e.to_string() })),
+                    )?;
+                    println!("🛑 部署 {} 自动续传已达到 {} 次，不再重试", deployment_id, max_attempts);
+                }
+                failed.auto_resume = Some(auto_resume);
+                state_manager.update_deployment(failed)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取程序文件并确认与排队时一致，同时读取工具配置的密钥对；
+/// 无人值守时所有角色都由它签名，剩余步骤需要其他密钥的部署须用 resume 手动续传
+fn load_inputs(deployment: &DeploymentState, tool_config: &ToolConfig) -> Result<(Vec<u8>, DeploymentSigners), String> {
+    let program_data = std::fs::read(&deployment.program_path)
+        .map_err(|e| format!("无法读取程序文件 {}: {}", deployment.program_path, e))?;
+    if let Some(ref expected) = deployment.program_fingerprint {
+        let fingerprint = ProgramFingerprint::compute(&program_data);
+        if expected != &fingerprint {
+            return Err(format!(
+                "程序文件已变化: 期望 sha256 {}，实际 {}",
+                expected.sha256, fingerprint.sha256
+            ));
+        }
+    }
+    let payer_keypair = read_keypair_file(tool_config.expanded_keypair_path())
+        .map_err(|e| format!("无法读取密钥对文件: {}", e))?;
+    let signers = DeploymentSigners::new(payer_keypair);
+    if let Some((role, pubkey)) = signers.missing(deployment).first() {
+        return Err(format!(
+            "需要{} {}，无法自动续传，请使用 resume --deployment-id {} {} 续传",
+            role, pubkey, deployment.id, role.flag()
+        ));
+    }
+    Ok((program_data, signers))
+}
+
+/// 执行失败后，开启 auto_resume 且未超过 max_auto_resume_attempts 时重新排队，等条件再次满足时续传
+fn requeue_after_failure(
+    state_manager: &mut StateManager,
+    tool_config: &ToolConfig,
+    deployment_id: &Uuid,
+    error: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 上传阶段的失败已由 upload_program 记录，开始上传之前的失败在这里补记
+    if state_manager
+        .get_deployment(deployment_id)
+        .is_some_and(|deployment| deployment.status != DeploymentStatus::Failed)
+    {
+        state_manager.add_error(deployment_id, error.clone())?;
+        state_manager.log_event(deployment_id, EventType::Failed, error, None)?;
+    }
+    let Some(mut deployment) = state_manager.get_deployment(deployment_id).cloned() else {
+        return Ok(());
+    };
+    let attempts = deployment.schedule.as_ref().map_or(0, |schedule| schedule.attempts);
+    let max_attempts = tool_config.resume.max_auto_resume_attempts;
+
+    if tool_config.resume.auto_resume && attempts < max_attempts {
+        deployment.status = DeploymentStatus::Scheduled;
+        state_manager.update_deployment(deployment)?;
+        state_manager.log_event(
+            deployment_id,
+            EventType::Info,
+            format!("已重新排队，条件满足时自动续传 ({}/{})", attempts, max_attempts),
+            None,
+        )?;
+        println!("🔁 计划部署 {} 已重新排队 ({}/{})", deployment_id, attempts, max_attempts);
+    } else {
+        state_manager.log_event(
+            deployment_id,
+            EventType::Warning,
+            format!("计划部署已停止，共执行 {} 次", attempts),
+            None,
+        )?;
+        println!("💡 可使用 resume --deployment-id {} 手动续传", deployment_id);
+    }
+    Ok(())
+}
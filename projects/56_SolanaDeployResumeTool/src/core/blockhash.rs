@@ -0,0 +1,162 @@
+use crate::core::rpc_pool::RpcPool;
+use anyhow::{anyhow, Result};
+use solana_client::nonce_utils;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::nonce::state::Data as NonceData;
+use solana_sdk::pubkey::Pubkey;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// 后台刷新区块哈希的间隔
+pub const BLOCKHASH_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 缓存的区块哈希超过这个时间就重新获取
+const BLOCKHASH_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// 等待区块哈希过期时查询区块高度的间隔
+const EXPIRY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 最近区块哈希及其有效期
+#[derive(Debug, Clone, Copy)]
+pub struct BlockhashInfo {
+    pub blockhash: Hash,
+    pub last_valid_block_height: u64,
+    pub fetched_at: Instant,
+}
+
+/// 交易签名所用哈希的有效期
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionLifetime {
+    /// 最近区块哈希，区块高度超过后失效
+    BlockHeight(u64),
+    /// 持久nonce，nonce账户推进到新值后失效
+    DurableNonce { account: Pubkey, nonce: Hash },
+}
+
+/// 区块哈希管理器：在后台刷新最近区块哈希，并根据 lastValidBlockHeight 判断交易是否已过期
+#[derive(Clone)]
+pub struct BlockhashManager {
+    rpc_pool: RpcPool,
+    commitment: CommitmentConfig,
+    latest: Arc<RwLock<Option<BlockhashInfo>>>,
+}
+
+impl BlockhashManager {
+    pub fn new(rpc_pool: RpcPool, commitment: CommitmentConfig) -> Self {
+        Self {
+            rpc_pool,
+            commitment,
+            latest: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// 立即从节点获取最新区块哈希
+    pub fn refresh(&self) -> Result<BlockhashInfo> {
+        let (blockhash, last_valid_block_height) = self
+            .rpc_pool
+            .call(|client| client.get_latest_blockhash_with_commitment(self.commitment))?;
+        let info = BlockhashInfo {
+            blockhash,
+            last_valid_block_height,
+            fetched_at: Instant::now(),
+        };
+        *self.latest.write().unwrap() = Some(info);
+        Ok(info)
+    }
+
+    /// 获取可用于签名的区块哈希，缓存过旧时重新获取
+    pub fn latest(&self) -> Result<BlockhashInfo> {
+        let cached = *self.latest.read().unwrap();
+        match cached {
+            Some(info) if info.fetched_at.elapsed() < BLOCKHASH_MAX_AGE => Ok(info),
+            _ => self.refresh(),
+        }
+    }
+
+    /// `latest` 的异步版本，缓存过旧时在阻塞线程池中重新获取
+    pub async fn latest_async(&self) -> Result<BlockhashInfo> {
+        let manager = self.clone();
+        tokio::task::spawn_blocking(move || manager.latest()).await?
+    }
+
+    /// 在后台定期刷新区块哈希
+    pub fn spawn_refresher(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let refresher = manager.clone();
+                match tokio::task::spawn_blocking(move || refresher.refresh()).await {
+                    Ok(Err(e)) => tracing::warn!("刷新区块哈希失败: {}", e),
+                    Err(e) => tracing::warn!("刷新区块哈希任务异常: {}", e),
+                    Ok(Ok(_)) => {}
+                }
+            }
+        })
+    }
+
+    /// 当前区块高度
+    pub fn block_height(&self) -> Result<u64> {
+        self.rpc_pool
+            .call(|client| client.get_block_height_with_commitment(self.commitment))
+    }
+
+    /// 以该高度为有效期的交易是否已经过期（不可能再上链）
+    pub fn is_expired(&self, last_valid_block_height: u64) -> Result<bool> {
+        Ok(self.block_height()? > last_valid_block_height)
+    }
+
+    /// 交易是否已经失效：区块哈希超过有效高度，或nonce账户已推进
+    pub fn is_lifetime_expired(&self, lifetime: &TransactionLifetime) -> Result<bool> {
+        match lifetime {
+            TransactionLifetime::BlockHeight(height) => self.is_expired(*height),
+            TransactionLifetime::DurableNonce { account, nonce } => {
+                Ok(self.durable_nonce(account)?.blockhash() != *nonce)
+            }
+        }
+    }
+
+    /// 读取持久nonce账户当前的nonce值和授权账户
+    pub fn durable_nonce(&self, account: &Pubkey) -> Result<NonceData> {
+        let account_data = self
+            .rpc_pool
+            .call(|client| client.get_account_with_commitment(account, self.commitment))?
+            .value
+            .ok_or_else(|| anyhow!("nonce账户不存在: {}", account))?;
+        nonce_utils::data_from_account(&account_data)
+            .map_err(|e| anyhow!("nonce账户 {} 无效: {}", account, e))
+    }
+
+    /// `durable_nonce` 的异步版本，在阻塞线程池中查询
+    pub async fn durable_nonce_async(&self, account: Pubkey) -> Result<NonceData> {
+        let manager = self.clone();
+        tokio::task::spawn_blocking(move || manager.durable_nonce(&account)).await?
+    }
+
+    /// 从指定节点读取持久nonce账户，之后可以在同一节点上查询交易状态，结果互相一致
+    pub fn durable_nonce_from(&self, client: &RpcClient, account: &Pubkey) -> Result<NonceData> {
+        self.rpc_pool.limiter().acquire();
+        let account_data = client
+            .get_account_with_commitment(account, self.commitment)?
+            .value
+            .ok_or_else(|| anyhow!("nonce账户不存在: {}", account))?;
+        nonce_utils::data_from_account(&account_data)
+            .map_err(|e| anyhow!("nonce账户 {} 无效: {}", account, e))
+    }
+
+    /// 等待直到以该高度为有效期的交易过期
+    pub async fn wait_until_expired(&self, last_valid_block_height: u64) -> Result<()> {
+        loop {
+            // 区块高度查询是同步RPC请求，放到阻塞线程池中执行
+            let manager = self.clone();
+            if tokio::task::spawn_blocking(move || manager.is_expired(last_valid_block_height)).await?? {
+                break;
+            }
+            tokio::time::sleep(EXPIRY_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::core::rate_limit::RpcLimiter;
+use crate::core::types::*;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use std::fmt;
+use std::str::FromStr;
+
+/// Solana 集群
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cluster {
+    #[serde(alias = "mainnet-beta")]
+    Mainnet,
+    Devnet,
+    Testnet,
+    Localnet,
+}
+
+impl Cluster {
+    /// 由编译特性决定的默认集群，devnet/testnet 特性优先于默认开启的 mainnet
+    pub fn compiled_default() -> Self {
+        if cfg!(feature = "devnet") {
+            Cluster::Devnet
+        } else if cfg!(feature = "testnet") {
+            Cluster::Testnet
+        } else if cfg!(feature = "mainnet") {
+            Cluster::Mainnet
+        } else {
+            Cluster::Localnet
+        }
+    }
+
+    /// 默认RPC节点
+    pub fn default_rpc_url(&self) -> &'static str {
+        match self {
+            Cluster::Mainnet => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::Localnet => "http://127.0.0.1:8899",
+        }
+    }
+
+    /// 集群的创世哈希，本地集群没有固定值
+    pub fn genesis_hash(&self) -> Option<&'static str> {
+        match self {
+            Cluster::Mainnet => Some("5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d"),
+            Cluster::Devnet => Some("EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG"),
+            Cluster::Testnet => Some("4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY"),
+            Cluster::Localnet => None,
+        }
+    }
+
+    /// 根据创世哈希识别公共集群
+    pub fn from_genesis_hash(hash: &str) -> Option<Self> {
+        [Cluster::Mainnet, Cluster::Devnet, Cluster::Testnet]
+            .into_iter()
+            .find(|cluster| cluster.genesis_hash() == Some(hash))
+    }
+
+    /// 根据RPC地址识别集群：公共节点的地址通常包含集群名，本机地址为本地集群；
+    /// 无法从地址判断时返回 None
+    pub fn from_rpc_url(rpc_url: &str) -> Option<Self> {
+        let host = url::Url::parse(rpc_url).ok()?.host_str()?.to_lowercase();
+        if host == "localhost" || host == "127.0.0.1" || host == "0.0.0.0" || host == "[::1]" {
+            Some(Cluster::Localnet)
+        } else if host.contains("devnet") {
+            Some(Cluster::Devnet)
+        } else if host.contains("testnet") {
+            Some(Cluster::Testnet)
+        } else if host.contains("mainnet") {
+            Some(Cluster::Mainnet)
+        } else {
+            None
+        }
+    }
+
+    /// 通过创世哈希识别RPC节点所在的集群，不是公共集群时视为本地集群
+    pub async fn detect(rpc_url: &str, limiter: &RpcLimiter) -> Result<Self> {
+        let genesis_hash = fetch_genesis_hash(rpc_url, limiter).await?;
+        Ok(Cluster::from_genesis_hash(&genesis_hash).unwrap_or(Cluster::Localnet))
+    }
+
+    /// 写操作前是否需要用户确认
+    pub fn requires_confirmation(&self) -> bool {
+        *self == Cluster::Mainnet
+    }
+
+    /// 默认的每笔交易优先费上限（lamports）
+    pub fn default_priority_fee_cap(&self) -> u64 {
+        match self {
+            Cluster::Mainnet => 50_000,
+            Cluster::Devnet | Cluster::Testnet => 10_000,
+            Cluster::Localnet => 0,
+        }
+    }
+
+    /// 默认的RPC每秒请求数上限，公共节点按其限流规则留出余量，本地集群不限速
+    pub fn default_requests_per_second(&self) -> f64 {
+        match self {
+            Cluster::Mainnet | Cluster::Devnet | Cluster::Testnet => 10.0,
+            Cluster::Localnet => 0.0,
+        }
+    }
+
+    /// 区块浏览器中账户的链接
+    pub fn explorer_address_url(&self, address: &str, rpc_url: &str) -> String {
+        format!("https://explorer.solana.com/address/{}{}", address, self.explorer_query(rpc_url))
+    }
+
+    /// 区块浏览器中交易的链接
+    pub fn explorer_tx_url(&self, signature: &str, rpc_url: &str) -> String {
+        format!("https://explorer.solana.com/tx/{}{}", signature, self.explorer_query(rpc_url))
+    }
+
+    fn explorer_query(&self, rpc_url: &str) -> String {
+        match self {
+            Cluster::Mainnet => String::new(),
+            Cluster::Devnet => "?cluster=devnet".to_string(),
+            Cluster::Testnet => "?cluster=testnet".to_string(),
+            Cluster::Localnet => format!("?cluster=custom&customUrl={}", rpc_url),
+        }
+    }
+
+    /// 校验RPC节点所在集群与选择的集群一致
+    pub async fn verify_rpc(&self, rpc_url: &str, limiter: &RpcLimiter) -> Result<()> {
+        let genesis_hash = fetch_genesis_hash(rpc_url, limiter).await?;
+        let actual = Cluster::from_genesis_hash(&genesis_hash);
+
+        let matches = match self.genesis_hash() {
+            Some(expected) => expected == genesis_hash,
+            // 本地集群只要不是公共集群即可
+            None => actual.is_none(),
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(DeployError::Configuration(format!(
+                "RPC节点 {} 属于 {}，与选择的集群 {} 不一致",
+                rpc_url,
+                actual.map_or_else(|| format!("未知集群 ({})", genesis_hash), |c| c.to_string()),
+                self
+            ))
+            .into())
+        }
+    }
+}
+
+/// 查询RPC节点的创世哈希，请求计入共享的限速器
+async fn fetch_genesis_hash(rpc_url: &str, limiter: &RpcLimiter) -> Result<String> {
+    limiter.acquire_async().await;
+    let client = RpcClient::new(rpc_url.to_string());
+    let url = rpc_url.to_string();
+    let genesis_hash = tokio::task::spawn_blocking(move || {
+        client
+            .get_genesis_hash()
+            .map_err(|e| DeployError::SolanaRpc(format!("获取 {} 的创世哈希失败: {}", url, e)))
+    })
+    .await??;
+    Ok(genesis_hash.to_string())
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Cluster::Mainnet => "mainnet",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+            Cluster::Localnet => "localnet",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = DeployError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet" | "mainnet-beta" | "m" => Ok(Cluster::Mainnet),
+            "devnet" | "d" => Ok(Cluster::Devnet),
+            "testnet" | "t" => Ok(Cluster::Testnet),
+            "localnet" | "localhost" | "l" => Ok(Cluster::Localnet),
+            other => Err(DeployError::Configuration(format!("未知集群: {}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_and_aliases() {
+        assert_eq!("mainnet-beta".parse::<Cluster>().unwrap(), Cluster::Mainnet);
+        assert_eq!("Devnet".parse::<Cluster>().unwrap(), Cluster::Devnet);
+        assert_eq!("t".parse::<Cluster>().unwrap(), Cluster::Testnet);
+        assert_eq!("localhost".parse::<Cluster>().unwrap(), Cluster::Localnet);
+        assert!("moonnet".parse::<Cluster>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        for cluster in [Cluster::Mainnet, Cluster::Devnet, Cluster::Testnet, Cluster::Localnet] {
+            assert_eq!(cluster.to_string().parse::<Cluster>().unwrap(), cluster);
+        }
+    }
+
+    #[test]
+    fn deserializes_mainnet_beta_alias() {
+        let cluster: Cluster = serde_json::from_str("\"mainnet-beta\"").unwrap();
+        assert_eq!(cluster, Cluster::Mainnet);
+    }
+
+    #[test]
+    fn identifies_public_clusters_by_genesis_hash() {
+        for cluster in [Cluster::Mainnet, Cluster::Devnet, Cluster::Testnet] {
+            assert_eq!(Cluster::from_genesis_hash(cluster.genesis_hash().unwrap()), Some(cluster));
+        }
+        assert_eq!(Cluster::from_genesis_hash("11111111111111111111111111111111"), None);
+    }
+
+    #[test]
+    fn infers_cluster_from_rpc_url() {
+        assert_eq!(Cluster::from_rpc_url("https://api.devnet.solana.com"), Some(Cluster::Devnet));
+        assert_eq!(Cluster::from_rpc_url("https://api.testnet.solana.com"), Some(Cluster::Testnet));
+        assert_eq!(Cluster::from_rpc_url("https://api.mainnet-beta.solana.com"), Some(Cluster::Mainnet));
+        assert_eq!(Cluster::from_rpc_url("http://127.0.0.1:8899"), Some(Cluster::Localnet));
+        assert_eq!(Cluster::from_rpc_url("http://localhost:8899"), Some(Cluster::Localnet));
+        assert_eq!(Cluster::from_rpc_url("https://rpc.example.com"), None);
+        assert_eq!(Cluster::from_rpc_url("not a url"), None);
+    }
+
+    #[test]
+    fn explorer_links_carry_cluster_query() {
+        assert_eq!(
+            Cluster::Mainnet.explorer_tx_url("sig", "https://api.mainnet-beta.solana.com"),
+            "https://explorer.solana.com/tx/sig"
+        );
+        assert_eq!(
+            Cluster::Devnet.explorer_address_url("addr", "https://api.devnet.solana.com"),
+            "https://explorer.solana.com/address/addr?cluster=devnet"
+        );
+        assert!(Cluster::Localnet
+            .explorer_tx_url("sig", "http://127.0.0.1:8899")
+            .ends_with("?cluster=custom&customUrl=http://127.0.0.1:8899"));
+    }
+
+    #[test]
+    fn only_mainnet_requires_confirmation() {
+        assert!(Cluster::Mainnet.requires_confirmation());
+        assert!(!Cluster::Devnet.requires_confirmation());
+        assert!(!Cluster::Localnet.requires_confirmation());
+    }
+}
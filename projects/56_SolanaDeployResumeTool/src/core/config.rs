@@ -0,0 +1,405 @@
+use crate::core::cluster::Cluster;
+use crate::core::types::*;
+use anyhow::{anyhow, Result};
+use config::{Config, ConfigError, Environment, File, FileFormat, Map, Source, Value};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// 项目级配置文件名
+pub const PROJECT_CONFIG_FILE: &str = "sol-deploy-resume.toml";
+
+/// 环境变量前缀，例如 `SOL_DEPLOY_RESUME_RPC_URL`、`SOL_DEPLOY_RESUME_RESUME__CHUNK_SIZE`
+pub const ENV_PREFIX: &str = "SOL_DEPLOY_RESUME";
+
+/// 工具配置（各层合并后的结果）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolConfig {
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub cluster: Cluster,
+    /// 集群是否由命令行、环境变量、配置文件或profile显式指定；
+    /// 否则由RPC地址推断，推断不出时才使用编译特性决定的默认集群
+    #[serde(skip)]
+    pub cluster_explicit: bool,
+    pub rpc_url: String,
+    /// 备用RPC节点，与 rpc_url 一起组成RPC池
+    #[serde(default)]
+    pub fallback_rpc_urls: Vec<String>,
+    pub keypair_path: String,
+    pub data_dir: String,
+    pub resume: ResumeConfig,
+    /// RPC限速和重试预算
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// 拥堵等级判定阈值
+    #[serde(default)]
+    pub congestion: CongestionThresholds,
+    /// 实际生效的配置来源，按优先级从低到高
+    #[serde(skip)]
+    pub sources: Vec<String>,
+}
+
+impl Default for ToolConfig {
+    fn default() -> Self {
+        Self::for_cluster(Cluster::compiled_default())
+    }
+}
+
+/// 命令行传入的配置选项，只包含用户显式指定的值
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOptions {
+    pub config_file: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub cluster: Option<Cluster>,
+    pub rpc_url: Option<String>,
+    pub fallback_rpc_urls: Vec<String>,
+    pub keypair_path: Option<String>,
+    pub data_dir: Option<String>,
+    /// 未显式指定集群时，通过RPC节点创世哈希识别出的集群
+    pub detected_cluster: Option<Cluster>,
+}
+
+/// 直接以键值表作为配置来源
+#[derive(Debug, Clone)]
+struct TableSource(Map<String, Value>);
+
+impl Source for TableSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> std::result::Result<Map<String, Value>, ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+impl ToolConfig {
+    /// 指定集群的默认配置
+    pub fn for_cluster(cluster: Cluster) -> Self {
+        Self {
+            profile: None,
+            cluster,
+            cluster_explicit: false,
+            rpc_url: cluster.default_rpc_url().to_string(),
+            fallback_rpc_urls: Vec::new(),
+            keypair_path: "~/.config/solana/id.json".to_string(),
+            data_dir: "./data".to_string(),
+            resume: ResumeConfig {
+                priority_fee_cap_lamports: cluster.default_priority_fee_cap(),
+                ..ResumeConfig::default()
+            },
+            rate_limit: RateLimitConfig {
+                requests_per_second: cluster.default_requests_per_second(),
+                ..RateLimitConfig::default()
+            },
+            congestion: CongestionThresholds::default(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// 按 Solana CLI配置 -> 项目配置文件(含profile) -> 环境变量 -> 命令行参数 的顺序加载配置
+    pub fn load(options: &ConfigOptions) -> Result<Self> {
+        let mut sources = Vec::new();
+        let mut layers: Vec<Box<dyn Source + Send + Sync>> = Vec::new();
+
+        // Solana CLI 配置文件
+        if let Some(cli_config_file) = solana_cli_config::CONFIG_FILE.as_ref() {
+            if Path::new(cli_config_file).exists() {
+                let cli_config = solana_cli_config::Config::load(cli_config_file)?;
+                let mut table = Map::new();
+                if !cli_config.json_rpc_url.is_empty() {
+                    table.insert("rpc_url".to_string(), Value::from(cli_config.json_rpc_url));
+                }
+                if !cli_config.keypair_path.is_empty() {
+                    table.insert("keypair_path".to_string(), Value::from(cli_config.keypair_path));
+                }
+                layers.push(Box::new(TableSource(table)));
+                sources.push(format!("Solana CLI配置 {}", cli_config_file));
+            }
+        }
+
+        // 项目配置文件，显式指定时必须存在
+        let project_file = options
+            .config_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(PROJECT_CONFIG_FILE));
+        let file_config = Config::builder()
+            .add_source(
+                File::from(project_file.as_path())
+                    .format(FileFormat::Toml)
+                    .required(options.config_file.is_some()),
+            )
+            .build()?;
+        if project_file.exists() {
+            layers.push(Box::new(file_config.clone()));
+            sources.push(format!("项目配置 {}", project_file.display()));
+        }
+
+        // 选择 profile: 命令行 > 环境变量 > 配置文件中的 default_profile
+        let profile = options
+            .profile
+            .clone()
+            .or_else(|| std::env::var(format!("{}_PROFILE", ENV_PREFIX)).ok())
+            .or_else(|| file_config.get_string("default_profile").ok());
+        if let Some(ref name) = profile {
+            let mut table = Self::builtin_profile(name).unwrap_or_default();
+            let found_builtin = !table.is_empty();
+            match file_config.get_table(&format!("profiles.{}", name)) {
+                Ok(file_table) => table.extend(file_table),
+                Err(_) if found_builtin => {}
+                Err(_) => return Err(anyhow!("未找到配置profile: {}", name)),
+            }
+            layers.push(Box::new(TableSource(table)));
+            sources.push(format!("profile {}", name));
+        }
+
+        // 环境变量
+        layers.push(Box::new(
+            Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("fallback_rpc_urls"),
+        ));
+        sources.push(format!("环境变量 {}_*", ENV_PREFIX));
+
+        // 命令行参数，显式指定集群但未指定RPC时使用该集群的默认节点
+        let mut flags = Map::new();
+        if let Some(cluster) = options.cluster {
+            flags.insert("cluster".to_string(), Value::from(cluster.to_string()));
+            if options.rpc_url.is_none() {
+                flags.insert("rpc_url".to_string(), Value::from(cluster.default_rpc_url()));
+            }
+        }
+        for (key, value) in [
+            ("rpc_url", &options.rpc_url),
+            ("keypair_path", &options.keypair_path),
+            ("data_dir", &options.data_dir),
+        ] {
+            if let Some(value) = value {
+                flags.insert(key.to_string(), Value::from(value.clone()));
+            }
+        }
+        if !options.fallback_rpc_urls.is_empty() {
+            flags.insert(
+                "fallback_rpc_urls".to_string(),
+                Value::from(options.fallback_rpc_urls.clone()),
+            );
+        }
+        layers.push(Box::new(TableSource(flags)));
+        sources.push("命令行参数".to_string());
+
+        // 先确定集群，再以该集群的默认值作为最底层。未显式指定时依次按创世哈希识别结果、
+        // RPC地址推断，编译特性决定的默认集群只作为最后的兜底
+        let merged = Config::builder().add_source(layers.clone()).build()?;
+        let explicit_cluster = merged.get_string("cluster").ok();
+        let cluster = match explicit_cluster {
+            Some(ref name) => name.parse::<Cluster>()?,
+            None => options
+                .detected_cluster
+                .or_else(|| merged.get_string("rpc_url").ok().and_then(|url| Cluster::from_rpc_url(&url)))
+                .unwrap_or_else(Cluster::compiled_default),
+        };
+        sources.insert(0, format!("内置默认值 ({})", cluster));
+
+        let mut tool_config: ToolConfig = Config::builder()
+            .add_source(Config::try_from(&ToolConfig::for_cluster(cluster))?)
+            .add_source(layers)
+            .build()?
+            .try_deserialize()?;
+        tool_config.profile = profile;
+        tool_config.cluster_explicit = explicit_cluster.is_some();
+        tool_config.sources = sources;
+        Ok(tool_config)
+    }
+
+    /// 内置 profile
+    fn builtin_profile(name: &str) -> Option<Map<String, Value>> {
+        let cluster = match name {
+            "devnet" | "testnet" | "mainnet" | "mainnet-beta" => name.parse::<Cluster>().ok()?,
+            _ => return None,
+        };
+        let mut table = Map::new();
+        table.insert("cluster".to_string(), Value::from(cluster.to_string()));
+        table.insert("rpc_url".to_string(), Value::from(cluster.default_rpc_url()));
+        Some(table)
+    }
+
+    /// RPC池中的全部节点，主节点在前
+    pub fn rpc_urls(&self) -> Vec<String> {
+        std::iter::once(self.rpc_url.clone())
+            .chain(self.fallback_rpc_urls.iter().cloned())
+            .collect()
+    }
+
+    /// 展开后的密钥对路径
+    pub fn expanded_keypair_path(&self) -> String {
+        expand_home(&self.keypair_path)
+    }
+}
+
+/// 展开路径开头的 `~/`
+pub fn expand_home(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest).display().to_string();
+        }
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn project_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn options_for(file: &tempfile::NamedTempFile) -> ConfigOptions {
+        ConfigOptions {
+            config_file: Some(file.path().to_path_buf()),
+            ..ConfigOptions::default()
+        }
+    }
+
+    #[test]
+    fn command_line_overrides_project_file() {
+        let file = project_file(
+            r#"
+            rpc_url = "https://rpc.example.com"
+            keypair_path = "/keys/file.json"
+            data_dir = "/data/file"
+            "#,
+        );
+        let options = ConfigOptions {
+            data_dir: Some("/data/flag".to_string()),
+            ..options_for(&file)
+        };
+        let config = ToolConfig::load(&options).unwrap();
+        assert_eq!(config.rpc_url, "https://rpc.example.com");
+        assert_eq!(config.keypair_path, "/keys/file.json");
+        assert_eq!(config.data_dir, "/data/flag");
+        assert_eq!(config.sources.last().map(String::as_str), Some("命令行参数"));
+    }
+
+    #[test]
+    fn nested_values_fall_back_to_cluster_defaults() {
+        let file = project_file(
+            r#"
+            cluster = "devnet"
+            rpc_url = "https://rpc.example.com"
+
+            [resume]
+            max_retries = 9
+            max_auto_resume_attempts = 2
+            "#,
+        );
+        let config = ToolConfig::load(&options_for(&file)).unwrap();
+        assert_eq!(config.cluster, Cluster::Devnet);
+        assert!(config.cluster_explicit);
+        assert_eq!(config.resume.max_retries, 9);
+        assert_eq!(config.resume.max_auto_resume_attempts, 2);
+        assert_eq!(
+            config.resume.priority_fee_cap_lamports,
+            Cluster::Devnet.default_priority_fee_cap()
+        );
+    }
+
+    #[test]
+    fn default_profile_from_project_file_is_applied() {
+        let file = project_file(
+            r#"
+            default_profile = "staging"
+            rpc_url = "https://rpc.example.com"
+            data_dir = "/data/file"
+
+            [profiles.staging]
+            data_dir = "/data/staging"
+            "#,
+        );
+        let config = ToolConfig::load(&options_for(&file)).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("staging"));
+        assert_eq!(config.data_dir, "/data/staging");
+        assert!(config.sources.iter().any(|source| source == "profile staging"));
+    }
+
+    #[test]
+    fn builtin_profile_selects_cluster_and_rpc() {
+        let file = project_file("");
+        let options = ConfigOptions {
+            profile: Some("devnet".to_string()),
+            ..options_for(&file)
+        };
+        let config = ToolConfig::load(&options).unwrap();
+        assert_eq!(config.cluster, Cluster::Devnet);
+        assert_eq!(config.rpc_url, Cluster::Devnet.default_rpc_url());
+    }
+
+    #[test]
+    fn unknown_profile_is_an_error() {
+        let file = project_file("");
+        let options = ConfigOptions {
+            profile: Some("missing".to_string()),
+            ..options_for(&file)
+        };
+        assert!(ToolConfig::load(&options).is_err());
+    }
+
+    #[test]
+    fn explicit_config_file_must_exist() {
+        let options = ConfigOptions {
+            config_file: Some(PathBuf::from("/nonexistent/sol-deploy-resume.toml")),
+            ..ConfigOptions::default()
+        };
+        assert!(ToolConfig::load(&options).is_err());
+    }
+
+    #[test]
+    fn cluster_is_inferred_from_rpc_url_when_not_explicit() {
+        let file = project_file(r#"rpc_url = "https://api.devnet.solana.com""#);
+        let config = ToolConfig::load(&options_for(&file)).unwrap();
+        assert_eq!(config.cluster, Cluster::Devnet);
+        assert!(!config.cluster_explicit);
+        assert_eq!(config.resume.priority_fee_cap_lamports, Cluster::Devnet.default_priority_fee_cap());
+    }
+
+    #[test]
+    fn detected_cluster_takes_precedence_over_url() {
+        let file = project_file(r#"rpc_url = "https://devnet.example.com""#);
+        let options = ConfigOptions {
+            detected_cluster: Some(Cluster::Localnet),
+            ..options_for(&file)
+        };
+        let config = ToolConfig::load(&options).unwrap();
+        assert_eq!(config.cluster, Cluster::Localnet);
+        assert!(!config.cluster_explicit);
+    }
+
+    #[test]
+    fn cluster_flag_without_rpc_uses_cluster_default() {
+        let file = project_file("");
+        let options = ConfigOptions {
+            cluster: Some(Cluster::Testnet),
+            ..options_for(&file)
+        };
+        let config = ToolConfig::load(&options).unwrap();
+        assert_eq!(config.cluster, Cluster::Testnet);
+        assert!(config.cluster_explicit);
+        assert_eq!(config.rpc_url, Cluster::Testnet.default_rpc_url());
+    }
+
+    #[test]
+    fn rpc_urls_lists_primary_first() {
+        let config = ToolConfig {
+            rpc_url: "https://a.example.com".to_string(),
+            fallback_rpc_urls: vec!["https://b.example.com".to_string()],
+            ..ToolConfig::for_cluster(Cluster::Localnet)
+        };
+        assert_eq!(config.rpc_urls(), vec!["https://a.example.com", "https://b.example.com"]);
+    }
+}
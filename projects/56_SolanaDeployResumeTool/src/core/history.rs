@@ -0,0 +1,139 @@
+use crate::core::types::*;
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use serde::Serialize;
+
+/// 时段至少需要的样本数，样本不足的时段不参与推荐
+pub const MIN_WINDOW_SAMPLES: usize = 2;
+
+/// 推荐时向后查找的小时数
+const RECOMMENDATION_HORIZON_HOURS: i64 = 7 * 24;
+
+const WEEKDAY_NAMES: [&str; 7] = ["周一", "周二", "周三", "周四", "周五", "周六", "周日"];
+
+/// 某个星期几某个小时（UTC）的历史网络状况
+#[derive(Debug, Clone, Serialize)]
+pub struct HourlyProfile {
+    /// 0 表示周一
+    pub weekday: u32,
+    pub hour: u32,
+    pub samples: usize,
+    /// 平均拥堵程度，0 为 Low，3 为 Critical
+    pub average_congestion: f64,
+    pub success_rate: f64,
+    pub average_priority_fee_p50: Option<f64>,
+    pub average_slot_time_ms: Option<f64>,
+}
+
+/// 推荐的部署时间窗口
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub weekday: String,
+    pub samples: usize,
+    pub expected_congestion: CongestionLevel,
+    /// 预期优先费 p50（micro-lamports/CU），历史样本中没有优先费数据时为 None
+    pub expected_priority_fee: Option<u64>,
+    pub expected_success_rate: f64,
+    /// 综合评分，越低越好
+    pub score: f64,
+}
+
+fn congestion_value(level: &CongestionLevel) -> f64 {
+    match level {
+        CongestionLevel::Low => 0.0,
+        CongestionLevel::Medium => 1.0,
+        CongestionLevel::High => 2.0,
+        CongestionLevel::Critical => 3.0,
+    }
+}
+
+fn congestion_from_value(value: f64) -> CongestionLevel {
+    match value.round() as i64 {
+        i64::MIN..=0 => CongestionLevel::Low,
+        1 => CongestionLevel::Medium,
+        2 => CongestionLevel::High,
+        _ => CongestionLevel::Critical,
+    }
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// 星期几的中文名称，0 表示周一
+pub fn weekday_name(weekday: u32) -> &'static str {
+    WEEKDAY_NAMES[weekday as usize % 7]
+}
+
+/// 按星期几和小时（UTC）聚合样本，只返回有样本的时段
+pub fn hourly_profiles(samples: &[NetworkSample]) -> Vec<HourlyProfile> {
+    let mut buckets: Vec<Vec<&NetworkSample>> = vec![Vec::new(); 7 * 24];
+    for sample in samples {
+        let weekday = sample.timestamp.weekday().num_days_from_monday();
+        buckets[(weekday * 24 + sample.timestamp.hour()) as usize].push(sample);
+    }
+
+    buckets
+        .into_iter()
+        .enumerate()
+        .filter(|(_, bucket)| !bucket.is_empty())
+        .map(|(index, bucket)| HourlyProfile {
+            weekday: index as u32 / 24,
+            hour: index as u32 % 24,
+            samples: bucket.len(),
+            average_congestion: average(bucket.iter().map(|s| congestion_value(&s.congestion_level))).unwrap_or_default(),
+            success_rate: average(bucket.iter().map(|s| s.success_rate)).unwrap_or_default(),
+            average_priority_fee_p50: average(bucket.iter().filter_map(|s| s.priority_fee_p50).map(|fee| fee as f64)),
+            average_slot_time_ms: average(bucket.iter().filter_map(|s| s.slot_time_ms)),
+        })
+        .collect()
+}
+
+/// 在接下来一周内，按历史同一时段的网络状况推荐最多 `count` 个一小时的部署窗口，按评分排序。
+/// 评分综合平均拥堵程度、失败率和相对优先费
+pub fn recommend_windows(samples: &[NetworkSample], now: DateTime<Utc>, count: usize) -> Vec<DeploymentWindow> {
+    let profiles: Vec<HourlyProfile> = hourly_profiles(samples)
+        .into_iter()
+        .filter(|profile| profile.samples >= MIN_WINDOW_SAMPLES)
+        .collect();
+    let max_fee = profiles
+        .iter()
+        .filter_map(|profile| profile.average_priority_fee_p50)
+        .fold(0.0, f64::max);
+
+    let current_hour = now.duration_trunc(Duration::hours(1)).unwrap_or(now);
+    let mut windows: Vec<DeploymentWindow> = (0..RECOMMENDATION_HORIZON_HOURS)
+        .filter_map(|offset| {
+            let start = current_hour + Duration::hours(offset);
+            let weekday = start.weekday().num_days_from_monday();
+            let profile = profiles
+                .iter()
+                .find(|profile| profile.weekday == weekday && profile.hour == start.hour())?;
+            let relative_fee = match profile.average_priority_fee_p50 {
+                Some(fee) if max_fee > 0.0 => fee / max_fee,
+                _ => 0.0,
+            };
+            Some(DeploymentWindow {
+                start,
+                end: start + Duration::hours(1),
+                weekday: weekday_name(weekday).to_string(),
+                samples: profile.samples,
+                expected_congestion: congestion_from_value(profile.average_congestion),
+                expected_priority_fee: profile.average_priority_fee_p50.map(|fee| fee.round() as u64),
+                expected_success_rate: profile.success_rate,
+                score: profile.average_congestion + (1.0 - profile.success_rate) * 10.0 + relative_fee,
+            })
+        })
+        .collect();
+
+    windows.sort_by(|a, b| {
+        a.score
+            .partial_cmp(&b.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.start.cmp(&b.start))
+    });
+    windows.truncate(count);
+    windows
+}
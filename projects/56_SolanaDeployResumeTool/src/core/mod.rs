@@ -5,11 +5,25 @@ pub mod optimizer;
 pub mod types;
 pub mod retry;
 pub mod performance;
+pub mod schema;
+pub mod config;
+pub mod cluster;
+pub mod rpc_pool;
+pub mod sender;
+pub mod blockhash;
+pub mod rate_limit;
+pub mod history;
+pub mod tuning;
+pub mod planner;
+pub mod signers;
 
 pub use state::StateManager;
 pub use resume::ResumeEngine;
 pub use network::NetworkAnalyzer;
 pub use optimizer::FeeOptimizer;
+pub use rpc_pool::RpcPool;
+pub use rate_limit::RpcLimiter;
+pub use sender::TransactionSender;
 pub use retry::{RetryHandler, CircuitBreaker, HealthChecker};
 pub use performance::PerformanceOptimizer;
 pub use types::*; 
\ No newline at end of file
@@ -1,12 +1,37 @@
+use crate::core::performance::BandwidthMonitor;
+use crate::core::planner::{ChunkPlan, WriteTransactionLayout};
+use crate::core::rpc_pool::RpcPool;
+use crate::core::sender::ConfirmationTracker;
 use crate::core::types::*;
 use anyhow::Result;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_sdk::{
+    bpf_loader_upgradeable, commitment_config::CommitmentConfig, message::Message, pubkey::Pubkey,
+    transaction::Transaction,
+};
 use std::time::{Duration, Instant};
 use tokio::time::timeout;
 
+/// 查询的性能样本数，每个样本约60秒
+const PERFORMANCE_SAMPLE_COUNT: usize = 5;
+
 /// 网络分析器
 pub struct NetworkAnalyzer {
-    rpc_url: String,
+    rpc_pool: RpcPool,
     recent_measurements: Vec<LatencyMeasurement>,
+    bandwidth_monitor: BandwidthMonitor,
+    throughput_probe: bool,
+    confirmation_tracker: ConfirmationTracker,
+    congestion_thresholds: CongestionThresholds,
+}
+
+/// 拥堵判定结果
+#[derive(Debug, Clone)]
+pub struct CongestionReport {
+    pub level: CongestionLevel,
+    pub signals: CongestionSignals,
+    /// 每项达到拥堵阈值或无法获取的指标的说明
+    pub reasons: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,20 +44,52 @@ struct LatencyMeasurement {
 impl NetworkAnalyzer {
     /// 创建新的网络分析器
     pub fn new(rpc_url: String) -> Self {
+        Self::with_pool(RpcPool::single(rpc_url))
+    }
+    
+    /// 使用RPC池创建网络分析器，测量当前最健康的节点
+    pub fn with_pool(rpc_pool: RpcPool) -> Self {
         Self {
-            rpc_url,
+            rpc_pool,
             recent_measurements: Vec::new(),
+            bandwidth_monitor: BandwidthMonitor::new(),
+            throughput_probe: false,
+            confirmation_tracker: ConfirmationTracker::default(),
+            congestion_thresholds: CongestionThresholds::default(),
         }
     }
     
+    /// 使用与交易发送器共享的确认用时记录
+    pub fn with_confirmation_tracker(mut self, tracker: ConfirmationTracker) -> Self {
+        self.confirmation_tracker = tracker;
+        self
+    }
+    
+    /// 设置拥堵判定阈值
+    pub fn set_congestion_thresholds(&mut self, thresholds: CongestionThresholds) {
+        self.congestion_thresholds = thresholds;
+    }
+    
+    /// 使用与续传引擎共享的带宽监控器，吞吐量取自真实上传
+    pub fn with_bandwidth_monitor(mut self, bandwidth_monitor: BandwidthMonitor) -> Self {
+        self.bandwidth_monitor = bandwidth_monitor;
+        self
+    }
+    
+    /// 没有上传样本时是否主动探测吞吐量
+    pub fn set_throughput_probe(&mut self, enabled: bool) {
+        self.throughput_probe = enabled;
+    }
+    
     /// 测量网络延迟
     pub async fn measure_latency(&mut self) -> Result<f64> {
         let start = Instant::now();
         
         // 发送简单的健康检查请求
+        self.rpc_pool.limiter().acquire_async().await;
         let client = reqwest::Client::new();
         let request_future = client
-            .post(&self.rpc_url)
+            .post(self.rpc_pool.primary_url())
             .json(&serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": 1,
@@ -41,6 +98,9 @@ impl NetworkAnalyzer {
             .send();
         
         match timeout(Duration::from_secs(5), request_future).await {
+            Ok(Ok(response)) if response.status().as_u16() == 429 => {
+                Err(DeployError::rate_limited(response.headers()).into())
+            }
             Ok(Ok(response)) => {
                 let latency_ms = start.elapsed().as_millis() as f64;
                 let success = response.status().is_success();
@@ -107,50 +167,110 @@ impl NetworkAnalyzer {
         success_count as f64 / self.recent_measurements.len() as f64
     }
     
-    /// 估算网络吞吐量
-    pub async fn estimate_throughput(&mut self) -> Result<f64> {
-        // 发送一个较大的测试请求来估算吞吐量
-        let test_data = vec![0u8; 1024]; // 1KB测试数据
+    /// 主动探测吞吐量：用 simulateTransaction 提交一笔满载的写入交易（不验签、替换区块哈希），
+    /// 以交易字节数除以往返时间估算，返回字节/秒
+    pub async fn probe_throughput(&self) -> Result<f64> {
+        let authority = Pubkey::new_unique();
+        let instruction = bpf_loader_upgradeable::write(
+            &Pubkey::new_unique(),
+            &authority,
+            0,
+            vec![0u8; ChunkPlan::new(LoaderVersion::V3, WriteTransactionLayout::default()).max_payload],
+        );
+        let transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&authority)));
+        // 签名数量(compact-u16) + 签名 + 消息
+        let transaction_bytes = 1 + transaction.signatures.len() * 64 + transaction.message_data().len();
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(CommitmentConfig::processed()),
+            ..RpcSimulateTransactionConfig::default()
+        };
+
         let start = Instant::now();
-        
-        let client = reqwest::Client::new();
-        
-        match client
-            .post(&self.rpc_url)
-            .json(&serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": 1,
-                "method": "getAccountInfo",
-                "params": [
-                    "11111111111111111111111111111112", // System Program
-                    {"encoding": "base64"}
-                ]
-            }))
-            .send()
-            .await
-        {
-            Ok(_response) => {
-                let elapsed = start.elapsed().as_secs_f64();
-                let bytes_per_second = 1024.0 / elapsed;
-                Ok(bytes_per_second)
+        self.rpc_pool
+            .call_blocking(move |client| client.simulate_transaction_with_config(&transaction, config.clone()))
+            .await?;
+        let elapsed = start.elapsed().as_secs_f64();
+        Ok(transaction_bytes as f64 / elapsed)
+    }
+    
+    /// 当前吞吐量：优先使用真实上传的样本，没有样本时按配置主动探测，否则返回 0
+    pub async fn current_throughput(&self) -> f64 {
+        if let Some(bandwidth) = self.bandwidth_monitor.current_bandwidth() {
+            return bandwidth;
+        }
+        if !self.throughput_probe {
+            return 0.0;
+        }
+        match self.probe_throughput().await {
+            Ok(bandwidth) => bandwidth,
+            Err(e) => {
+                tracing::warn!("吞吐量探测失败: {}", e);
+                0.0
             }
-            Err(e) => Err(anyhow::anyhow!("吞吐量测试失败: {}", e)),
         }
     }
     
     /// 检测网络拥堵等级
     pub async fn detect_congestion_level(&mut self) -> Result<CongestionLevel> {
+        Ok(self.assess_congestion().await?.level)
+    }
+    
+    /// 采集拥堵指标并判定等级：本地到RPC的延迟和成功率、最近性能样本的出块时间和TPS、
+    /// 最近区块的优先费分位数，以及本工具交易的确认用时
+    pub async fn assess_congestion(&mut self) -> Result<CongestionReport> {
         let _ = self.measure_latency().await;
-        let avg_latency = self.get_average_latency();
-        let success_rate = self.get_success_rate();
+        let mut signals = CongestionSignals {
+            http_latency_ms: self.get_average_latency(),
+            success_rate: self.get_success_rate(),
+            ..CongestionSignals::default()
+        };
         
-        // 根据延迟和成功率判断拥堵等级
-        match (avg_latency, success_rate) {
-            (lat, rate) if lat < 100.0 && rate > 0.95 => Ok(CongestionLevel::Low),
-            (lat, rate) if lat < 300.0 && rate > 0.90 => Ok(CongestionLevel::Medium),
-            (lat, rate) if lat < 1000.0 && rate > 0.80 => Ok(CongestionLevel::High),
-            _ => Ok(CongestionLevel::Critical),
+        match self
+            .rpc_pool
+            .call_blocking(|client| client.get_recent_performance_samples(Some(PERFORMANCE_SAMPLE_COUNT)))
+            .await
+        {
+            Ok(samples) => {
+                let slots: u64 = samples.iter().map(|sample| sample.num_slots).sum();
+                let seconds: u64 = samples.iter().map(|sample| sample.sample_period_secs as u64).sum();
+                if slots > 0 && seconds > 0 {
+                    let transactions: u64 = samples.iter().map(|sample| sample.num_transactions).sum();
+                    signals.slot_time_ms = Some(seconds as f64 * 1000.0 / slots as f64);
+                    signals.tps = Some(transactions as f64 / seconds as f64);
+                    signals.non_vote_tps = samples
+                        .iter()
+                        .map(|sample| sample.num_non_vote_transactions)
+                        .sum::<Option<u64>>()
+                        .map(|non_vote| non_vote as f64 / seconds as f64);
+                }
+            }
+            Err(e) => tracing::warn!("获取性能样本失败: {}", e),
+        }
+        
+        match self
+            .rpc_pool
+            .call_blocking(|client| client.get_recent_prioritization_fees(&[]))
+            .await
+        {
+            Ok(fees) if !fees.is_empty() => {
+                let mut fees: Vec<u64> = fees.iter().map(|fee| fee.prioritization_fee).collect();
+                fees.sort_unstable();
+                signals.priority_fee_p50 = Some(percentile(&fees, 0.50));
+                signals.priority_fee_p75 = Some(percentile(&fees, 0.75));
+                signals.priority_fee_p90 = Some(percentile(&fees, 0.90));
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("获取优先费失败: {}", e),
         }
+        
+        signals.confirmation_latency_ms = self
+            .confirmation_tracker
+            .average()
+            .map(|latency| latency.as_secs_f64() * 1000.0);
+        
+        Ok(classify_congestion(signals, &self.congestion_thresholds))
     }
     
     /// 计算最优块大小
@@ -170,55 +290,51 @@ impl NetworkAnalyzer {
             Err(_) => self.get_average_latency(),
         };
         
-        let throughput = match self.estimate_throughput().await {
-            Ok(tput) => tput,
-            Err(_) => 1024.0 * 8.0, // 默认8KB/s
-        };
+        let throughput = self.current_throughput().await;
         
+        let congestion = self.assess_congestion().await?;
         let packet_loss_rate = 1.0 - self.get_success_rate();
-        let congestion_level = self.detect_congestion_level().await?;
-        let optimal_chunk_size = self.calculate_optimal_chunk_size(congestion_level.clone());
+        let optimal_chunk_size = self.calculate_optimal_chunk_size(congestion.level.clone());
         
         Ok(NetworkStats {
             latency_ms: latency,
             throughput_bps: throughput,
             packet_loss_rate,
-            congestion_level,
+            congestion_level: congestion.level,
             optimal_chunk_size,
+            congestion_reasons: congestion.reasons,
+            congestion_signals: congestion.signals,
         })
     }
     
-    /// 推荐部署策略
-    pub fn recommend_deployment_strategy(&self, network_stats: &NetworkStats) -> ResumeConfig {
-        let mut config = ResumeConfig::default();
+    /// 推荐部署策略，并发数不超过用户配置；块大小沿用用户配置作为上限，上传时由AIMD控制器调整
+    pub fn recommend_deployment_strategy(&self, network_stats: &NetworkStats, base: &ResumeConfig) -> ResumeConfig {
+        let mut config = base.clone();
         
         match network_stats.congestion_level {
             CongestionLevel::Low => {
-                config.chunk_size = 8192;
                 config.parallel_uploads = 8;
                 config.retry_delay_ms = 500;
                 config.max_retries = 2;
             }
             CongestionLevel::Medium => {
-                config.chunk_size = 4096;
                 config.parallel_uploads = 4;
                 config.retry_delay_ms = 1000;
                 config.max_retries = 3;
             }
             CongestionLevel::High => {
-                config.chunk_size = 2048;
                 config.parallel_uploads = 2;
                 config.retry_delay_ms = 2000;
                 config.max_retries = 5;
             }
             CongestionLevel::Critical => {
-                config.chunk_size = 1024;
                 config.parallel_uploads = 1;
                 config.retry_delay_ms = 5000;
                 config.max_retries = 10;
             }
         }
         
+        config.parallel_uploads = config.parallel_uploads.min(base.parallel_uploads);
         config
     }
     
@@ -232,7 +348,7 @@ impl NetworkAnalyzer {
             match self.generate_network_stats().await {
                 Ok(stats) => {
                     stats_history.push(stats);
-                    println!(
+                    eprintln!(
                         "网络状况: 延迟 {:.1}ms, 拥堵等级 {:?}",
                         stats_history.last().unwrap().latency_ms,
                         stats_history.last().unwrap().congestion_level
@@ -271,4 +387,165 @@ impl NetworkAnalyzer {
             CongestionLevel::Critical => Some("网络严重拥堵，强烈建议延后部署".to_string()),
         }
     }
-} 
\ No newline at end of file
+}
+
+/// 已排序数据的分位数
+fn percentile(sorted: &[u64], quantile: f64) -> u64 {
+    let index = ((sorted.len() - 1) as f64 * quantile).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+fn level_name(level: &CongestionLevel) -> &'static str {
+    match level {
+        CongestionLevel::Low => "低",
+        CongestionLevel::Medium => "中",
+        CongestionLevel::High => "高",
+        CongestionLevel::Critical => "严重",
+    }
+}
+
+/// 按阈值判定各项指标的拥堵等级，综合等级取最严重的一项，并说明每项的判定依据
+pub fn classify_congestion(signals: CongestionSignals, thresholds: &CongestionThresholds) -> CongestionReport {
+    let mut level = CongestionLevel::Low;
+    let mut reasons = Vec::new();
+    let mut missing = Vec::new();
+    
+    let mut check = |name: &str, value: Option<f64>, limits: &LevelThresholds, unit: &str| match value {
+        Some(value) => {
+            let (signal_level, threshold) = limits.classify(value);
+            if signal_level > CongestionLevel::Low {
+                reasons.push(format!(
+                    "{} {:.0}{} 达到「{}」阈值 {:.0}{}",
+                    name,
+                    value,
+                    unit,
+                    level_name(&signal_level),
+                    threshold,
+                    unit
+                ));
+            }
+            level = level.clone().max(signal_level);
+        }
+        None => missing.push(name.to_string()),
+    };
+    
+    check("出块时间", signals.slot_time_ms, &thresholds.slot_time_ms, "ms");
+    check(
+        "优先费p75",
+        signals.priority_fee_p75.map(|fee| fee as f64),
+        &thresholds.priority_fee_p75,
+        " micro-lamports/CU",
+    );
+    check("交易确认用时", signals.confirmation_latency_ms, &thresholds.confirmation_latency_ms, "ms");
+    check("本地到RPC延迟", Some(signals.http_latency_ms), &thresholds.http_latency_ms, "ms");
+    // 失败率以百分比展示
+    let failure_percent = LevelThresholds::new(
+        thresholds.failure_rate.medium * 100.0,
+        thresholds.failure_rate.high * 100.0,
+        thresholds.failure_rate.critical * 100.0,
+    );
+    check("RPC失败率", Some((1.0 - signals.success_rate) * 100.0), &failure_percent, "%");
+    
+    if reasons.is_empty() {
+        reasons.push("各项指标均低于拥堵阈值".to_string());
+    }
+    if !missing.is_empty() {
+        reasons.push(format!("未获取到 {}，未参与判定", missing.join("、")));
+    }
+    
+    CongestionReport {
+        level,
+        signals,
+        reasons,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy_signals() -> CongestionSignals {
+        CongestionSignals {
+            http_latency_ms: 50.0,
+            success_rate: 1.0,
+            slot_time_ms: Some(400.0),
+            priority_fee_p75: Some(100),
+            confirmation_latency_ms: Some(1_000.0),
+            ..CongestionSignals::default()
+        }
+    }
+
+    #[test]
+    fn level_thresholds_are_inclusive() {
+        let limits = LevelThresholds::new(10.0, 20.0, 30.0);
+        assert_eq!(limits.classify(9.9).0, CongestionLevel::Low);
+        assert_eq!(limits.classify(10.0), (CongestionLevel::Medium, 10.0));
+        assert_eq!(limits.classify(20.0), (CongestionLevel::High, 20.0));
+        assert_eq!(limits.classify(30.0), (CongestionLevel::Critical, 30.0));
+        assert_eq!(limits.classify(1e9).0, CongestionLevel::Critical);
+    }
+
+    #[test]
+    fn healthy_signals_are_low() {
+        let report = classify_congestion(healthy_signals(), &CongestionThresholds::default());
+        assert_eq!(report.level, CongestionLevel::Low);
+        assert_eq!(report.reasons, vec!["各项指标均低于拥堵阈值".to_string()]);
+    }
+
+    #[test]
+    fn most_severe_signal_wins() {
+        let signals = CongestionSignals {
+            slot_time_ms: Some(700.0),
+            priority_fee_p75: Some(600_000),
+            ..healthy_signals()
+        };
+        let report = classify_congestion(signals, &CongestionThresholds::default());
+        assert_eq!(report.level, CongestionLevel::Critical);
+        assert_eq!(report.reasons.len(), 2);
+    }
+
+    #[test]
+    fn failure_rate_is_compared_as_fraction() {
+        let signals = CongestionSignals {
+            success_rate: 0.85,
+            ..healthy_signals()
+        };
+        let report = classify_congestion(signals, &CongestionThresholds::default());
+        assert_eq!(report.level, CongestionLevel::High);
+        assert!(report.reasons[0].starts_with("RPC失败率"));
+    }
+
+    #[test]
+    fn missing_signals_are_reported_and_ignored() {
+        let signals = CongestionSignals {
+            slot_time_ms: None,
+            priority_fee_p75: None,
+            confirmation_latency_ms: None,
+            ..healthy_signals()
+        };
+        let report = classify_congestion(signals, &CongestionThresholds::default());
+        assert_eq!(report.level, CongestionLevel::Low);
+        assert!(report.reasons.last().unwrap().contains("出块时间"));
+    }
+
+    #[test]
+    fn custom_thresholds_are_respected() {
+        let thresholds = CongestionThresholds {
+            http_latency_ms: LevelThresholds::new(10.0, 20.0, 40.0),
+            ..CongestionThresholds::default()
+        };
+        let report = classify_congestion(healthy_signals(), &thresholds);
+        assert_eq!(report.level, CongestionLevel::Critical);
+    }
+
+    #[test]
+    fn percentile_picks_nearest_rank() {
+        let sorted = [1, 2, 3, 4, 5];
+        assert_eq!(percentile(&sorted, 0.0), 1);
+        assert_eq!(percentile(&sorted, 0.5), 3);
+        assert_eq!(percentile(&sorted, 0.75), 4);
+        assert_eq!(percentile(&sorted, 1.0), 5);
+        assert_eq!(percentile(&[7], 0.9), 7);
+    }
+}
@@ -1,17 +1,24 @@
+use crate::core::planner::{write_message, ChunkPlan, WriteTransactionLayout};
+use crate::core::rpc_pool::RpcPool;
+use crate::core::tuning::{AimdController, UploadTuning};
 use crate::core::types::*;
 use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
+use serde::Serialize;
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
-    // pubkey::Pubkey,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    loader_v4::{self, LoaderV4State},
+    message::Message,
+    pubkey::Pubkey,
 };
-use std::collections::HashMap;
+use uuid::Uuid;
 
 /// 费用优化器
 pub struct FeeOptimizer {
-    rpc_client: RpcClient,
+    rpc_pool: RpcPool,
     fee_history: Vec<FeeRecord>,
-    buffer_registry: HashMap<String, Vec<BufferInfo>>,
+    priority_fee_cap: Option<u64>,
+    write_layout: WriteTransactionLayout,
+    max_chunk_size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -25,21 +32,44 @@ struct FeeRecord {
 impl FeeOptimizer {
     /// 创建新的费用优化器
     pub fn new(rpc_url: String) -> Self {
+        Self::with_pool(RpcPool::single(rpc_url))
+    }
+    
+    /// 使用RPC池创建费用优化器
+    pub fn with_pool(rpc_pool: RpcPool) -> Self {
         Self {
-            rpc_client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed()),
+            rpc_pool,
             fee_history: Vec::new(),
-            buffer_registry: HashMap::new(),
+            priority_fee_cap: None,
+            write_layout: WriteTransactionLayout::default(),
+            max_chunk_size: 0,
         }
     }
     
-    /// 获取当前基础费用
+    /// 设置每笔交易的优先费上限
+    pub fn set_priority_fee_cap(&mut self, cap: u64) {
+        self.priority_fee_cap = Some(cap);
+    }
+    
+    /// 设置写入交易的结构，估算交易数时按其计算每笔交易可携带的数据量
+    pub fn set_write_layout(&mut self, layout: WriteTransactionLayout) {
+        self.write_layout = layout;
+    }
+    
+    /// 设置块大小上限，0 表示按交易包长自动计算
+    pub fn set_max_chunk_size(&mut self, max_chunk_size: usize) {
+        self.max_chunk_size = max_chunk_size;
+    }
+    
+    /// 获取当前基础费用：按写入交易的结构向节点查询一笔交易的费用，不含优先费
     pub async fn get_current_base_fee(&mut self) -> Result<u64> {
-        // 模拟获取当前网络基础费用
-        // 实际应该通过 RPC 调用获取
-        match self.rpc_client.get_recent_blockhash() {
-            Ok(_) => {
-                let base_fee = 5000; // 5000 lamports 作为基础费用
-                
+        let layout = WriteTransactionLayout {
+            compute_budget: false,
+            ..self.write_layout.clone()
+        };
+        let mut message = write_message(&LoaderVersion::V3, &layout, 0);
+        match self.fee_for_message(&mut message).await {
+            Ok(base_fee) => {
                 self.fee_history.push(FeeRecord {
                     timestamp: chrono::Utc::now(),
                     base_fee,
@@ -53,6 +83,15 @@ impl FeeOptimizer {
         }
     }
     
+    /// 用最新区块哈希查询消息的费用
+    async fn fee_for_message(&self, message: &mut Message) -> Result<u64> {
+        message.recent_blockhash = self.rpc_pool.call_blocking(|client| client.get_latest_blockhash()).await?;
+        let message = message.clone();
+        self.rpc_pool
+            .call_blocking(move |client| client.get_fee_for_message(&message))
+            .await
+    }
+    
     /// 计算优先费用
     pub async fn calculate_priority_fee(&mut self, congestion_level: &CongestionLevel) -> Result<u64> {
         let base_priority = match congestion_level {
@@ -76,7 +115,7 @@ impl FeeOptimizer {
             base_priority
         };
         
-        Ok(adjusted_priority)
+        Ok(self.priority_fee_cap.map_or(adjusted_priority, |cap| adjusted_priority.min(cap)))
     }
     
     /// 估算总部署费用
@@ -87,24 +126,17 @@ impl FeeOptimizer {
         network_stats: &NetworkStats,
     ) -> Result<CostStats> {
         let base_fee = self.get_current_base_fee().await?;
-        let priority_fee = self.calculate_priority_fee(&network_stats.congestion_level).await?;
-        
-        // 根据加载器版本计算不同的费用结构
-        let (transaction_count, bytes_per_transaction) = match loader_version {
-            LoaderVersion::V3 => {
-                // Loader v3 需要更多交易来处理buffer
-                let bytes_per_tx = network_stats.optimal_chunk_size as u64;
-                let tx_count = (program_size + bytes_per_tx - 1) / bytes_per_tx;
-                (tx_count, bytes_per_tx)
-            }
-            LoaderVersion::V4 => {
-                // Loader v4 更高效
-                let bytes_per_tx = (network_stats.optimal_chunk_size * 2) as u64;
-                let tx_count = (program_size + bytes_per_tx - 1) / bytes_per_tx;
-                (tx_count, bytes_per_tx)
-            }
+        // 只有写入交易带 SetComputeUnitPrice 指令时才会支付优先费
+        let priority_fee = if self.write_layout.compute_budget {
+            self.calculate_priority_fee(&network_stats.congestion_level).await?
+        } else {
+            0
         };
         
+        // 与上传时相同的分块规划，交易数与实际写入交易数一致
+        let plan = ChunkPlan::new(loader_version.clone(), self.write_layout.clone());
+        let transaction_count = plan.transaction_count(program_size, self.max_chunk_size);
+        
         let total_base_fees = transaction_count * base_fee;
         let total_priority_fees = transaction_count * priority_fee;
         let total_fees = total_base_fees + total_priority_fees;
@@ -125,6 +157,7 @@ impl FeeOptimizer {
             saved_fees: 0,
             transaction_count: transaction_count as u32,
             retry_count: 0,
+            ..CostStats::default()
         })
     }
     
@@ -160,153 +193,409 @@ impl FeeOptimizer {
         Ok(savings)
     }
     
-    /// Buffer复用策略分析
-    pub fn analyze_buffer_reuse_opportunities(&mut self, program_hash: String) -> Vec<BufferInfo> {
-        self.buffer_registry
-            .get(&program_hash)
-            .cloned()
-            .unwrap_or_default()
-    }
-    
-    /// 注册可复用的Buffer
-    pub fn register_reusable_buffer(&mut self, program_hash: String, buffer: BufferInfo) {
-        self.buffer_registry
-            .entry(program_hash)
-            .or_insert_with(Vec::new)
-            .push(buffer);
-    }
-    
-    /// 清理过期的Buffer记录
-    pub fn cleanup_expired_buffers(&mut self, hours_to_keep: i64) -> usize {
-        let cutoff_time = chrono::Utc::now() - chrono::Duration::hours(hours_to_keep);
-        let mut removed_count = 0;
-        
-        for buffers in self.buffer_registry.values_mut() {
-            let initial_len = buffers.len();
-            buffers.retain(|buffer| buffer.created_at > cutoff_time);
-            removed_count += initial_len - buffers.len();
-        }
-        
-        removed_count
-    }
-    
-    /// 成本效益分析
+    /// 成本效益分析：按数据块账本比较续传与重新开始的费用。
+    /// 续传只发送账本中未确认的块，重新开始要新建上传账户并写入全部数据；
+    /// 每笔交易的费用按部署实际使用的写入交易结构向节点查询
     pub async fn cost_benefit_analysis(
         &mut self,
         deployment: &DeploymentState,
+        ledger: &[ChunkRecord],
+        chunk_size: usize,
         network_stats: &NetworkStats,
     ) -> Result<CostBenefitReport> {
-        let potential_savings = self.calculate_resume_savings(deployment, network_stats).await?;
-        let resume_cost = self.estimate_total_deployment_cost(
-            deployment.total_size.saturating_sub(deployment.uploaded_bytes),
-            &deployment.loader_version,
-            network_stats,
-        ).await?;
+        let plan = ChunkPlan::new(deployment.loader_version.clone(), WriteTransactionLayout::for_deployment(deployment));
+        let mut write = write_message(&deployment.loader_version, &WriteTransactionLayout::for_deployment(deployment), 0);
+        let fee_per_transaction = self.fee_for_message(&mut write).await?;
         
-        let time_cost_factor = match network_stats.congestion_level {
-            CongestionLevel::Low => 1.0,
-            CongestionLevel::Medium => 1.5,
-            CongestionLevel::High => 2.0,
-            CongestionLevel::Critical => 3.0,
+        let confirmed_bytes: u64 = if ledger.is_empty() {
+            deployment.uploaded_bytes
+        } else {
+            ledger
+                .iter()
+                .filter(|chunk| chunk.status == ChunkStatus::Confirmed)
+                .map(|chunk| chunk.size as u64)
+                .sum()
+        };
+        let remaining_bytes = deployment.total_size.saturating_sub(confirmed_bytes);
+        let resume_transactions = if ledger.is_empty() {
+            plan.transaction_count(remaining_bytes, chunk_size)
+        } else {
+            Self::ledger_transaction_count(&plan, ledger, chunk_size)
+        };
+        let restart_transactions = plan.transaction_count(deployment.total_size, chunk_size);
+        
+        // 重新开始还需要一笔创建上传账户的交易
+        let placeholder = Pubkey::new_unique();
+        let create_instructions = match deployment.loader_version {
+            LoaderVersion::V3 => bpf_loader_upgradeable::create_buffer(
+                &placeholder,
+                &Pubkey::new_unique(),
+                &placeholder,
+                0,
+                deployment.total_size as usize,
+            )?,
+            LoaderVersion::V4 => loader_v4::create_buffer(
+                &placeholder,
+                &Pubkey::new_unique(),
+                0,
+                &placeholder,
+                deployment.total_size as u32,
+                &placeholder,
+            ),
         };
+        let create_account_fee = self
+            .fee_for_message(&mut Message::new(&create_instructions, Some(&placeholder)))
+            .await?;
+        let upload_account_size = match deployment.loader_version {
+            LoaderVersion::V3 => UpgradeableLoaderState::size_of_buffer(deployment.total_size as usize),
+            LoaderVersion::V4 => LoaderV4State::program_data_offset() + deployment.total_size as usize,
+        };
+        let restart_rent_lamports = self
+            .rpc_pool
+            .call_blocking(move |client| client.get_minimum_balance_for_rent_exemption(upload_account_size))
+            .await?;
+        
+        let resume_cost = resume_transactions * fee_per_transaction;
+        let restart_cost = restart_transactions * fee_per_transaction + create_account_fee;
+        let potential_savings = restart_cost.saturating_sub(resume_cost);
         
-        let recommended_action = if potential_savings > resume_cost.estimated_remaining_fees / 2 {
-            "建议立即续传".to_string()
-        } else if network_stats.congestion_level == CongestionLevel::Critical {
-            "建议等待网络状况改善".to_string()
+        let mut recommended_action = if remaining_bytes == 0 {
+            "数据已全部写入，续传只需完成部署".to_string()
+        } else if potential_savings > 0 {
+            format!("建议续传，比重新开始少 {} 笔交易、{} lamports", restart_transactions.saturating_sub(resume_transactions), potential_savings)
         } else {
-            "建议评估时间成本后决定".to_string()
+            "续传与重新开始的费用相同".to_string()
         };
+        if network_stats.congestion_level == CongestionLevel::Critical {
+            recommended_action.push_str("；网络严重拥堵，建议等待网络状况改善");
+        }
         
         Ok(CostBenefitReport {
+            confirmed_bytes,
+            remaining_bytes,
+            fee_per_transaction,
+            resume_transactions,
+            resume_cost,
+            restart_transactions,
+            restart_cost,
+            restart_rent_lamports,
             potential_savings,
-            resume_cost: resume_cost.estimated_remaining_fees,
-            time_cost_factor,
             recommended_action,
-            break_even_point: potential_savings / 2,
         })
     }
     
-    /// 获取费用优化建议
-    pub fn get_optimization_recommendations(&self, deployment: &DeploymentState) -> Vec<OptimizationRecommendation> {
+    /// 续传时还需发送的写入交易数：发送过的块保持原样，从未发送的连续块会按块大小重新切分
+    fn ledger_transaction_count(plan: &ChunkPlan, ledger: &[ChunkRecord], chunk_size: usize) -> u64 {
+        let mut transactions = 0;
+        let mut fresh_bytes = 0u64;
+        for chunk in ledger.iter().filter(|chunk| chunk.status != ChunkStatus::Confirmed) {
+            if chunk.status == ChunkStatus::Pending && chunk.attempts == 0 {
+                fresh_bytes += chunk.size as u64;
+                continue;
+            }
+            transactions += plan.transaction_count(fresh_bytes, chunk_size) + 1;
+            fresh_bytes = 0;
+        }
+        transactions + plan.transaction_count(fresh_bytes, chunk_size)
+    }
+    
+    /// 查询部署记录中每个上传账户当前持有的租金，账户已关闭时为 None
+    pub async fn buffer_rent(&self, deployment: &DeploymentState) -> Result<Vec<BufferRent>> {
+        let pubkeys: Vec<Pubkey> = deployment.buffer_accounts.iter().map(|buffer| buffer.pubkey).collect();
+        let accounts = self
+            .rpc_pool
+            .call_blocking(move |client| client.get_multiple_accounts(&pubkeys))
+            .await?;
+        Ok(deployment
+            .buffer_accounts
+            .iter()
+            .zip(accounts)
+            .map(|(buffer, account)| BufferRent {
+                pubkey: buffer.pubkey,
+                status: buffer.status.clone(),
+                lamports: account.map(|account| account.lamports),
+            })
+            .collect())
+    }
+    
+    /// 获取费用优化建议：根据成本效益分析、数据块账本和学到的上传参数给出 ResumeConfig 的调整
+    pub fn get_optimization_recommendations(
+        &self,
+        deployment: &DeploymentState,
+        ledger: &[ChunkRecord],
+        report: &CostBenefitReport,
+        learned: Option<&UploadTuning>,
+        config: &ResumeConfig,
+    ) -> Vec<OptimizationRecommendation> {
         let mut recommendations = Vec::new();
+        let plan = ChunkPlan::new(deployment.loader_version.clone(), WriteTransactionLayout::for_deployment(deployment));
         
-        // 基于失败次数的建议
-        if deployment.failure_count > 2 {
+        // 手动设置的块大小小于单笔交易的上限时，交易数更多
+        if config.chunk_size != 0 && config.chunk_size < plan.max_payload {
+            let configured = plan.transaction_count(report.remaining_bytes, config.chunk_size);
+            let automatic = plan.transaction_count(report.remaining_bytes, 0);
             recommendations.push(OptimizationRecommendation {
-                category: "重试策略".to_string(),
-                description: "考虑增加重试延迟或减少并发数".to_string(),
-                priority: RecommendationPriority::High,
-                estimated_savings: 10000, // 估算节省的lamports
+                category: "块大小".to_string(),
+                description: format!(
+                    "块大小 {}B 小于单笔交易上限 {}B，剩余数据需要 {} 笔交易，自动计算只需 {} 笔",
+                    config.chunk_size, plan.max_payload, configured, automatic
+                ),
+                priority: RecommendationPriority::Medium,
+                estimated_savings: Some((configured - automatic) * report.fee_per_transaction),
+                config_change: Some(ConfigChange::new("chunk_size", config.chunk_size, 0)),
             });
         }
         
-        // 基于网络状况的建议
-        match deployment.network_stats.congestion_level {
-            CongestionLevel::Critical => {
+        // 当前节点上学到的并发数
+        if let Some(learned) = learned.filter(|learned| learned.rounds > 0) {
+            let parallelism = AimdController::new(plan.max_payload, config.parallel_uploads)
+                .with_learned(learned)
+                .parallelism();
+            if parallelism < config.parallel_uploads {
                 recommendations.push(OptimizationRecommendation {
-                    category: "时机选择".to_string(),
-                    description: "网络严重拥堵，建议延后部署".to_string(),
-                    priority: RecommendationPriority::High,
-                    estimated_savings: 50000,
+                    category: "并发数".to_string(),
+                    description: format!(
+                        "节点 {} 上 {} 轮上传后稳定在并发数 {}，更高的并发会导致交易未确认",
+                        learned.endpoint, learned.rounds, parallelism
+                    ),
+                    priority: RecommendationPriority::Low,
+                    estimated_savings: None,
+                    config_change: Some(ConfigChange::new("parallel_uploads", config.parallel_uploads, parallelism)),
                 });
             }
-            CongestionLevel::High => {
+        }
+        
+        // 用完重试次数仍未确认的块
+        let exhausted: Vec<&ChunkRecord> = ledger
+            .iter()
+            .filter(|chunk| chunk.status != ChunkStatus::Confirmed && chunk.attempts >= config.max_retries.max(1))
+            .collect();
+        if let Some(max_attempts) = exhausted.iter().map(|chunk| chunk.attempts).max() {
+            let suggested = max_attempts + config.max_retries.max(1);
+            recommendations.push(OptimizationRecommendation {
+                category: "重试策略".to_string(),
+                description: format!(
+                    "{} 个块已发送 {} 次仍未确认，上次上传因重试次数用完而停止",
+                    exhausted.len(),
+                    max_attempts
+                ),
+                priority: RecommendationPriority::High,
+                estimated_savings: None,
+                config_change: Some(ConfigChange::new("max_retries", config.max_retries, suggested)),
+            });
+        }
+        
+        // 续传后的总费用超过上限时会被拒绝
+        if let Some(max_total) = config.max_total_fee_lamports {
+            let projected = deployment.cost_stats.total_fees_paid + report.resume_cost;
+            if projected > max_total {
                 recommendations.push(OptimizationRecommendation {
-                    category: "策略调整".to_string(),
-                    description: "减少块大小和并发数".to_string(),
-                    priority: RecommendationPriority::Medium,
-                    estimated_savings: 20000,
+                    category: "费用上限".to_string(),
+                    description: format!(
+                        "已付 {} lamports，续传还需 {} lamports，超过上限 {} lamports",
+                        deployment.cost_stats.total_fees_paid, report.resume_cost, max_total
+                    ),
+                    priority: RecommendationPriority::High,
+                    estimated_savings: None,
+                    config_change: Some(ConfigChange::new("max_total_fee_lamports", max_total, projected)),
                 });
             }
-            _ => {}
         }
         
-        // 基于加载器版本的建议
-        if deployment.loader_version == LoaderVersion::V3 && deployment.total_size > 200 * 1024 {
+        // 续传比重新开始便宜
+        if report.potential_savings > 0 && !config.auto_resume {
             recommendations.push(OptimizationRecommendation {
-                category: "技术升级".to_string(),
-                description: "大程序建议使用Loader v4".to_string(),
-                priority: RecommendationPriority::Medium,
-                estimated_savings: 30000,
+                category: "自动续传".to_string(),
+                description: "失败后由守护进程自动续传，避免重新上传已确认的数据".to_string(),
+                priority: RecommendationPriority::Low,
+                estimated_savings: Some(report.potential_savings),
+                config_change: Some(ConfigChange::new("auto_resume", config.auto_resume, true)),
             });
         }
         
         recommendations
     }
     
-    /// 更新费用记录
+    /// 续传建议：成本效益分析、上传账户占用的租金和 ResumeConfig 调整建议。
+    /// `learned` 为当前RPC节点上学到的上传参数，续传时以其块大小为起点
+    pub async fn advise(
+        &mut self,
+        deployment: &DeploymentState,
+        ledger: &[ChunkRecord],
+        learned: Option<&UploadTuning>,
+        network_stats: &NetworkStats,
+        config: &ResumeConfig,
+    ) -> Result<DeploymentAdvice> {
+        let plan = ChunkPlan::new(deployment.loader_version.clone(), WriteTransactionLayout::for_deployment(deployment));
+        let controller = AimdController::new(plan.chunk_size(config.chunk_size), config.parallel_uploads);
+        let chunk_size = learned
+            .map(|learned| controller.clone().with_learned(learned).chunk_size())
+            .unwrap_or_else(|| controller.chunk_size());
+        
+        let buffers = self.buffer_rent(deployment).await?;
+        let mut cost = self.cost_benefit_analysis(deployment, ledger, chunk_size, network_stats).await?;
+        // 上传账户已不存在时续传会新建账户，已确认的数据不再有用
+        let target_missing = deployment.status != DeploymentStatus::Completed
+            && buffers
+                .last()
+                .filter(|buffer| buffer.lamports.is_some() && buffer.status != BufferStatus::Transferred)
+                .is_none();
+        if target_missing && deployment.total_size > 0 {
+            cost.confirmed_bytes = 0;
+            cost.remaining_bytes = deployment.total_size;
+            cost.resume_transactions = cost.restart_transactions;
+            cost.resume_cost = cost.restart_cost;
+            cost.potential_savings = 0;
+            cost.recommended_action = "上传账户已不存在，续传等同于重新开始".to_string();
+        }
+        let recommendations = self.get_optimization_recommendations(deployment, ledger, &cost, learned, config);
+        
+        Ok(DeploymentAdvice {
+            deployment_id: deployment.id,
+            status: deployment.status.clone(),
+            chunk_size,
+            // 已转交给其他部署的buffer的租金不再归本部署
+            rent_held_lamports: buffers
+                .iter()
+                .filter(|buffer| buffer.status != BufferStatus::Transferred)
+                .filter_map(|buffer| buffer.lamports)
+                .sum(),
+            buffers,
+            cost,
+            recommendations,
+        })
+    }
+    
+    /// 用部署实际支付的优先费更新最近一次的费用记录，只统计费用取自交易元数据的交易
     pub fn update_fee_record(&mut self, cost_stats: &CostStats) {
+        let Some(average) = cost_stats
+            .priority_fees_paid
+            .checked_div(cost_stats.audited_transactions as u64)
+        else {
+            return;
+        };
         if let Some(last_record) = self.fee_history.last_mut() {
-            last_record.priority_fee = cost_stats.total_fees_paid / cost_stats.transaction_count as u64;
+            last_record.priority_fee = average;
         }
     }
 }
 
-/// 成本效益报告
-#[derive(Debug, Clone)]
+/// 成本效益报告，所有费用单位为 lamports
+#[derive(Debug, Clone, Serialize)]
 pub struct CostBenefitReport {
-    pub potential_savings: u64,
+    /// 账本中已确认的字节数
+    pub confirmed_bytes: u64,
+    pub remaining_bytes: u64,
+    /// 按写入交易结构查询到的单笔交易费用
+    pub fee_per_transaction: u64,
+    pub resume_transactions: u64,
     pub resume_cost: u64,
-    pub time_cost_factor: f64,
+    pub restart_transactions: u64,
+    /// 写入全部数据的费用加上创建新上传账户的交易费用
+    pub restart_cost: u64,
+    /// 重新开始时新上传账户需要存入的租金，关闭旧账户可取回其租金
+    pub restart_rent_lamports: u64,
+    pub potential_savings: u64,
     pub recommended_action: String,
-    pub break_even_point: u64,
+}
+
+/// 上传账户当前持有的租金
+#[derive(Debug, Clone, Serialize)]
+pub struct BufferRent {
+    pub pubkey: Pubkey,
+    pub status: BufferStatus,
+    /// 账户已关闭时为 None
+    pub lamports: Option<u64>,
+}
+
+/// 部署的续传建议
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentAdvice {
+    pub deployment_id: Uuid,
+    pub status: DeploymentStatus,
+    /// 续传开始时使用的块大小
+    pub chunk_size: usize,
+    pub cost: CostBenefitReport,
+    pub buffers: Vec<BufferRent>,
+    /// 所有上传账户当前持有的租金之和
+    pub rent_held_lamports: u64,
+    pub recommendations: Vec<OptimizationRecommendation>,
 }
 
 /// 优化建议
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct OptimizationRecommendation {
     pub category: String,
     pub description: String,
     pub priority: RecommendationPriority,
-    pub estimated_savings: u64,
+    /// 能按当前费用算出时给出，单位 lamports
+    pub estimated_savings: Option<u64>,
+    pub config_change: Option<ConfigChange>,
+}
+
+/// 建议的 ResumeConfig 字段调整
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    pub field: String,
+    pub current: String,
+    pub suggested: String,
+}
+
+impl ConfigChange {
+    fn new(field: &str, current: impl ToString, suggested: impl ToString) -> Self {
+        Self {
+            field: field.to_string(),
+            current: current.to_string(),
+            suggested: suggested.to_string(),
+        }
+    }
 }
 
 /// 建议优先级
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum RecommendationPriority {
     Low,
     Medium,
     High,
     Critical,
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimizer_with_record() -> FeeOptimizer {
+        let mut optimizer = FeeOptimizer::new("http://127.0.0.1:8899".to_string());
+        optimizer.fee_history.push(FeeRecord {
+            timestamp: chrono::Utc::now(),
+            base_fee: 5_000,
+            priority_fee: 0,
+            congestion_multiplier: 1.0,
+        });
+        optimizer
+    }
+
+    #[test]
+    fn fee_record_uses_average_priority_fee_of_audited_transactions() {
+        let mut optimizer = optimizer_with_record();
+        let stats = CostStats {
+            priority_fees_paid: 9_000,
+            audited_transactions: 3,
+            transaction_count: 5,
+            ..CostStats::default()
+        };
+        optimizer.update_fee_record(&stats);
+        assert_eq!(optimizer.fee_history[0].priority_fee, 3_000);
+    }
+
+    #[test]
+    fn fee_record_is_unchanged_without_audited_transactions() {
+        let mut optimizer = optimizer_with_record();
+        let stats = CostStats {
+            transaction_count: 5,
+            ..CostStats::default()
+        };
+        optimizer.update_fee_record(&stats);
+        assert_eq!(optimizer.fee_history[0].priority_fee, 0);
+    }
+}
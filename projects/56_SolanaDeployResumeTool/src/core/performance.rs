@@ -3,6 +3,7 @@ use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Semaphore, RwLock};
+use crate::core::planner::ChunkPlan;
 use crate::core::types::*;
 
 /// 性能优化器
@@ -116,6 +117,8 @@ impl PerformanceOptimizer {
 /// 分块管理器
 pub struct ChunkManager {
     default_chunk_size: usize,
+    /// 单笔写入交易可携带的最大数据量，分块不会超过该值
+    max_chunk_size: Option<usize>,
     chunk_cache: VecDeque<Vec<u8>>,
     max_cache_size: usize,
 }
@@ -124,11 +127,19 @@ impl ChunkManager {
     pub fn new(default_chunk_size: usize) -> Self {
         Self {
             default_chunk_size,
+            max_chunk_size: None,
             chunk_cache: VecDeque::new(),
             max_cache_size: 50, // 最多缓存50个分块
         }
     }
 
+    /// 按分块规划创建：默认块大小和上限都是单笔写入交易可携带的最大数据量
+    pub fn for_plan(plan: &ChunkPlan) -> Self {
+        let mut chunk_manager = Self::new(plan.max_payload);
+        chunk_manager.max_chunk_size = Some(plan.max_payload);
+        chunk_manager
+    }
+
     /// 智能分块文件
     pub fn chunk_file(&mut self, data: &[u8], chunk_size: usize) -> Vec<Chunk> {
         let actual_chunk_size = if chunk_size == 0 {
@@ -136,6 +147,10 @@ impl ChunkManager {
         } else {
             chunk_size
         };
+        let actual_chunk_size = self
+            .max_chunk_size
+            .map_or(actual_chunk_size, |max| actual_chunk_size.min(max))
+            .max(1);
 
         let mut chunks = Vec::new();
         let mut offset = 0;
@@ -161,7 +176,7 @@ impl ChunkManager {
     }
 
     /// 计算校验和
-    fn calculate_checksum(&self, data: &[u8]) -> String {
+    pub fn calculate_checksum(&self, data: &[u8]) -> String {
         use sha2::{Sha256, Digest};
         let mut hasher = Sha256::new();
         hasher.update(data);
@@ -197,32 +212,50 @@ impl UploadScheduler {
     }
 }
 
-/// 带宽监控器
+/// 带宽监控器，样本来自真实的上传批次；克隆后共享同一组样本
+#[derive(Clone)]
 pub struct BandwidthMonitor {
-    samples: VecDeque<BandwidthSample>,
+    samples: Arc<std::sync::Mutex<VecDeque<BandwidthSample>>>,
     max_samples: usize,
 }
 
 impl BandwidthMonitor {
     pub fn new() -> Self {
         Self {
-            samples: VecDeque::new(),
+            samples: Arc::new(std::sync::Mutex::new(VecDeque::new())),
             max_samples: 100,
         }
     }
 
-    /// 获取当前带宽
-    pub async fn get_current_bandwidth(&self) -> Result<f64> {
-        if self.samples.is_empty() {
-            return Ok(0.0);
+    /// 记录一次传输：在 `duration` 内确认上链的字节数
+    pub fn record_transfer(&self, bytes_transferred: u64, duration: Duration) {
+        if bytes_transferred == 0 || duration.is_zero() {
+            return;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back(BandwidthSample {
+            timestamp: Instant::now(),
+            bytes_transferred,
+            duration,
+            bandwidth: bytes_transferred as f64 / duration.as_secs_f64(),
+        });
+        while samples.len() > self.max_samples {
+            samples.pop_front();
         }
+    }
 
-        // 计算最近10个样本的平均带宽
-        let recent_samples = self.samples.iter().rev().take(10);
-        let total_bandwidth: f64 = recent_samples.map(|s| s.bandwidth).sum();
-        let count = self.samples.len().min(10);
+    /// 最近10个样本的吞吐量（字节/秒），按字节数加权；没有样本时返回 None
+    pub fn current_bandwidth(&self) -> Option<f64> {
+        let samples = self.samples.lock().unwrap();
+        let recent: Vec<&BandwidthSample> = samples.iter().rev().take(10).collect();
+        let bytes: u64 = recent.iter().map(|s| s.bytes_transferred).sum();
+        let seconds: f64 = recent.iter().map(|s| s.duration.as_secs_f64()).sum();
+        (bytes > 0 && seconds > 0.0).then(|| bytes as f64 / seconds)
+    }
 
-        Ok(total_bandwidth / count as f64)
+    /// 获取当前带宽
+    pub async fn get_current_bandwidth(&self) -> Result<f64> {
+        Ok(self.current_bandwidth().unwrap_or(0.0))
     }
 }
 
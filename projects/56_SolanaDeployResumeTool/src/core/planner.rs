@@ -0,0 +1,283 @@
+use crate::core::types::*;
+use solana_sdk::{
+    bpf_loader_upgradeable,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    loader_v4,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    system_instruction,
+};
+
+/// 签名长度
+const SIGNATURE_SIZE: usize = 64;
+
+/// 写入交易的结构，决定除数据外还要占用多少字节
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteTransactionLayout {
+    /// 每笔交易以 AdvanceNonceAccount 开头
+    pub durable_nonce: bool,
+    /// 带 SetComputeUnitLimit 和 SetComputeUnitPrice 指令
+    pub compute_budget: bool,
+    /// 写入授权账户与付款账户不同，需要额外一个签名
+    pub separate_authority: bool,
+}
+
+impl WriteTransactionLayout {
+    /// 部署上传时实际使用的交易结构
+    pub fn for_deployment(deployment: &DeploymentState) -> Self {
+        Self {
+            durable_nonce: deployment.nonce_account.is_some(),
+            separate_authority: deployment
+                .keys
+                .as_ref()
+                .is_some_and(|keys| keys.buffer_authority != keys.fee_payer),
+            ..Self::default()
+        }
+    }
+}
+
+/// 某个加载器在给定交易结构下的分块规划
+#[derive(Debug, Clone)]
+pub struct ChunkPlan {
+    /// 单笔写入交易可携带的最大数据量
+    pub max_payload: usize,
+    /// 签名、账户、指令头等固定开销
+    pub overhead: usize,
+}
+
+impl ChunkPlan {
+    /// 按 1232 字节的包长计算单笔写入交易能携带的最大数据量
+    pub fn new(loader_version: LoaderVersion, layout: WriteTransactionLayout) -> Self {
+        let overhead = write_transaction_size(&loader_version, &layout, 0);
+        let mut max_payload = PACKET_DATA_SIZE.saturating_sub(overhead);
+        // 指令数据长度的compact-u16编码会随数据变长而多占字节
+        while max_payload > 0 && write_transaction_size(&loader_version, &layout, max_payload) > PACKET_DATA_SIZE {
+            max_payload -= 1;
+        }
+        Self { max_payload, overhead }
+    }
+
+    /// 实际使用的块大小：用户配置为0或超过上限时使用上限
+    pub fn chunk_size(&self, requested: usize) -> usize {
+        match requested {
+            0 => self.max_payload,
+            requested => requested.min(self.max_payload),
+        }
+    }
+
+    /// 按块大小上传 `program_size` 字节需要的写入交易数
+    pub fn transaction_count(&self, program_size: u64, requested_chunk_size: usize) -> u64 {
+        let chunk_size = self.chunk_size(requested_chunk_size).max(1) as u64;
+        program_size.div_ceil(chunk_size)
+    }
+}
+
+/// 新程序与链上程序按块比较的结果，块的边界与上传时的分块一致
+#[derive(Debug, Clone)]
+pub struct ProgramDelta {
+    pub chunk_size: usize,
+    /// 每个块是否与链上程序相同
+    pub unchanged: Vec<bool>,
+    pub unchanged_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl ProgramDelta {
+    /// 按 `chunk_size` 切分新程序，逐块与链上程序同一位置的数据比较
+    pub fn compute(current: &[u8], new: &[u8], chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let mut unchanged_bytes = 0u64;
+        let unchanged = new
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let start = index * chunk_size;
+                let same = current.get(start..start + chunk.len()) == Some(chunk);
+                if same {
+                    unchanged_bytes += chunk.len() as u64;
+                }
+                same
+            })
+            .collect();
+        Self {
+            chunk_size,
+            unchanged,
+            unchanged_bytes,
+            total_bytes: new.len() as u64,
+        }
+    }
+
+    /// 第 `index` 块是否与链上程序相同
+    pub fn is_unchanged(&self, index: usize) -> bool {
+        self.unchanged.get(index).copied().unwrap_or(false)
+    }
+
+    /// 需要写入的块数
+    pub fn changed_chunks(&self) -> usize {
+        self.unchanged.iter().filter(|same| !**same).count()
+    }
+
+    /// 与链上程序相同的字节占比
+    pub fn redundant_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            self.unchanged_bytes as f64 / self.total_bytes as f64
+        }
+    }
+}
+
+/// 携带 `payload_len` 字节数据的写入交易序列化后的大小
+pub fn write_transaction_size(loader_version: &LoaderVersion, layout: &WriteTransactionLayout, payload_len: usize) -> usize {
+    let message = write_message(loader_version, layout, payload_len);
+    let signatures = message.header.num_required_signatures as usize;
+    // 签名数量(compact-u16) + 签名 + 消息
+    compact_u16_len(signatures) + signatures * SIGNATURE_SIZE + message.serialize().len()
+}
+
+/// 与真实写入交易结构相同的消息，账户为占位公钥，可用于计算长度或查询费用
+pub fn write_message(loader_version: &LoaderVersion, layout: &WriteTransactionLayout, payload_len: usize) -> Message {
+    let payer = Pubkey::new_unique();
+    let authority = if layout.separate_authority {
+        Pubkey::new_unique()
+    } else {
+        payer
+    };
+    let target = Pubkey::new_unique();
+
+    let mut instructions: Vec<Instruction> = Vec::with_capacity(4);
+    if layout.durable_nonce {
+        instructions.push(system_instruction::advance_nonce_account(&Pubkey::new_unique(), &payer));
+    }
+    if layout.compute_budget {
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(u32::MAX));
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(u64::MAX));
+    }
+    let bytes = vec![0u8; payload_len];
+    instructions.push(match loader_version {
+        LoaderVersion::V3 => bpf_loader_upgradeable::write(&target, &authority, 0, bytes),
+        LoaderVersion::V4 => loader_v4::write(&target, &authority, 0, bytes),
+    });
+
+    Message::new(&instructions, Some(&payer))
+}
+
+/// compact-u16 编码长度
+fn compact_u16_len(value: usize) -> usize {
+    match value {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layouts() -> Vec<WriteTransactionLayout> {
+        let mut layouts = Vec::new();
+        for durable_nonce in [false, true] {
+            for compute_budget in [false, true] {
+                for separate_authority in [false, true] {
+                    layouts.push(WriteTransactionLayout {
+                        durable_nonce,
+                        compute_budget,
+                        separate_authority,
+                    });
+                }
+            }
+        }
+        layouts
+    }
+
+    #[test]
+    fn max_payload_fills_packet_exactly() {
+        for loader_version in [LoaderVersion::V3, LoaderVersion::V4] {
+            for layout in layouts() {
+                let plan = ChunkPlan::new(loader_version.clone(), layout.clone());
+                assert!(plan.max_payload > 0);
+                assert!(write_transaction_size(&loader_version, &layout, plan.max_payload) <= PACKET_DATA_SIZE);
+                assert!(write_transaction_size(&loader_version, &layout, plan.max_payload + 1) > PACKET_DATA_SIZE);
+            }
+        }
+    }
+
+    #[test]
+    fn extra_instructions_and_signers_reduce_payload() {
+        let base = ChunkPlan::new(LoaderVersion::V3, WriteTransactionLayout::default()).max_payload;
+        for layout in layouts().into_iter().skip(1) {
+            assert!(ChunkPlan::new(LoaderVersion::V3, layout).max_payload < base);
+        }
+    }
+
+    #[test]
+    fn overhead_is_size_of_empty_write() {
+        let layout = WriteTransactionLayout::default();
+        let plan = ChunkPlan::new(LoaderVersion::V4, layout.clone());
+        assert_eq!(plan.overhead, write_transaction_size(&LoaderVersion::V4, &layout, 0));
+    }
+
+    #[test]
+    fn requested_chunk_size_is_capped() {
+        let plan = ChunkPlan::new(LoaderVersion::V3, WriteTransactionLayout::default());
+        assert_eq!(plan.chunk_size(0), plan.max_payload);
+        assert_eq!(plan.chunk_size(100), 100);
+        assert_eq!(plan.chunk_size(usize::MAX), plan.max_payload);
+    }
+
+    #[test]
+    fn transaction_count_rounds_up() {
+        let plan = ChunkPlan::new(LoaderVersion::V3, WriteTransactionLayout::default());
+        assert_eq!(plan.transaction_count(0, 100), 0);
+        assert_eq!(plan.transaction_count(100, 100), 1);
+        assert_eq!(plan.transaction_count(101, 100), 2);
+        let max = plan.max_payload as u64;
+        assert_eq!(plan.transaction_count(max * 3, 0), 3);
+    }
+
+    #[test]
+    fn delta_marks_identical_chunks_unchanged() {
+        let current = vec![1u8; 10];
+        let mut new = current.clone();
+        new[4] = 9;
+        let delta = ProgramDelta::compute(&current, &new, 4);
+        assert_eq!(delta.unchanged, vec![true, false, true]);
+        assert_eq!(delta.changed_chunks(), 1);
+        assert_eq!(delta.unchanged_bytes, 6);
+        assert_eq!(delta.total_bytes, 10);
+        assert!((delta.redundant_ratio() - 0.6).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn delta_treats_bytes_beyond_current_program_as_changed() {
+        let current = vec![1u8; 6];
+        let new = vec![1u8; 10];
+        let delta = ProgramDelta::compute(&current, &new, 4);
+        // 第二块只有一半落在链上程序范围内
+        assert_eq!(delta.unchanged, vec![true, false, false]);
+        assert!(!delta.is_unchanged(3));
+    }
+
+    #[test]
+    fn delta_handles_shrinking_program_and_empty_input() {
+        let delta = ProgramDelta::compute(&[1, 2, 3, 4, 5, 6], &[1, 2, 9], 2);
+        assert_eq!(delta.unchanged, vec![true, false]);
+        assert_eq!(delta.unchanged_bytes, 2);
+
+        let empty = ProgramDelta::compute(&[1, 2], &[], 0);
+        assert_eq!(empty.chunk_size, 1);
+        assert_eq!(empty.changed_chunks(), 0);
+        assert_eq!(empty.redundant_ratio(), 0.0);
+    }
+
+    #[test]
+    fn compact_u16_length_boundaries() {
+        assert_eq!(compact_u16_len(0x7f), 1);
+        assert_eq!(compact_u16_len(0x80), 2);
+        assert_eq!(compact_u16_len(0x3fff), 2);
+        assert_eq!(compact_u16_len(0x4000), 3);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::core::types::*;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 共享的RPC限速器：令牌桶限制每秒请求数，重试预算限制窗口内重试次数占成功调用的比例。
+/// 克隆后共享同一份状态，所有RPC调用方使用同一个实例
+#[derive(Clone)]
+pub struct RpcLimiter {
+    config: RateLimitConfig,
+    state: Arc<Mutex<LimiterState>>,
+}
+
+struct LimiterState {
+    tokens: f64,
+    last_refill: Instant,
+    /// 窗口内成功调用和重试的时间点
+    successes: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+    total_requests: u64,
+    throttled_requests: u64,
+    total_wait: Duration,
+    total_retries: u64,
+    denied_retries: u64,
+}
+
+/// 限速器使用情况
+#[derive(Debug, Clone, Serialize)]
+pub struct LimiterUsage {
+    pub requests_per_second: f64,
+    pub burst: u32,
+    pub total_requests: u64,
+    pub throttled_requests: u64,
+    pub total_wait_ms: u64,
+    pub retry_budget_ratio: f64,
+    pub retry_budget_window_secs: u64,
+    pub window_successes: usize,
+    pub window_retries: usize,
+    pub total_retries: u64,
+    pub denied_retries: u64,
+}
+
+impl RpcLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(LimiterState {
+                tokens: config.burst.max(1) as f64,
+                last_refill: Instant::now(),
+                successes: VecDeque::new(),
+                retries: VecDeque::new(),
+                total_requests: 0,
+                throttled_requests: 0,
+                total_wait: Duration::ZERO,
+                total_retries: 0,
+                denied_retries: 0,
+            })),
+            config,
+        }
+    }
+
+    /// 换用新的配置，令牌桶和重试窗口的状态保持共享
+    pub fn with_config(mut self, config: RateLimitConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 预留一个令牌，返回发出请求前需要等待的时间。
+    /// 令牌不足时透支，后到的请求会排在已预留的请求之后
+    fn reserve(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        state.total_requests += 1;
+        let rate = self.config.requests_per_second;
+        if rate <= 0.0 {
+            return Duration::ZERO;
+        }
+
+        let now = Instant::now();
+        let refill = now.duration_since(state.last_refill).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refill).min(self.config.burst.max(1) as f64);
+        state.last_refill = now;
+        state.tokens -= 1.0;
+        if state.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+
+        let wait = Duration::from_secs_f64(-state.tokens / rate);
+        state.throttled_requests += 1;
+        state.total_wait += wait;
+        wait
+    }
+
+    /// 阻塞等待直到允许发出一个请求，供同步RPC调用使用
+    pub fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// 异步等待直到允许发出一个请求
+    pub async fn acquire_async(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// 记录一次成功调用，用于计算重试预算
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.successes.push_back(now);
+        self.prune(&mut state, now);
+    }
+
+    /// 申请一次重试：窗口内重试次数超过成功次数的 `retry_budget_ratio`（且超过保底次数）时拒绝
+    pub fn try_retry(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        self.prune(&mut state, now);
+
+        let budget = (state.successes.len() as f64 * self.config.retry_budget_ratio)
+            .max(self.config.min_retry_budget as f64);
+        if state.retries.len() as f64 >= budget {
+            state.denied_retries += 1;
+            return false;
+        }
+        state.retries.push_back(now);
+        state.total_retries += 1;
+        true
+    }
+
+    fn prune(&self, state: &mut LimiterState, now: Instant) {
+        let window = Duration::from_secs(self.config.retry_budget_window_secs.max(1));
+        while matches!(state.successes.front(), Some(at) if now.duration_since(*at) > window) {
+            state.successes.pop_front();
+        }
+        while matches!(state.retries.front(), Some(at) if now.duration_since(*at) > window) {
+            state.retries.pop_front();
+        }
+    }
+
+    /// 当前使用情况
+    pub fn usage(&self) -> LimiterUsage {
+        let mut state = self.state.lock().unwrap();
+        self.prune(&mut state, Instant::now());
+        LimiterUsage {
+            requests_per_second: self.config.requests_per_second,
+            burst: self.config.burst,
+            total_requests: state.total_requests,
+            throttled_requests: state.throttled_requests,
+            total_wait_ms: state.total_wait.as_millis() as u64,
+            retry_budget_ratio: self.config.retry_budget_ratio,
+            retry_budget_window_secs: self.config.retry_budget_window_secs,
+            window_successes: state.successes.len(),
+            window_retries: state.retries.len(),
+            total_retries: state.total_retries,
+            denied_retries: state.denied_retries,
+        }
+    }
+}
@@ -1,34 +1,143 @@
+use crate::core::blockhash::{BlockhashManager, TransactionLifetime};
+use crate::core::performance::{BandwidthMonitor, ChunkManager};
+use crate::core::planner::{ChunkPlan, ProgramDelta, WriteTransactionLayout};
+use crate::core::retry::{self, AdaptiveTimeout, CircuitBreaker, CircuitState, RetryHandler};
+use crate::core::rpc_pool::RpcPool;
+use crate::core::sender::{ConfirmationStatus, SenderConfig, TransactionSender};
+use crate::core::signers::DeploymentSigners;
+use crate::core::state::{StateManager, DEFAULT_LEASE_TTL_SECS};
+use crate::core::tuning::{AimdController, TuningAction};
 use crate::core::types::*;
 use anyhow::{anyhow, Result};
-// use chrono::Utc;
-use solana_client::rpc_client::RpcClient;
+use chrono::Utc;
+use solana_client::rpc_config::RpcTransactionConfig;
 use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::DEFAULT_MS_PER_SLOT,
     commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::Instruction,
+    loader_v4::{self, LoaderV4State, LoaderV4Status},
+    message::Message,
+    nonce::State as NonceState,
     pubkey::Pubkey,
-    signature::Keypair,
-    // transaction::Transaction,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
 };
 use std::fs;
+use std::future::Future;
 use std::path::Path;
-use std::time::Duration;
-// use uuid::Uuid;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 上传路径断路器的连续失败阈值
+const UPLOAD_BREAKER_THRESHOLD: u32 = 5;
+
+/// 上传路径断路器开启后的冷却时间
+const UPLOAD_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// 等待 Loader v4 冷却期时每次最多休眠的slot数
+const COOLDOWN_POLL_SLOTS: u64 = 50;
 
 /// 续传引擎
 pub struct ResumeEngine {
-    rpc_client: RpcClient,
+    rpc_pool: RpcPool,
+    sender: TransactionSender,
     commitment: CommitmentConfig,
+    circuit_breaker: Mutex<CircuitBreaker>,
+    adaptive_timeout: Mutex<AdaptiveTimeout>,
+    /// 尚未计入 CostStats 的RPC重试次数
+    rpc_retries: AtomicU32,
+    bandwidth_monitor: BandwidthMonitor,
+}
+
+/// 待上传的数据块
+struct PendingChunk {
+    record: ChunkRecord,
+    data: Vec<u8>,
+}
+
+/// 上传过程中的持久化上下文：数据块账本、进度、费用以及部署租约
+pub struct UploadSession<'a> {
+    pub state_manager: &'a mut StateManager,
+    pub lease: DeploymentLease,
+}
+
+impl<'a> UploadSession<'a> {
+    pub fn new(state_manager: &'a mut StateManager, lease: DeploymentLease) -> Self {
+        Self { state_manager, lease }
+    }
+    
+    /// 记录一批交易确认后的进度、费用和实测吞吐量，并续期租约
+    fn record_progress(
+        &mut self,
+        id: &Uuid,
+        confirmed_bytes: u64,
+        round_cost: &CostStats,
+        throughput_bps: Option<f64>,
+    ) -> Result<()> {
+        if let Some(mut deployment) = self.state_manager.get_deployment(id).cloned() {
+            if let Some(throughput_bps) = throughput_bps {
+                deployment.network_stats.throughput_bps = throughput_bps;
+            }
+            deployment.uploaded_bytes = confirmed_bytes.min(deployment.total_size);
+            if let Some(buffer) = deployment.buffer_accounts.last_mut() {
+                buffer.uploaded_size = deployment.uploaded_bytes;
+            }
+            deployment.cost_stats.accumulate(round_cost);
+            self.state_manager.update_deployment(deployment)?;
+        }
+        self.lease = self.state_manager.renew_lease(&self.lease, DEFAULT_LEASE_TTL_SECS)?;
+        Ok(())
+    }
 }
 
 impl ResumeEngine {
     /// 创建新的续传引擎
     pub fn new(rpc_url: String) -> Self {
-        let rpc_client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+        Self::with_pool(RpcPool::single(rpc_url))
+    }
+    
+    /// 使用RPC池创建续传引擎，节点故障时自动切换
+    pub fn with_pool(rpc_pool: RpcPool) -> Self {
+        let sender = TransactionSender::new(rpc_pool.clone(), SenderConfig::default());
+        Self::with_sender(rpc_pool, sender)
+    }
+    
+    /// 使用指定的交易发送器创建续传引擎
+    pub fn with_sender(rpc_pool: RpcPool, sender: TransactionSender) -> Self {
         Self {
-            rpc_client,
+            rpc_pool,
+            sender,
             commitment: CommitmentConfig::confirmed(),
+            circuit_breaker: Mutex::new(CircuitBreaker::new(UPLOAD_BREAKER_THRESHOLD, UPLOAD_BREAKER_COOLDOWN)),
+            adaptive_timeout: Mutex::new(AdaptiveTimeout::new()),
+            rpc_retries: AtomicU32::new(0),
+            bandwidth_monitor: BandwidthMonitor::new(),
         }
     }
     
+    /// 使用共享的带宽监控器，上传批次的实测吞吐量会写入其中
+    pub fn with_bandwidth_monitor(mut self, bandwidth_monitor: BandwidthMonitor) -> Self {
+        self.bandwidth_monitor = bandwidth_monitor;
+        self
+    }
+    
+    /// 引擎使用的RPC池
+    pub fn rpc_pool(&self) -> &RpcPool {
+        &self.rpc_pool
+    }
+    
+    /// 签名使用的区块哈希管理器
+    pub fn blockhash_manager(&self) -> &BlockhashManager {
+        self.sender.blockhash_manager()
+    }
+    
     /// 检测程序文件的加载器版本
     pub fn detect_loader_version(&self, program_path: &Path) -> Result<LoaderVersion> {
         // 这里简化处理，实际需要分析.so文件格式
@@ -88,7 +197,10 @@ impl ResumeEngine {
     
     /// 检查buffer账户状态
     fn check_buffer_status(&self, buffer_pubkey: &Pubkey) -> Result<u64> {
-        match self.rpc_client.get_account_with_commitment(buffer_pubkey, self.commitment) {
+        match self
+            .rpc_pool
+            .call(|client| client.get_account_with_commitment(buffer_pubkey, self.commitment))
+        {
             Ok(response) => {
                 if let Some(account) = response.value {
                     Ok(account.data.len() as u64)
@@ -100,9 +212,148 @@ impl ResumeEngine {
         }
     }
     
+    /// 找出链上已不存在的buffer账户。只有节点确认账户不存在才算缺失，
+    /// 超时、限流等RPC错误直接返回，避免把仍然有效的buffer当成丢失
+    pub async fn find_missing_buffers(&self, deployment: &DeploymentState) -> Result<Vec<Pubkey>> {
+        let mut missing = Vec::new();
+        for buffer in &deployment.buffer_accounts {
+            let (pubkey, commitment) = (buffer.pubkey, self.commitment);
+            let response = self
+                .rpc_pool
+                .call_blocking(move |client| client.get_account_with_commitment(&pubkey, commitment))
+                .await
+                .map_err(|e| anyhow!("获取Buffer账户 {} 失败: {}", buffer.pubkey, e))?;
+            if response.value.is_none() {
+                missing.push(buffer.pubkey);
+            }
+        }
+        Ok(missing)
+    }
+    
+    /// 检查登记的buffer能否直接用于部署：仍归可升级加载器所有、写入授权为 `authority`，
+    /// 且数据与程序逐字节相同
+    pub async fn verify_reusable_buffer(&self, buffer: &Pubkey, program_data: &[u8], authority: &Pubkey) -> Result<bool> {
+        let (buffer, commitment) = (*buffer, self.commitment);
+        let response = self
+            .rpc_pool
+            .call_blocking(move |client| client.get_account_with_commitment(&buffer, commitment))
+            .await?;
+        let Some(account) = response.value else {
+            return Ok(false);
+        };
+        if account.owner != bpf_loader_upgradeable::id() {
+            return Ok(false);
+        }
+        match account.deserialize_data::<UpgradeableLoaderState>() {
+            Ok(UpgradeableLoaderState::Buffer { authority_address }) if authority_address == Some(*authority) => {}
+            _ => return Ok(false),
+        }
+        let data_offset = UpgradeableLoaderState::size_of_buffer_metadata();
+        Ok(account.data.get(data_offset..) == Some(program_data))
+    }
+
+    /// 读取待升级程序当前的链上数据，并确认 `authority` 有权升级它
+    pub async fn fetch_upgradeable_program(
+        &self,
+        loader_version: &LoaderVersion,
+        program_id: &Pubkey,
+        authority: &Pubkey,
+    ) -> Result<Vec<u8>> {
+        match loader_version {
+            LoaderVersion::V3 => {
+                let programdata = self.fetch_programdata(program_id).await?;
+                match programdata.deserialize_data::<UpgradeableLoaderState>() {
+                    Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address: None, .. }) => {
+                        return Err(anyhow!("程序 {} 已不可升级", program_id));
+                    }
+                    Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address: Some(current), .. }) => {
+                        if current != *authority {
+                            return Err(anyhow!("程序 {} 的升级授权为 {}，不是 {}", program_id, current, authority));
+                        }
+                    }
+                    _ => return Err(anyhow!("程序 {} 的ProgramData账户无效", program_id)),
+                }
+                let data_offset = UpgradeableLoaderState::size_of_programdata_metadata();
+                Ok(programdata.data.get(data_offset..).unwrap_or_default().to_vec())
+            }
+            LoaderVersion::V4 => {
+                let account = self.fetch_account(program_id).await?;
+                let state = (account.owner == loader_v4::id())
+                    .then(|| loader_v4_state(&account.data))
+                    .flatten()
+                    .ok_or_else(|| anyhow!("{} 不是 Loader v4 程序", program_id))?;
+                if state.status == LoaderV4Status::Finalized {
+                    return Err(anyhow!("程序 {} 已不可升级", program_id));
+                }
+                if state.authority_address != *authority {
+                    return Err(anyhow!(
+                        "程序 {} 的授权为 {}，不是 {}",
+                        program_id,
+                        state.authority_address,
+                        authority
+                    ));
+                }
+                Ok(account.data[LoaderV4State::program_data_offset()..].to_vec())
+            }
+        }
+    }
+    
+    /// 可升级加载器程序对应的ProgramData账户
+    async fn fetch_programdata(&self, program_id: &Pubkey) -> Result<Account> {
+        match self.fetch_account(program_id).await?.deserialize_data::<UpgradeableLoaderState>() {
+            Ok(UpgradeableLoaderState::Program { programdata_address }) => self.fetch_account(&programdata_address).await,
+            _ => Err(anyhow!("{} 不是可升级加载器的程序", program_id)),
+        }
+    }
+    
+    async fn fetch_account(&self, pubkey: &Pubkey) -> Result<Account> {
+        let (pubkey, commitment) = (*pubkey, self.commitment);
+        self.rpc_pool
+            .call_blocking(move |client| client.get_account_with_commitment(&pubkey, commitment))
+            .await?
+            .value
+            .ok_or_else(|| anyhow!("账户不存在: {}", pubkey))
+    }
+    
+    /// 指定大小的账户免租所需的最低余额
+    async fn rent_exemption(&self, size: usize) -> Result<u64> {
+        self.rpc_pool
+            .call_blocking(move |client| client.get_minimum_balance_for_rent_exemption(size))
+            .await
+    }
+    
+    /// 增量升级的初始账本：与链上程序相同的块直接记为已确认，只有变化的块需要写入
+    pub fn delta_ledger(deployment: &DeploymentState, program_data: &[u8], delta: &ProgramDelta) -> Vec<ChunkRecord> {
+        let mut chunk_manager = ChunkManager::for_plan(&Self::chunk_plan(deployment));
+        chunk_manager
+            .chunk_file(program_data, delta.chunk_size)
+            .into_iter()
+            .map(|chunk| ChunkRecord {
+                index: chunk.id as u32,
+                offset: chunk.offset,
+                size: chunk.size as u32,
+                checksum: chunk.checksum,
+                status: if delta.is_unchanged(chunk.id) {
+                    ChunkStatus::Confirmed
+                } else {
+                    ChunkStatus::Pending
+                },
+                signature: None,
+                last_valid_block_height: None,
+                attempts: 0,
+                updated_at: Utc::now(),
+                fee: None,
+                savings_counted: false,
+            })
+            .collect()
+    }
+    
     /// 获取程序数据长度
     fn get_program_data_length(&self, program_id: &Pubkey) -> Result<u64> {
-        match self.rpc_client.get_account_with_commitment(program_id, self.commitment) {
+        match self
+            .rpc_pool
+            .call(|client| client.get_account_with_commitment(program_id, self.commitment))
+        {
             Ok(response) => {
                 if let Some(account) = response.value {
                     Ok(account.data.len() as u64)
@@ -114,61 +365,341 @@ impl ResumeEngine {
         }
     }
     
-    /// 执行续传部署
+    /// 创建上传目标账户（v3 为buffer账户，v4 为程序账户）；已存在时不做任何事。
+    /// 返回是否新建了账户，新建时旧的数据块账本不再有效
+    pub async fn prepare_deployment(
+        &self,
+        deployment: &mut DeploymentState,
+        program_len: usize,
+        signers: &DeploymentSigners,
+    ) -> Result<bool> {
+        if self.upload_target(deployment).is_ok() {
+            return Ok(false);
+        }
+        if let (LoaderVersion::V4, Some(upgrade)) = (&deployment.loader_version, &deployment.upgrade) {
+            let program_id = upgrade.program_id;
+            self.prepare_v4_upgrade(deployment, &program_id, program_len, signers).await?;
+            return Ok(true);
+        }
+
+        let payer = signers.fee_payer();
+        let authority = signers.buffer_authority();
+        // v4 的上传账户就是程序账户，指定了程序密钥对时使用它的地址
+        let generated = Keypair::new();
+        let account = match deployment.loader_version {
+            LoaderVersion::V4 => signers.program().unwrap_or(&generated),
+            LoaderVersion::V3 => &generated,
+        };
+        match deployment.loader_version {
+            LoaderVersion::V3 => {
+                let lamports = self
+                    .rent_exemption(UpgradeableLoaderState::size_of_buffer(program_len))
+                    .await?;
+                let instructions = bpf_loader_upgradeable::create_buffer(
+                    &payer.pubkey(),
+                    &account.pubkey(),
+                    &authority.pubkey(),
+                    lamports,
+                    program_len,
+                )?;
+                self.send_recorded(deployment, &instructions, payer, &[account], lamports).await?;
+            }
+            LoaderVersion::V4 => {
+                let lamports = self
+                    .rent_exemption(LoaderV4State::program_data_offset() + program_len)
+                    .await?;
+                let instructions = loader_v4::create_buffer(
+                    &payer.pubkey(),
+                    &account.pubkey(),
+                    lamports,
+                    &authority.pubkey(),
+                    program_len as u32,
+                    &payer.pubkey(),
+                );
+                self.send_recorded(deployment, &instructions, payer, &[account, authority], lamports)
+                    .await?;
+                deployment.program_id = Some(account.pubkey());
+            }
+        }
+
+        deployment.buffer_accounts.push(BufferInfo {
+            pubkey: account.pubkey(),
+            size: program_len as u64,
+            uploaded_size: 0,
+            offset: 0,
+            status: BufferStatus::Uploading,
+            created_at: Utc::now(),
+            authority: Some(authority.pubkey()),
+        });
+        deployment.uploaded_bytes = 0;
+        Ok(true)
+    }
+    
+    /// Loader v4 原地升级：撤回程序使其可写，再把账户调整为新程序的大小。
+    /// 撤回期间程序不可调用；原有数据保留在账户中，增量模式下相同的块无需重写
+    async fn prepare_v4_upgrade(
+        &self,
+        deployment: &mut DeploymentState,
+        program_id: &Pubkey,
+        program_len: usize,
+        signers: &DeploymentSigners,
+    ) -> Result<()> {
+        let payer = signers.fee_payer();
+        let authority = signers.upgrade_authority();
+        let account = self.fetch_account(program_id).await?;
+        let state = loader_v4_state(&account.data).ok_or_else(|| anyhow!("{} 不是 Loader v4 程序", program_id))?;
+        let mut instructions = Vec::new();
+        match state.status {
+            LoaderV4Status::Finalized => return Err(anyhow!("程序 {} 已不可升级", program_id)),
+            LoaderV4Status::Deployed => {
+                self.wait_for_v4_cooldown(program_id).await?;
+                println!("⚠️  撤回程序 {}，升级完成前程序不可调用", program_id);
+                instructions.push(loader_v4::retract(program_id, &authority.pubkey()));
+            }
+            LoaderV4Status::Retracted => {}
+        }
+        let required = self
+            .rent_exemption(LoaderV4State::program_data_offset() + program_len)
+            .await?;
+        let top_up = required.saturating_sub(account.lamports);
+        if top_up > 0 {
+            instructions.push(system_instruction::transfer(&payer.pubkey(), program_id, top_up));
+        }
+        instructions.push(loader_v4::truncate(program_id, &authority.pubkey(), program_len as u32, &payer.pubkey()));
+        self.send_recorded(deployment, &instructions, payer, &[authority], top_up).await?;
+
+        deployment.program_id = Some(*program_id);
+        deployment.buffer_accounts.push(BufferInfo {
+            pubkey: *program_id,
+            size: program_len as u64,
+            uploaded_size: 0,
+            offset: 0,
+            status: BufferStatus::Uploading,
+            created_at: Utc::now(),
+            authority: Some(authority.pubkey()),
+        });
+        deployment.uploaded_bytes = 0;
+        Ok(())
+    }
+    
+    /// Loader v4 的程序在上次部署或撤回后的冷却期内不能再次部署或撤回，等待冷却期结束
+    async fn wait_for_v4_cooldown(&self, program_id: &Pubkey) -> Result<()> {
+        loop {
+            let account = self.fetch_account(program_id).await?;
+            let state = loader_v4_state(&account.data).ok_or_else(|| anyhow!("{} 不是 Loader v4 程序", program_id))?;
+            let commitment = self.commitment;
+            let current_slot = self
+                .rpc_pool
+                .call_blocking(move |client| client.get_slot_with_commitment(commitment))
+                .await?;
+            let ready_slot = state.slot.saturating_add(loader_v4::DEPLOYMENT_COOLDOWN_IN_SLOTS);
+            if current_slot >= ready_slot {
+                return Ok(());
+            }
+            let remaining = ready_slot - current_slot;
+            println!("⏳ Loader v4 冷却期内，还需等待 {} 个slot", remaining);
+            tokio::time::sleep(Duration::from_millis(remaining.min(COOLDOWN_POLL_SLOTS) * DEFAULT_MS_PER_SLOT)).await;
+        }
+    }
+    
+    /// 写入指令的目标账户
+    fn upload_target(&self, deployment: &DeploymentState) -> Result<Pubkey> {
+        let buffer = deployment
+            .buffer_accounts
+            .last()
+            .filter(|buffer| !buffer.status.is_abandoned())
+            .ok_or_else(|| anyhow!("部署缺少可用的上传账户"))?;
+        match deployment.loader_version {
+            LoaderVersion::V3 => Ok(buffer.pubkey),
+            LoaderVersion::V4 => deployment
+                .program_id
+                .filter(|program_id| *program_id == buffer.pubkey)
+                .ok_or_else(|| anyhow!("部署缺少程序账户")),
+        }
+    }
+    
+    /// 按账本规划数据块：账本与程序数据吻合时沿用原有分块，否则按块大小重新分块
+    fn plan_chunks(plan: &ChunkPlan, program_data: &[u8], ledger: Vec<ChunkRecord>, chunk_size: usize) -> Vec<PendingChunk> {
+        let chunk_manager = ChunkManager::for_plan(plan);
+        let mut expected_offset = 0u64;
+        let ledger_usable = !ledger.is_empty()
+            && ledger.iter().enumerate().all(|(i, record)| {
+                let contiguous = record.index as usize == i && record.offset == expected_offset;
+                expected_offset += record.size as u64;
+                contiguous
+            })
+            && expected_offset == program_data.len() as u64;
+
+        if ledger_usable {
+            return ledger
+                .into_iter()
+                .map(|mut record| {
+                    let start = record.offset as usize;
+                    let data = program_data[start..start + record.size as usize].to_vec();
+                    let checksum = chunk_manager.calculate_checksum(&data);
+                    if checksum != record.checksum {
+                        record.checksum = checksum;
+                        record.status = ChunkStatus::Pending;
+                        record.savings_counted = false;
+                    }
+                    PendingChunk { record, data }
+                })
+                .collect();
+        }
+
+        let mut chunk_manager = chunk_manager;
+        chunk_manager
+            .chunk_file(program_data, chunk_size)
+            .into_iter()
+            .map(|chunk| PendingChunk {
+                record: ChunkRecord {
+                    index: chunk.id as u32,
+                    offset: chunk.offset,
+                    size: chunk.size as u32,
+                    checksum: chunk.checksum,
+                    status: ChunkStatus::Pending,
+                    signature: None,
+                    last_valid_block_height: None,
+                    attempts: 0,
+                    updated_at: Utc::now(),
+                    fee: None,
+                    savings_counted: false,
+                },
+                data: chunk.data,
+            })
+            .collect()
+    }
+    
+    /// 按新的块大小重新切分 `from` 之后从未发送过的块，发送过的块保持原样，然后重新编号。
+    /// 返回块是否有变化
+    fn rechunk_remaining(plan: &ChunkPlan, chunks: &mut Vec<PendingChunk>, from: usize, chunk_size: usize) -> bool {
+        let tail = chunks.split_off(from);
+        let mut changed = false;
+        let mut run: Vec<PendingChunk> = Vec::new();
+        for chunk in tail {
+            if chunk.record.status == ChunkStatus::Pending && chunk.record.attempts == 0 {
+                run.push(chunk);
+                continue;
+            }
+            if !run.is_empty() {
+                changed |= Self::split_run(plan, std::mem::take(&mut run), chunk_size, chunks);
+            }
+            chunks.push(chunk);
+        }
+        if !run.is_empty() {
+            changed |= Self::split_run(plan, run, chunk_size, chunks);
+        }
+        
+        for (index, chunk) in chunks.iter_mut().enumerate().skip(from) {
+            if chunk.record.index as usize != index {
+                chunk.record.index = index as u32;
+                changed = true;
+            }
+        }
+        changed
+    }
+    
+    /// 把一段连续的未发送块按块大小重新切分后追加到 `out`，返回切分结果是否不同
+    fn split_run(plan: &ChunkPlan, run: Vec<PendingChunk>, chunk_size: usize, out: &mut Vec<PendingChunk>) -> bool {
+        let base_offset = run[0].record.offset;
+        let old_sizes: Vec<u32> = run.iter().map(|chunk| chunk.record.size).collect();
+        let data: Vec<u8> = run.into_iter().flat_map(|chunk| chunk.data).collect();
+        let mut chunk_manager = ChunkManager::for_plan(plan);
+        let new_chunks = chunk_manager.chunk_file(&data, chunk_size);
+        let changed = new_chunks.len() != old_sizes.len()
+            || new_chunks.iter().zip(&old_sizes).any(|(chunk, size)| chunk.size as u32 != *size);
+        out.extend(new_chunks.into_iter().map(|chunk| PendingChunk {
+            record: ChunkRecord {
+                index: 0,
+                offset: base_offset + chunk.offset,
+                size: chunk.size as u32,
+                checksum: chunk.checksum,
+                status: ChunkStatus::Pending,
+                signature: None,
+                last_valid_block_height: None,
+                attempts: 0,
+                updated_at: Utc::now(),
+                fee: None,
+                savings_counted: false,
+            },
+            data: chunk.data,
+        }));
+        changed
+    }
+    
+    /// 执行续传部署：只上传账本中尚未确认的数据块
     pub async fn resume_deployment(
         &self,
         deployment: &DeploymentState,
         program_data: &[u8],
-        payer: &Keypair,
+        signers: &DeploymentSigners,
         config: &ResumeConfig,
+        session: &mut UploadSession<'_>,
     ) -> Result<()> {
-        let resume_point = self.calculate_resume_point(deployment)?;
+        let plan = Self::chunk_plan(deployment);
+        let controller = self.upload_controller(deployment, config, session);
+        let ledger = session.state_manager.get_chunk_ledger(&deployment.id);
+        let chunks = Self::plan_chunks(&plan, program_data, ledger, controller.chunk_size());
         
         match deployment.loader_version {
             LoaderVersion::V3 => {
-                self.resume_v3_deployment(deployment, program_data, payer, config, resume_point).await
+                self.resume_v3_deployment(deployment, signers, config, chunks, session).await
             }
             LoaderVersion::V4 => {
-                self.resume_v4_deployment(deployment, program_data, payer, config, resume_point).await
+                self.resume_v4_deployment(deployment, signers, config, chunks, session).await
             }
         }
     }
     
+    /// 写入交易的分块规划：按加载器和是否使用持久nonce计算单笔交易可携带的数据量
+    pub fn chunk_plan(deployment: &DeploymentState) -> ChunkPlan {
+        ChunkPlan::new(
+            deployment.loader_version.clone(),
+            WriteTransactionLayout::for_deployment(deployment),
+        )
+    }
+    
+    /// 上传参数的AIMD控制器：从能装进一笔交易的最大块大小开始，
+    /// 当前RPC节点上有学到的参数时以其为起点
+    fn upload_controller(
+        &self,
+        deployment: &DeploymentState,
+        config: &ResumeConfig,
+        session: &UploadSession<'_>,
+    ) -> AimdController {
+        // 持久nonce模式下每笔交易都会推进nonce，必须逐笔发送
+        let max_parallelism = if deployment.nonce_account.is_some() {
+            1
+        } else {
+            config.parallel_uploads
+        };
+        let controller = AimdController::new(
+            Self::chunk_plan(deployment).chunk_size(config.chunk_size),
+            max_parallelism,
+        );
+        match session.state_manager.get_upload_tuning(&self.rpc_pool.primary_url()) {
+            Some(learned) => controller.with_learned(&learned),
+            None => controller,
+        }
+    }
+    
     /// 续传 Loader v3 部署
     async fn resume_v3_deployment(
         &self,
         deployment: &DeploymentState,
-        program_data: &[u8],
-        payer: &Keypair,
+        signers: &DeploymentSigners,
         config: &ResumeConfig,
-        resume_point: u64,
+        chunks: Vec<PendingChunk>,
+        session: &mut UploadSession<'_>,
     ) -> Result<()> {
-        println!("开始续传 Loader v3 部署，从字节 {} 开始", resume_point);
+        let buffer = self.upload_target(deployment)?;
+        println!("开始续传 Loader v3 部署，写入buffer {}", buffer);
         
-        let remaining_data = &program_data[resume_point as usize..];
-        let chunk_size = config.chunk_size;
-        
-        // 分批上传剩余数据
-        for (i, chunk) in remaining_data.chunks(chunk_size).enumerate() {
-            let offset = resume_point + (i * chunk_size) as u64;
-            
-            for retry in 0..config.max_retries {
-                match self.upload_chunk_v3(chunk, offset, payer).await {
-                    Ok(_) => {
-                        println!("成功上传块 {} (偏移: {})", i, offset);
-                        break;
-                    }
-                    Err(e) => {
-                        eprintln!("上传块 {} 失败 (重试 {}): {}", i, retry + 1, e);
-                        if retry < config.max_retries - 1 {
-                            tokio::time::sleep(Duration::from_millis(config.retry_delay_ms)).await;
-                        } else {
-                            return Err(anyhow!("上传块 {} 最终失败", i));
-                        }
-                    }
-                }
-            }
-        }
+        let authority = signers.buffer_authority().pubkey();
+        self.upload_chunks(deployment, signers, config, chunks, session, |offset, bytes| {
+            bpf_loader_upgradeable::write(&buffer, &authority, offset, bytes)
+        })
+        .await?;
         
         println!("Loader v3 续传部署完成");
         Ok(())
@@ -178,70 +709,848 @@ impl ResumeEngine {
     async fn resume_v4_deployment(
         &self,
         deployment: &DeploymentState,
-        program_data: &[u8],
-        payer: &Keypair,
+        signers: &DeploymentSigners,
         config: &ResumeConfig,
-        resume_point: u64,
+        chunks: Vec<PendingChunk>,
+        session: &mut UploadSession<'_>,
     ) -> Result<()> {
-        println!("开始续传 Loader v4 部署，从字节 {} 开始", resume_point);
+        let program_id = self.upload_target(deployment)?;
+        println!("开始续传 Loader v4 部署，写入程序账户 {}", program_id);
         
-        let remaining_data = &program_data[resume_point as usize..];
-        let chunk_size = config.chunk_size;
+        let authority = signers.buffer_authority().pubkey();
+        self.upload_chunks(deployment, signers, config, chunks, session, |offset, bytes| {
+            loader_v4::write(&program_id, &authority, offset, bytes)
+        })
+        .await?;
         
-        for (i, chunk) in remaining_data.chunks(chunk_size).enumerate() {
-            let offset = resume_point + (i * chunk_size) as u64;
+        println!("Loader v4 续传部署完成");
+        Ok(())
+    }
+    
+    /// 按并发数分批广播写入交易，每批确认后记录账本和进度。
+    /// 块大小和并发数由AIMD控制器根据每轮的确认情况调整，学到的参数按RPC节点保存。
+    /// 区块哈希过期仍未上链的块会用新的区块哈希重新签名；结果未知的块先确认链上状态，
+    /// 确认过的块只计一次费用
+    async fn upload_chunks<F>(
+        &self,
+        deployment: &DeploymentState,
+        signers: &DeploymentSigners,
+        config: &ResumeConfig,
+        mut chunks: Vec<PendingChunk>,
+        session: &mut UploadSession<'_>,
+        write_instruction: F,
+    ) -> Result<()>
+    where
+        F: Fn(u32, Vec<u8>) -> Instruction,
+    {
+        let blockhash_manager = self.sender.blockhash_manager();
+        let payer = signers.fee_payer();
+        let mut write_signers = vec![payer];
+        if signers.buffer_authority().pubkey() != payer.pubkey() {
+            write_signers.push(signers.buffer_authority());
+        }
+        let retry_handler = RetryHandler::new(config).with_retry_budget(self.rpc_pool.limiter().clone());
+        let mut breaker_state = self.circuit_state();
+        let mut confirmed_bytes: u64 = chunks
+            .iter()
+            .filter(|chunk| chunk.record.status == ChunkStatus::Confirmed)
+            .map(|chunk| chunk.record.size as u64)
+            .sum();
+        println!("已确认 {} / {} bytes", confirmed_bytes, deployment.total_size);
+        
+        // 持久nonce模式下每笔交易先推进nonce
+        let nonce_account = deployment.nonce_account;
+        let build_instructions = |offset: u32, bytes: Vec<u8>| {
+            let mut instructions = Vec::with_capacity(2);
+            if let Some(ref nonce_account) = nonce_account {
+                instructions.push(system_instruction::advance_nonce_account(nonce_account, &payer.pubkey()));
+            }
+            instructions.push(write_instruction(offset, bytes));
+            instructions
+        };
+        
+        // 所有写入交易的结构相同，费用只需查询一次
+        let fee_per_transaction = {
+            let mut message = Message::new(&build_instructions(0, Vec::new()), Some(&payer.pubkey()));
+            message.recent_blockhash = self
+                .guarded_rpc(&retry_handler, || {
+                    let blockhash_manager = blockhash_manager.clone();
+                    async move { blockhash_manager.latest_async().await }
+                })
+                .await?
+                .blockhash;
+            self.transaction_fee(message).await
+        };
+        
+        // 之前的会话中确认、这次不再发送的块，按交易元数据中的实际费用计入节省。
+        // 每块只计一次；没有元数据的块和增量升级中未变化的块从未付费，不计入
+        let mut saved_fees = 0;
+        for chunk in chunks.iter_mut() {
+            if chunk.record.status != ChunkStatus::Confirmed || chunk.record.savings_counted {
+                continue;
+            }
+            if let Some(fee) = chunk.record.fee {
+                saved_fees += fee;
+                chunk.record.savings_counted = true;
+                session.state_manager.record_chunk(&deployment.id, &chunk.record)?;
+            }
+        }
+        if saved_fees > 0 {
+            let saved = CostStats {
+                saved_fees,
+                ..CostStats::default()
+            };
+            session.record_progress(&deployment.id, confirmed_bytes, &saved, None)?;
+        }
+        
+        let endpoint = self.rpc_pool.primary_url();
+        let plan = Self::chunk_plan(deployment);
+        let mut controller = self.upload_controller(deployment, config, session);
+        println!(
+            "🎛️  上传参数: 块大小 {}B, 并发数 {} (节点 {})",
+            controller.chunk_size(),
+            controller.parallelism(),
+            endpoint
+        );
+        let mut planned_chunk_size = None;
+        let mut cursor = 0;
+        while cursor < chunks.len() {
+            // 块大小变化后，按新的大小重新切分还没发送过的块
+            if planned_chunk_size != Some(controller.chunk_size()) {
+                if Self::rechunk_remaining(&plan, &mut chunks, cursor, controller.chunk_size()) {
+                    let records: Vec<ChunkRecord> = chunks.iter().map(|chunk| chunk.record.clone()).collect();
+                    session.state_manager.rewrite_chunk_ledger(&deployment.id, &records)?;
+                }
+                planned_chunk_size = Some(controller.chunk_size());
+            }
             
-            for retry in 0..config.max_retries {
-                match self.upload_chunk_v4(chunk, offset, payer).await {
-                    Ok(_) => {
-                        println!("成功上传块 {} (偏移: {})", i, offset);
-                        break;
+            let mut end = cursor;
+            let mut selected = 0;
+            while end < chunks.len() && selected < controller.parallelism() {
+                if chunks[end].record.status != ChunkStatus::Confirmed {
+                    selected += 1;
+                }
+                end += 1;
+            }
+            let mut batch: Vec<&mut PendingChunk> = chunks[cursor..end]
+                .iter_mut()
+                .filter(|chunk| chunk.record.status != ChunkStatus::Confirmed)
+                .collect();
+            cursor = end;
+            if batch.is_empty() {
+                continue;
+            }
+            let batch = batch.as_mut_slice();
+            
+            for retry in 0..config.max_retries.max(1) {
+                // 之前发送过但结果未知的块，先确认链上状态再决定是否重新签名
+                let (reconciled_bytes, mut round_cost) = self
+                    .reconcile_sent_chunks(deployment, batch, session, &retry_handler, fee_per_transaction)
+                    .await?;
+                confirmed_bytes += reconciled_bytes;
+                
+                let (recent_blockhash, lifetime) = self
+                    .guarded_rpc(&retry_handler, || self.transaction_lifetime(nonce_account))
+                    .await?;
+                let round_started = Instant::now();
+                let mut round_bytes: u64 = 0;
+                let mut sent = Vec::new();
+                for chunk in batch.iter_mut().filter(|chunk| chunk.record.status != ChunkStatus::Confirmed) {
+                    let transaction = Transaction::new_signed_with_payer(
+                        &build_instructions(chunk.record.offset as u32, chunk.data.clone()),
+                        Some(&payer.pubkey()),
+                        &write_signers,
+                        recent_blockhash,
+                    );
+                    chunk.record.status = ChunkStatus::Sent;
+                    chunk.record.signature = Some(transaction.signatures[0].to_string());
+                    // nonce交易不记录有效高度：用当前nonce重新签名时，若旧交易未上链则得到完全相同的交易，
+                    // 若nonce已推进则旧交易已失效，因此无需等待即可重签
+                    chunk.record.last_valid_block_height = match lifetime {
+                        TransactionLifetime::BlockHeight(height) => Some(height),
+                        TransactionLifetime::DurableNonce { .. } => None,
+                    };
+                    chunk.record.attempts += 1;
+                    chunk.record.updated_at = Utc::now();
+                    session.state_manager.record_chunk(&deployment.id, &chunk.record)?;
+                    sent.push((&mut **chunk, transaction));
+                }
+                
+                let transactions: Vec<Transaction> = sent.iter().map(|(_, tx)| tx.clone()).collect();
+                let statuses = if transactions.is_empty() {
+                    Vec::new()
+                } else {
+                    self.wait_for_breaker().await;
+                    let confirm_timeout = self.adaptive_timeout.lock().unwrap().get_timeout();
+                    let started = Instant::now();
+                    match self
+                        .sender
+                        .send_and_confirm_batch(&transactions, lifetime, confirm_timeout)
+                        .await
+                    {
+                        Ok(statuses) => {
+                            self.record_batch_outcome(&statuses, started.elapsed());
+                            statuses
+                        }
+                        Err(e) => {
+                            // 交易可能已经广播，保持已发送状态，下一轮先确认结果
+                            eprintln!("发送上传批次失败 (重试 {}): {}", retry + 1, e);
+                            self.record_rpc_failure();
+                            vec![ConfirmationStatus::Pending; transactions.len()]
+                        }
                     }
-                    Err(e) => {
-                        eprintln!("上传块 {} 失败 (重试 {}): {}", i, retry + 1, e);
-                        if retry < config.max_retries - 1 {
-                            tokio::time::sleep(Duration::from_millis(config.retry_delay_ms)).await;
-                        } else {
-                            return Err(anyhow!("上传块 {} 最终失败", i));
+                };
+                
+                let sent_count = sent.len();
+                let mut round_confirmed = 0;
+                let mut failed = 0;
+                let mut fatal_error = None;
+                for ((chunk, transaction), status) in sent.into_iter().zip(statuses) {
+                    match status {
+                        ConfirmationStatus::Confirmed => {
+                            let cost = self.transaction_cost(&transaction.signatures[0], fee_per_transaction, 0).await;
+                            round_cost.record_transaction(cost.as_ref(), fee_per_transaction);
+                            chunk.record.status = ChunkStatus::Confirmed;
+                            chunk.record.fee = cost.map(|cost| cost.fee);
+                            confirmed_bytes += chunk.record.size as u64;
+                            round_bytes += chunk.record.size as u64;
+                            round_confirmed += 1;
+                        }
+                        ConfirmationStatus::Failed(err) => {
+                            // 执行失败的交易已经上链，同样扣除了手续费
+                            let cost = self.transaction_cost(&transaction.signatures[0], fee_per_transaction, 0).await;
+                            round_cost.record_transaction(cost.as_ref(), fee_per_transaction);
+                            eprintln!("上传块 {} 失败 (重试 {}): {}", chunk.record.index, retry + 1, err);
+                            chunk.record.status = ChunkStatus::Failed;
+                            failed += 1;
+                            // 余额不足、指令错误等永久性失败，重试只会浪费手续费
+                            let classified = DeployError::from_transaction_error(&err);
+                            if !classified.is_retryable() {
+                                fatal_error.get_or_insert(classified);
+                            }
+                        }
+                        ConfirmationStatus::Expired => {
+                            eprintln!("上传块 {} 的区块哈希已失效，将重新签名 (重试 {})", chunk.record.index, retry + 1);
+                            chunk.record.status = ChunkStatus::Failed;
+                            failed += 1;
+                        }
+                        ConfirmationStatus::Pending => {
+                            // 仍可能上链，保持已发送状态，下一轮先确认结果
+                            eprintln!("上传块 {} 未在超时前确认 (重试 {})", chunk.record.index, retry + 1);
+                            failed += 1;
                         }
                     }
+                    chunk.record.updated_at = Utc::now();
+                    session.state_manager.record_chunk(&deployment.id, &chunk.record)?;
+                }
+                
+                // 从签名到确认的实际用时，反映真实的上传吞吐量
+                self.bandwidth_monitor.record_transfer(round_bytes, round_started.elapsed());
+                round_cost.retry_count += u32::from(retry > 0) + self.rpc_retries.swap(0, Ordering::Relaxed);
+                session.record_progress(
+                    &deployment.id,
+                    confirmed_bytes,
+                    &round_cost,
+                    self.bandwidth_monitor.current_bandwidth(),
+                )?;
+                self.report_breaker_transition(&deployment.id, session, &mut breaker_state)?;
+                let action = controller.record_round(sent_count, round_confirmed, round_started.elapsed());
+                Self::report_tuning_change(&deployment.id, session, &controller, &endpoint, action, sent_count - round_confirmed)?;
+                
+                if let Some(e) = fatal_error {
+                    return Err(e.into());
+                }
+                if failed == 0 {
+                    break;
+                }
+                if retry < config.max_retries.max(1) - 1 {
+                    let delay = retry_handler.backoff_delay(retry + 1);
+                    let confirm_timeout = self.adaptive_timeout.lock().unwrap().get_timeout();
+                    let retry_count = session
+                        .state_manager
+                        .get_deployment(&deployment.id)
+                        .map(|deployment| deployment.cost_stats.retry_count)
+                        .unwrap_or_default();
+                    session.state_manager.log_event(
+                        &deployment.id,
+                        EventType::Warning,
+                        format!(
+                            "{} 个块未确认，{:.1} 秒后进行第 {} 次重试",
+                            failed,
+                            delay.as_secs_f64(),
+                            retry + 1
+                        ),
+                        Some(serde_json::json!({
+                            "retry": retry + 1,
+                            "unconfirmed_chunks": failed,
+                            "delay_ms": delay.as_millis() as u64,
+                            "circuit_state": format!("{:?}", breaker_state),
+                            "confirm_timeout_ms": confirm_timeout.as_millis() as u64,
+                            "retry_count": retry_count,
+                        })),
+                    )?;
+                    tokio::time::sleep(delay).await;
+                } else {
+                    let indexes: Vec<u32> = batch
+                        .iter()
+                        .filter(|chunk| chunk.record.status != ChunkStatus::Confirmed)
+                        .map(|chunk| chunk.record.index)
+                        .collect();
+                    return Err(anyhow!("上传块 {:?} 最终失败", indexes));
                 }
             }
+            println!("📈 上传进度: {} / {} bytes", confirmed_bytes, deployment.total_size);
         }
         
-        println!("Loader v4 续传部署完成");
+        session.state_manager.save_upload_tuning(&controller.tuning(&endpoint))?;
+        println!(
+            "🎛️  上传结束时的参数: 块大小 {}B, 并发数 {} (已保存，供节点 {} 下次使用)",
+            controller.chunk_size(),
+            controller.parallelism(),
+            endpoint
+        );
         Ok(())
     }
     
-    /// 上传 Loader v3 数据块
-    async fn upload_chunk_v3(&self, chunk: &[u8], offset: u64, payer: &Keypair) -> Result<()> {
-        // 这里是简化的实现，实际需要调用 Solana 的 write-buffer 指令
-        // 使用模拟延时来表示网络操作
-        tokio::time::sleep(Duration::from_millis(100)).await;
+    /// 确认已发送但结果未知的块：已上链的标记为已确认；未上链的等到其区块哈希过期后再查一次，
+    /// 仍未上链才允许重新签名，保证同一个块不会同时有两笔有效交易在途。
+    /// 返回新确认的字节数和已上链交易的费用
+    async fn reconcile_sent_chunks(
+        &self,
+        deployment: &DeploymentState,
+        batch: &mut [&mut PendingChunk],
+        session: &mut UploadSession<'_>,
+        retry_handler: &RetryHandler,
+        fee_per_transaction: u64,
+    ) -> Result<(u64, CostStats)> {
+        let mut unresolved: Vec<&mut PendingChunk> = batch
+            .iter_mut()
+            .filter(|chunk| chunk.record.status == ChunkStatus::Sent)
+            .map(|chunk| &mut **chunk)
+            .collect();
+        let mut costs = CostStats::default();
+        if unresolved.is_empty() {
+            return Ok((0, costs));
+        }
         
-        if chunk.len() > 1024 {
-            return Err(anyhow!("模拟的网络错误"));
+        let mut confirmed_bytes = 0;
+        let mut confirmed_count = 0;
+        let mut waited = false;
+        loop {
+            let signatures: Vec<Signature> = unresolved
+                .iter()
+                .filter_map(|chunk| chunk.record.signature.as_deref())
+                .filter_map(|signature| Signature::from_str(signature).ok())
+                .collect();
+            // 结果可能来自很久之前的运行，需要查询历史
+            let statuses = self
+                .guarded_rpc(retry_handler, || self.sender.confirm(&signatures, true))
+                .await?;
+            
+            let mut still_unknown = Vec::new();
+            for chunk in unresolved {
+                let signature = chunk
+                    .record
+                    .signature
+                    .as_deref()
+                    .and_then(|signature| Signature::from_str(signature).ok());
+                let status = signature.and_then(|signature| statuses.get(&signature).cloned());
+                match (status, signature) {
+                    (Some(ConfirmationStatus::Confirmed), Some(signature)) => {
+                        let cost = self.transaction_cost(&signature, fee_per_transaction, 0).await;
+                        costs.record_transaction(cost.as_ref(), fee_per_transaction);
+                        chunk.record.status = ChunkStatus::Confirmed;
+                        chunk.record.fee = cost.map(|cost| cost.fee);
+                        confirmed_bytes += chunk.record.size as u64;
+                        confirmed_count += 1;
+                    }
+                    (Some(ConfirmationStatus::Failed(_)), Some(signature)) => {
+                        let cost = self.transaction_cost(&signature, fee_per_transaction, 0).await;
+                        costs.record_transaction(cost.as_ref(), fee_per_transaction);
+                        chunk.record.status = ChunkStatus::Failed;
+                    }
+                    _ if waited || chunk.record.last_valid_block_height.is_none() => {
+                        chunk.record.status = ChunkStatus::Failed;
+                    }
+                    _ => {
+                        still_unknown.push(chunk);
+                        continue;
+                    }
+                }
+                chunk.record.updated_at = Utc::now();
+                session.state_manager.record_chunk(&deployment.id, &chunk.record)?;
+            }
+            
+            if still_unknown.is_empty() {
+                break;
+            }
+            let last_valid_block_height = still_unknown
+                .iter()
+                .filter_map(|chunk| chunk.record.last_valid_block_height)
+                .max()
+                .unwrap_or_default();
+            println!("⏳ 等待 {} 笔结果未知的交易过期 (区块高度 {})...", still_unknown.len(), last_valid_block_height);
+            self.sender
+                .blockhash_manager()
+                .wait_until_expired(last_valid_block_height)
+                .await?;
+            unresolved = still_unknown;
+            waited = true;
         }
         
-        Ok(())
+        if confirmed_count > 0 {
+            println!("✅ {} 个之前发送的块已上链，无需重新上传", confirmed_count);
+        }
+        Ok((confirmed_bytes, costs))
     }
     
-    /// 上传 Loader v4 数据块
-    async fn upload_chunk_v4(&self, chunk: &[u8], offset: u64, payer: &Keypair) -> Result<()> {
-        // 这里是简化的实现，实际需要调用 Solana 的 Loader v4 指令
-        tokio::time::sleep(Duration::from_millis(80)).await;
-        
-        if chunk.len() > 2048 {
-            return Err(anyhow!("模拟的网络错误"));
+    /// 完成部署：v3 从buffer部署新程序或升级已有程序，v4 部署已写入数据的程序账户。
+    /// buffer授权与升级授权不同时，在同一笔交易中先把buffer交给升级授权
+    pub async fn finalize_deployment(&self, deployment: &mut DeploymentState, signers: &DeploymentSigners) -> Result<Signature> {
+        let target = self.upload_target(deployment)?;
+        let payer = signers.fee_payer();
+        let buffer_authority = signers.buffer_authority();
+        let upgrade_authority = signers.upgrade_authority();
+
+        let current_authority = deployment
+            .buffer_accounts
+            .last()
+            .and_then(|buffer| buffer.authority)
+            .unwrap_or(buffer_authority.pubkey());
+        let mut instructions = Vec::new();
+        if current_authority != upgrade_authority.pubkey() {
+            instructions.push(match deployment.loader_version {
+                LoaderVersion::V3 => {
+                    bpf_loader_upgradeable::set_buffer_authority(&target, &current_authority, &upgrade_authority.pubkey())
+                }
+                LoaderVersion::V4 => {
+                    loader_v4::transfer_authority(&target, &current_authority, Some(&upgrade_authority.pubkey()))
+                }
+            });
         }
+
+        let signature = match deployment.loader_version {
+            LoaderVersion::V3 if deployment.upgrade.is_some() => {
+                let program_id = deployment.upgrade.as_ref().map(|upgrade| upgrade.program_id).unwrap_or_default();
+                // ProgramData 容量不足时先扩容，扩容与升级不能放在同一笔交易中
+                let programdata = self.fetch_programdata(&program_id).await?;
+                let capacity = programdata
+                    .data
+                    .len()
+                    .saturating_sub(UpgradeableLoaderState::size_of_programdata_metadata()) as u64;
+                if deployment.total_size > capacity {
+                    let additional_bytes = (deployment.total_size - capacity) as u32;
+                    println!("📏 扩容ProgramData {} bytes", additional_bytes);
+                    // 扩容所需的租金差额由付款账户补足
+                    let rent = self
+                        .rent_exemption(programdata.data.len() + additional_bytes as usize)
+                        .await?
+                        .saturating_sub(programdata.lamports);
+                    let instruction = bpf_loader_upgradeable::extend_program(&program_id, Some(&payer.pubkey()), additional_bytes);
+                    self.send_recorded(deployment, &[instruction], payer, &[], rent).await?;
+                }
+                instructions.push(bpf_loader_upgradeable::upgrade(
+                    &program_id,
+                    &target,
+                    &upgrade_authority.pubkey(),
+                    &payer.pubkey(),
+                ));
+                let signature = self
+                    .send_recorded(deployment, &instructions, payer, &[buffer_authority, upgrade_authority], 0)
+                    .await?;
+                deployment.program_id = Some(program_id);
+                signature
+            }
+            LoaderVersion::V3 => {
+                let generated = Keypair::new();
+                let program = signers.program().unwrap_or(&generated);
+                let program_lamports = self.rent_exemption(UpgradeableLoaderState::size_of_program()).await?;
+                // 加载器创建 ProgramData 的租金同样由付款账户支付，buffer中的余额退还给付款账户
+                let programdata_lamports = self
+                    .rent_exemption(UpgradeableLoaderState::size_of_programdata(deployment.total_size as usize))
+                    .await?;
+                instructions.extend(bpf_loader_upgradeable::deploy_with_max_program_len(
+                    &payer.pubkey(),
+                    &program.pubkey(),
+                    &target,
+                    &upgrade_authority.pubkey(),
+                    program_lamports,
+                    deployment.total_size as usize,
+                )?);
+                let signature = self
+                    .send_recorded(
+                        deployment,
+                        &instructions,
+                        payer,
+                        &[program, buffer_authority, upgrade_authority],
+                        program_lamports + programdata_lamports,
+                    )
+                    .await?;
+                deployment.program_id = Some(program.pubkey());
+                signature
+            }
+            LoaderVersion::V4 => {
+                self.wait_for_v4_cooldown(&target).await?;
+                instructions.push(loader_v4::deploy(&target, &upgrade_authority.pubkey()));
+                self.send_recorded(deployment, &instructions, payer, &[buffer_authority, upgrade_authority], 0)
+                    .await?
+            }
+        };
         
+        if let Some(buffer) = deployment.buffer_accounts.last_mut() {
+            buffer.uploaded_size = buffer.size;
+            buffer.status = BufferStatus::Completed;
+            buffer.authority = Some(upgrade_authority.pubkey());
+        }
+        Ok(signature)
+    }
+    
+    /// AIMD控制器调整参数后保存到节点记录，减小时记录事件
+    fn report_tuning_change(
+        id: &Uuid,
+        session: &mut UploadSession<'_>,
+        controller: &AimdController,
+        endpoint: &str,
+        action: TuningAction,
+        unconfirmed: usize,
+    ) -> Result<()> {
+        if action == TuningAction::Hold {
+            return Ok(());
+        }
+        session.state_manager.save_upload_tuning(&controller.tuning(endpoint))?;
+        if action == TuningAction::Decrease {
+            session.state_manager.log_event(
+                id,
+                EventType::Info,
+                format!(
+                    "{} 笔交易未确认，调整为块大小 {}B、并发数 {}",
+                    unconfirmed,
+                    controller.chunk_size(),
+                    controller.parallelism()
+                ),
+                Some(serde_json::json!({
+                    "chunk_size": controller.chunk_size(),
+                    "parallelism": controller.parallelism(),
+                    "unconfirmed": unconfirmed,
+                    "endpoint": endpoint,
+                })),
+            )?;
+        } else {
+            tracing::debug!(
+                "上传参数增加为块大小 {}B、并发数 {}",
+                controller.chunk_size(),
+                controller.parallelism()
+            );
+        }
+        Ok(())
+    }
+    
+    /// 在上传路径断路器、自适应超时统计和指数退避重试的保护下执行RPC调用。
+    /// 断路器开启时先等待冷却结束；重试次数会计入 CostStats.retry_count
+    async fn guarded_rpc<T, F, Fut>(&self, retry_handler: &RetryHandler, operation: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let attempts = AtomicU32::new(0);
+        let (attempts_ref, operation_ref) = (&attempts, &operation);
+        let result = retry_handler
+            .retry_with_backoff(move || async move {
+                attempts_ref.fetch_add(1, Ordering::Relaxed);
+                self.wait_for_breaker().await;
+                let started = Instant::now();
+                let result = operation_ref().await;
+                match &result {
+                    Ok(_) => self.record_rpc_success(started.elapsed()),
+                    // 请求本身的错误与链路健康无关
+                    Err(e) if retry::retry_decision(e).0 => self.record_rpc_failure(),
+                    // 节点已正常应答，只是请求本身出错；同时结束半开状态下的试探
+                    Err(_) => self.circuit_breaker.lock().unwrap().record_success(),
+                }
+                result
+            })
+            .await;
+        self.rpc_retries
+            .fetch_add(attempts.into_inner().saturating_sub(1), Ordering::Relaxed);
+        result
+    }
+    
+    /// 断路器开启时等待其进入半开状态
+    async fn wait_for_breaker(&self) {
+        loop {
+            let remaining = {
+                let mut breaker = self.circuit_breaker.lock().unwrap();
+                if breaker.allow_request() {
+                    return;
+                }
+                breaker.open_remaining().unwrap_or(UPLOAD_BREAKER_COOLDOWN)
+            };
+            println!("🔌 上传链路断路器开启，{:.0} 秒后重试", remaining.as_secs_f64());
+            tokio::time::sleep(remaining.max(Duration::from_millis(100))).await;
+        }
+    }
+    
+    fn record_rpc_success(&self, elapsed: Duration) {
+        self.circuit_breaker.lock().unwrap().record_success();
+        self.adaptive_timeout.lock().unwrap().record_success(elapsed);
+    }
+    
+    fn record_rpc_failure(&self) {
+        self.circuit_breaker.lock().unwrap().record_failure();
+        self.adaptive_timeout.lock().unwrap().record_failure();
+    }
+    
+    /// 根据一批交易的确认结果更新断路器和自适应超时：
+    /// 全部在超时内有结果视为成功，一笔都没有上链视为链路故障
+    fn record_batch_outcome(&self, statuses: &[ConfirmationStatus], elapsed: Duration) {
+        let unresolved = statuses
+            .iter()
+            .filter(|status| matches!(status, ConfirmationStatus::Pending | ConfirmationStatus::Expired))
+            .count();
+        if unresolved == 0 {
+            self.record_rpc_success(elapsed);
+        } else if unresolved == statuses.len() {
+            self.record_rpc_failure();
+        } else {
+            self.circuit_breaker.lock().unwrap().record_success();
+            self.adaptive_timeout.lock().unwrap().record_failure();
+        }
+    }
+    
+    /// 上传链路断路器的当前状态
+    fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker.lock().unwrap().get_state().clone()
+    }
+    
+    /// 断路器状态变化时记录事件
+    fn report_breaker_transition(
+        &self,
+        id: &Uuid,
+        session: &mut UploadSession<'_>,
+        last_state: &mut CircuitState,
+    ) -> Result<()> {
+        let state = self.circuit_state();
+        if state == *last_state {
+            return Ok(());
+        }
+        let event_type = match state {
+            CircuitState::Open => EventType::Warning,
+            CircuitState::HalfOpen | CircuitState::Closed => EventType::Info,
+        };
+        session.state_manager.log_event(
+            id,
+            event_type,
+            format!("上传链路断路器状态: {:?} -> {:?}", last_state, state),
+            Some(serde_json::json!({
+                "circuit_state": format!("{:?}", state),
+                "previous_state": format!("{:?}", last_state),
+                "confirm_timeout_ms": self.adaptive_timeout.lock().unwrap().get_timeout().as_millis() as u64,
+            })),
+        )?;
+        *last_state = state;
         Ok(())
     }
     
+    /// 签名写入交易使用的哈希及其有效期：持久nonce模式下为nonce账户当前的nonce值
+    async fn transaction_lifetime(&self, nonce_account: Option<Pubkey>) -> Result<(Hash, TransactionLifetime)> {
+        let blockhash_manager = self.sender.blockhash_manager();
+        match nonce_account {
+            Some(account) => {
+                let nonce = blockhash_manager.durable_nonce_async(account).await?.blockhash();
+                Ok((nonce, TransactionLifetime::DurableNonce { account, nonce }))
+            }
+            None => {
+                let info = blockhash_manager.latest_async().await?;
+                Ok((info.blockhash, TransactionLifetime::BlockHeight(info.last_valid_block_height)))
+            }
+        }
+    }
+    
+    /// 为部署准备持久nonce账户：使用指定的已有账户（授权账户必须是付款账户），否则新建一个。
+    /// 部署已记录nonce账户时不做任何事，返回是否有变化
+    pub async fn prepare_nonce_account(
+        &self,
+        deployment: &mut DeploymentState,
+        existing: Option<Pubkey>,
+        payer: &Keypair,
+    ) -> Result<bool> {
+        if deployment.nonce_account.is_some() {
+            return Ok(false);
+        }
+        
+        let account = match existing {
+            Some(account) => {
+                let nonce = self.sender.blockhash_manager().durable_nonce_async(account).await?;
+                if nonce.authority != payer.pubkey() {
+                    return Err(DeployError::Configuration(format!(
+                        "nonce账户 {} 的授权账户是 {}，不是付款账户 {}",
+                        account,
+                        nonce.authority,
+                        payer.pubkey()
+                    ))
+                    .into());
+                }
+                account
+            }
+            None => {
+                let nonce_keypair = Keypair::new();
+                let lamports = self.rent_exemption(NonceState::size()).await?;
+                let instructions = system_instruction::create_nonce_account(
+                    &payer.pubkey(),
+                    &nonce_keypair.pubkey(),
+                    &payer.pubkey(),
+                    lamports,
+                );
+                self.send_recorded(deployment, &instructions, payer, &[&nonce_keypair], lamports).await?;
+                nonce_keypair.pubkey()
+            }
+        };
+        
+        deployment.nonce_account = Some(account);
+        Ok(true)
+    }
+    
+    /// 把部署当前buffer的写入授权转移给 `new_authority`，之后只有新授权能继续写入或用它完成部署
+    pub async fn set_buffer_authority(
+        &self,
+        deployment: &mut DeploymentState,
+        new_authority: &Pubkey,
+        signers: &DeploymentSigners,
+    ) -> Result<Signature> {
+        if deployment.loader_version != LoaderVersion::V3 {
+            return Err(anyhow!("只有 Loader v3 的buffer支持转移授权"));
+        }
+        let buffer = self.upload_target(deployment)?;
+        let authority = signers.buffer_authority();
+        let instruction = bpf_loader_upgradeable::set_buffer_authority(&buffer, &authority.pubkey(), new_authority);
+        let signature = self
+            .send_recorded(deployment, &[instruction], signers.fee_payer(), &[authority], 0)
+            .await?;
+        if let Some(info) = deployment.buffer_accounts.iter_mut().rev().find(|info| info.pubkey == buffer) {
+            info.authority = Some(*new_authority);
+        }
+        if let Some(keys) = deployment.keys.as_mut() {
+            keys.buffer_authority = *new_authority;
+        }
+        Ok(signature)
+    }
+    
+    /// 修改可升级程序的升级授权；`new_authority` 为空时程序变为不可升级，且无法撤销
+    pub async fn set_upgrade_authority(
+        &self,
+        deployment: &mut DeploymentState,
+        program_id: &Pubkey,
+        new_authority: Option<&Pubkey>,
+        signers: &DeploymentSigners,
+    ) -> Result<Signature> {
+        if deployment.loader_version != LoaderVersion::V3 {
+            return Err(anyhow!("只有 Loader v3 的程序支持修改升级授权"));
+        }
+        let authority = signers.upgrade_authority();
+        let instruction = bpf_loader_upgradeable::set_upgrade_authority(program_id, &authority.pubkey(), new_authority);
+        let signature = self
+            .send_recorded(deployment, &[instruction], signers.fee_payer(), &[authority], 0)
+            .await?;
+        if let (Some(keys), Some(new_authority)) = (deployment.keys.as_mut(), new_authority) {
+            keys.upgrade_authority = *new_authority;
+        }
+        Ok(signature)
+    }
+    
+    /// 签名并发送一组指令，等待确认。`extra_signers` 中只有消息实际需要签名的密钥会被使用，
+    /// 多个角色由同一密钥兼任时只签一次
+    async fn send_instructions(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        extra_signers: &[&Keypair],
+    ) -> Result<Signature> {
+        let blockhash = self.sender.blockhash_manager().latest_async().await?;
+        let message = Message::new_with_blockhash(instructions, Some(&payer.pubkey()), &blockhash.blockhash);
+        let required = &message.account_keys[..message.header.num_required_signatures as usize];
+        let mut signers = vec![payer];
+        for signer in extra_signers {
+            let pubkey = signer.pubkey();
+            if required.contains(&pubkey) && !signers.iter().any(|existing| existing.pubkey() == pubkey) {
+                signers.push(signer);
+            }
+        }
+        let transaction = Transaction::new(&signers, message, blockhash.blockhash);
+        self.sender
+            .send_and_confirm(
+                &transaction,
+                TransactionLifetime::BlockHeight(blockhash.last_valid_block_height),
+            )
+            .await
+    }
+    
+    /// 签名并发送账户操作交易，确认后把实际费用和租金变化计入部署的费用统计；
+    /// `rent_deposited` 为交易新建或扩容账户时由付款账户存入的租金
+    async fn send_recorded(
+        &self,
+        deployment: &mut DeploymentState,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        extra_signers: &[&Keypair],
+        rent_deposited: u64,
+    ) -> Result<Signature> {
+        let signature = self.send_instructions(instructions, payer, extra_signers).await?;
+        let mut message = Message::new(instructions, Some(&payer.pubkey()));
+        message.recent_blockhash = self.sender.blockhash_manager().latest_async().await?.blockhash;
+        let base_fee = self.transaction_fee(message).await;
+        let cost = self.transaction_cost(&signature, base_fee, rent_deposited).await;
+        deployment.cost_stats.record_transaction(cost.as_ref(), base_fee);
+        Ok(signature)
+    }
+    
+    /// 查询已上链交易的元数据得到实际费用明细，`base_fee` 为不含优先费的交易费用，
+    /// `rent_deposited` 为交易新建或扩容账户存入的租金。查询失败时返回 None，由调用方按估算费用记账
+    async fn transaction_cost(&self, signature: &Signature, base_fee: u64, rent_deposited: u64) -> Option<TransactionCost> {
+        let config = RpcTransactionConfig {
+            commitment: Some(self.commitment),
+            max_supported_transaction_version: Some(0),
+            ..RpcTransactionConfig::default()
+        };
+        let target = *signature;
+        match self
+            .rpc_pool
+            .call_blocking(move |client| client.get_transaction_with_config(&target, config))
+            .await
+        {
+            Ok(transaction) => {
+                let meta = transaction.transaction.meta?;
+                // 付款账户总是第一个账户
+                Some(TransactionCost::from_balances(
+                    meta.fee,
+                    base_fee,
+                    *meta.pre_balances.first()?,
+                    *meta.post_balances.first()?,
+                    rent_deposited,
+                ))
+            }
+            Err(e) => {
+                tracing::warn!("获取交易 {} 的元数据失败，按估算费用记账: {}", signature, e);
+                None
+            }
+        }
+    }
+    
+    /// 交易的基础费用，查询失败时按每个签名 5000 lamports 估算
+    async fn transaction_fee(&self, message: Message) -> u64 {
+        let estimate = message.header.num_required_signatures as u64 * 5000;
+        self.rpc_pool
+            .call_blocking(move |client| client.get_fee_for_message(&message))
+            .await
+            .unwrap_or(estimate)
+    }
+    
     /// 验证部署完整性
     pub fn verify_deployment(&self, deployment: &DeploymentState, original_data: &[u8]) -> Result<bool> {
         if let Some(program_id) = deployment.program_id {
-            match self.rpc_client.get_account_with_commitment(&program_id, self.commitment) {
+            match self
+                .rpc_pool
+                .call(|client| client.get_account_with_commitment(&program_id, self.commitment))
+            {
                 Ok(response) => {
                     if let Some(account) = response.value {
                         // 简单的大小验证
@@ -281,4 +1590,22 @@ impl ResumeEngine {
         let remaining_bytes = deployment.total_size.saturating_sub(deployment.uploaded_bytes);
         remaining_bytes * base_fee_per_byte
     }
-} 
\ No newline at end of file
+} 
+
+/// 解析 Loader v4 程序账户头部的状态
+fn loader_v4_state(data: &[u8]) -> Option<LoaderV4State> {
+    let header = data.get(..LoaderV4State::program_data_offset())?;
+    let slot = u64::from_le_bytes(header[0..8].try_into().ok()?);
+    let authority_address = Pubkey::try_from(&header[8..40]).ok()?;
+    let status = match u64::from_le_bytes(header[40..48].try_into().ok()?) {
+        0 => LoaderV4Status::Retracted,
+        1 => LoaderV4Status::Deployed,
+        2 => LoaderV4Status::Finalized,
+        _ => return None,
+    };
+    Some(LoaderV4State {
+        slot,
+        authority_address,
+        status,
+    })
+}
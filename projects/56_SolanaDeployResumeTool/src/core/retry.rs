@@ -1,7 +1,9 @@
 use anyhow::Result;
 use std::time::{Duration, Instant};
 use tokio::time::sleep;
+use crate::core::rate_limit::RpcLimiter;
 use crate::core::types::*;
+use solana_client::client_error::ClientError;
 
 /// 智能重试机制
 pub struct RetryHandler {
@@ -10,6 +12,7 @@ pub struct RetryHandler {
     max_delay: Duration,
     backoff_multiplier: f64,
     jitter_factor: f64,
+    retry_budget: Option<RpcLimiter>,
 }
 
 impl RetryHandler {
@@ -20,9 +23,23 @@ impl RetryHandler {
             max_delay: Duration::from_secs(30), // 最大延迟30秒
             backoff_multiplier: 2.0,
             jitter_factor: 0.1, // 10%的随机抖动
+            retry_budget: None,
         }
     }
 
+    /// 重试前先向共享限速器申请重试预算，预算用尽时不再重试
+    pub fn with_retry_budget(mut self, limiter: RpcLimiter) -> Self {
+        self.retry_budget = Some(limiter);
+        self
+    }
+
+    /// 调整退避的初始延迟和最大延迟，用于间隔更长的整体重试
+    pub fn with_delay_bounds(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay.max(base_delay);
+        self
+    }
+
     /// 执行带重试的操作
     pub async fn retry_with_backoff<F, Fut, T>(&self, operation: F) -> Result<T>
     where
@@ -59,13 +76,23 @@ impl RetryHandler {
                     last_error = Some(e);
                     
                     // 检查是否应该继续重试
-                    if !self.should_retry(&last_error.as_ref().unwrap()) {
+                    let (retryable, retry_after) = retry_decision(last_error.as_ref().unwrap());
+                    if !retryable {
                         tracing::info!("错误类型不适合重试，停止重试");
                         break;
                     }
+                    if attempt < self.max_retries
+                        && !self.retry_budget.as_ref().map_or(true, |limiter| limiter.try_retry())
+                    {
+                        tracing::warn!("重试预算已用尽，停止重试");
+                        break;
+                    }
                     
-                    // 计算下次延迟时间
+                    // 计算下次延迟时间，限流时至少等待 Retry-After 指定的时间
                     delay = self.calculate_next_delay(delay);
+                    if let Some(retry_after) = retry_after {
+                        delay = delay.max(retry_after);
+                    }
                 }
             }
         }
@@ -73,6 +100,15 @@ impl RetryHandler {
         Err(last_error.unwrap_or_else(|| anyhow::anyhow!("重试次数已用完")))
     }
 
+    /// 第 `attempt` 次重试前应等待的时间（指数退避加抖动），`attempt` 从1开始
+    pub fn backoff_delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.base_delay;
+        for _ in 1..attempt {
+            delay = self.calculate_next_delay(delay);
+        }
+        delay + self.calculate_jitter(delay)
+    }
+
     /// 计算抖动时间
     fn calculate_jitter(&self, base_delay: Duration) -> Duration {
         let jitter_ms = (base_delay.as_millis() as f64 * self.jitter_factor * rand::random::<f64>()) as u64;
@@ -90,39 +126,21 @@ impl RetryHandler {
             next_delay
         }
     }
+}
 
-    /// 判断错误是否应该重试
-    fn should_retry(&self, error: &anyhow::Error) -> bool {
-        let error_str = error.to_string().to_lowercase();
-        
-        // 网络相关错误应该重试
-        if error_str.contains("timeout") || 
-           error_str.contains("connection") ||
-           error_str.contains("network") ||
-           error_str.contains("502") ||
-           error_str.contains("503") ||
-           error_str.contains("504") {
-            return true;
+/// 根据错误链中的结构化错误判断是否应该重试，以及服务端要求的最短等待时间；
+/// RPC客户端错误会先被映射为 `DeployError`，未归类的错误默认重试
+pub fn retry_decision(error: &anyhow::Error) -> (bool, Option<Duration>) {
+    for cause in error.chain() {
+        if let Some(deploy_error) = cause.downcast_ref::<DeployError>() {
+            return (deploy_error.is_retryable(), deploy_error.retry_after());
         }
-        
-        // Solana特定的临时错误
-        if error_str.contains("blockhash not found") ||
-           error_str.contains("too many requests") ||
-           error_str.contains("rate limit") {
-            return true;
-        }
-        
-        // 永久性错误不应该重试
-        if error_str.contains("invalid") ||
-           error_str.contains("insufficient funds") ||
-           error_str.contains("unauthorized") ||
-           error_str.contains("forbidden") {
-            return false;
+        if let Some(client_error) = cause.downcast_ref::<ClientError>() {
+            let deploy_error = DeployError::from_client_error(client_error);
+            return (deploy_error.is_retryable(), deploy_error.retry_after());
         }
-        
-        // 默认重试
-        true
     }
+    (true, None)
 }
 
 /// 断路器模式实现
@@ -132,6 +150,8 @@ pub struct CircuitBreaker {
     current_failures: u32,
     last_failure_time: Option<Instant>,
     state: CircuitState,
+    /// 半开状态下正在进行的试探请求的开始时间，结果返回前拒绝其他请求
+    trial_started: Option<Instant>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -149,6 +169,7 @@ impl CircuitBreaker {
             current_failures: 0,
             last_failure_time: None,
             state: CircuitState::Closed,
+            trial_started: None,
         }
     }
 
@@ -159,24 +180,8 @@ impl CircuitBreaker {
         Fut: std::future::Future<Output = Result<T>>,
     {
         // 检查断路器状态
-        match self.state {
-            CircuitState::Open => {
-                // 检查是否到了重试时间
-                if let Some(last_failure) = self.last_failure_time {
-                    if last_failure.elapsed() > self.timeout {
-                        self.state = CircuitState::HalfOpen;
-                        tracing::info!("断路器进入半开状态");
-                    } else {
-                        return Err(anyhow::anyhow!("断路器开启，拒绝执行操作"));
-                    }
-                }
-            }
-            CircuitState::HalfOpen => {
-                // 半开状态下只允许一次尝试
-            }
-            CircuitState::Closed => {
-                // 正常状态，允许执行
-            }
+        if !self.allow_request() {
+            return Err(anyhow::anyhow!("断路器开启，拒绝执行操作"));
         }
 
         // 执行操作
@@ -192,31 +197,92 @@ impl CircuitBreaker {
         }
     }
 
+    /// 检查当前是否允许执行操作，开启状态超时后进入半开状态
+    pub fn allow_request(&mut self) -> bool {
+        match self.state {
+            CircuitState::Open => {
+                // 检查是否到了重试时间
+                match self.last_failure_time {
+                    Some(last_failure) if last_failure.elapsed() <= self.timeout => false,
+                    _ => {
+                        self.state = CircuitState::HalfOpen;
+                        self.trial_started = Some(Instant::now());
+                        tracing::info!("断路器进入半开状态");
+                        true
+                    }
+                }
+            }
+            // 半开状态下同一时间只允许一个试探请求；试探超过冷却时间仍无结果时允许重新试探
+            CircuitState::HalfOpen => match self.trial_started {
+                Some(started) if started.elapsed() <= self.timeout => false,
+                _ => {
+                    self.trial_started = Some(Instant::now());
+                    true
+                }
+            },
+            // 正常状态，允许执行
+            CircuitState::Closed => true,
+        }
+    }
+
+    /// 记录一次成功
+    pub fn record_success(&mut self) {
+        self.on_success();
+    }
+
+    /// 记录一次失败
+    pub fn record_failure(&mut self) {
+        self.on_failure();
+    }
+
     fn on_success(&mut self) {
         self.current_failures = 0;
         self.state = CircuitState::Closed;
+        self.trial_started = None;
     }
 
     fn on_failure(&mut self) {
         self.current_failures += 1;
         self.last_failure_time = Some(Instant::now());
+        self.trial_started = None;
 
-        if self.current_failures >= self.failure_threshold {
+        // 半开状态下试探失败立即重新断开
+        if self.state == CircuitState::HalfOpen || self.current_failures >= self.failure_threshold {
             self.state = CircuitState::Open;
             tracing::warn!("断路器开启，失败次数: {}", self.current_failures);
         }
     }
 
+    /// 请求被拒绝时距离可以再次尝试还需等待的时间：开启状态下为冷却剩余时间，
+    /// 半开状态下为正在进行的试探请求的剩余超时
+    pub fn open_remaining(&self) -> Option<Duration> {
+        match (&self.state, self.last_failure_time, self.trial_started) {
+            (CircuitState::Open, Some(last_failure), _) => Some(self.timeout.saturating_sub(last_failure.elapsed())),
+            (CircuitState::HalfOpen, _, Some(started)) => Some(self.timeout.saturating_sub(started.elapsed())),
+            _ => None,
+        }
+    }
+
+    /// 只读地判断现在是否可以尝试请求，不会进入半开状态或占用试探名额
+    pub fn is_available(&self) -> bool {
+        match self.open_remaining() {
+            Some(remaining) => remaining.is_zero(),
+            None => true,
+        }
+    }
+
     pub fn get_state(&self) -> &CircuitState {
         &self.state
     }
 }
 
 /// 网络健康检查器
+#[derive(Clone)]
 pub struct HealthChecker {
     rpc_url: String,
     check_interval: Duration,
     timeout: Duration,
+    limiter: Option<RpcLimiter>,
 }
 
 impl HealthChecker {
@@ -225,11 +291,27 @@ impl HealthChecker {
             rpc_url,
             check_interval: Duration::from_secs(30),
             timeout: Duration::from_secs(10),
+            limiter: None,
         }
     }
 
+    /// 健康检查请求也计入共享的RPC限速器
+    pub fn with_limiter(mut self, limiter: RpcLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// 设置持续监控时的检查间隔
+    pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
     /// 检查网络健康状态
     pub async fn check_health(&self) -> Result<NetworkHealth> {
+        if let Some(ref limiter) = self.limiter {
+            limiter.acquire_async().await;
+        }
         let start = Instant::now();
 
         let client = reqwest::Client::new();
@@ -374,4 +456,55 @@ impl AdaptiveTimeout {
         self.failure_count = 0;
         self.current_timeout = Duration::from_secs(15);
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tripped(timeout: Duration) -> CircuitBreaker {
+        let mut breaker = CircuitBreaker::new(1, timeout);
+        breaker.record_failure();
+        breaker
+    }
+
+    #[test]
+    fn open_breaker_rejects_until_timeout() {
+        let mut breaker = tripped(Duration::from_secs(60));
+        assert_eq!(breaker.get_state(), &CircuitState::Open);
+        assert!(!breaker.is_available());
+        assert!(!breaker.allow_request());
+    }
+
+    #[test]
+    fn availability_check_does_not_take_the_trial() {
+        let mut breaker = tripped(Duration::ZERO);
+        for _ in 0..3 {
+            assert!(breaker.is_available());
+        }
+        assert_eq!(breaker.get_state(), &CircuitState::Open);
+        assert!(breaker.allow_request());
+        assert_eq!(breaker.get_state(), &CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn half_open_allows_a_single_trial() {
+        let mut breaker = tripped(Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        assert!(!breaker.is_available());
+        assert!(!breaker.allow_request());
+        breaker.record_success();
+        assert_eq!(breaker.get_state(), &CircuitState::Closed);
+        assert!(breaker.allow_request());
+    }
+
+    #[test]
+    fn failed_trial_reopens_breaker() {
+        let mut breaker = tripped(Duration::from_millis(50));
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        breaker.record_failure();
+        assert_eq!(breaker.get_state(), &CircuitState::Open);
+        assert!(!breaker.allow_request());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::core::rate_limit::RpcLimiter;
+use crate::core::retry::{CircuitBreaker, CircuitState, HealthChecker, NetworkHealth};
+use crate::core::types::*;
+use anyhow::Result;
+use serde::Serialize;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 每个节点保留的延迟样本数
+const LATENCY_HISTORY_SIZE: usize = 50;
+
+/// 断路器连续失败阈值
+const BREAKER_FAILURE_THRESHOLD: u32 = 3;
+
+/// 断路器开启后多久进入半开状态
+const BREAKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// RPC节点
+pub struct RpcEndpoint {
+    url: String,
+    client: RpcClient,
+    health_checker: HealthChecker,
+    state: Mutex<EndpointState>,
+}
+
+struct EndpointState {
+    breaker: CircuitBreaker,
+    latency_history: VecDeque<f64>,
+    success_count: u64,
+    failure_count: u64,
+    last_health: Option<NetworkHealth>,
+}
+
+/// 节点状态快照，用于展示
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub score: f64,
+    pub circuit_state: String,
+    pub average_latency_ms: f64,
+    pub success_rate: f64,
+    pub healthy: Option<bool>,
+}
+
+impl RpcEndpoint {
+    fn new(url: String, commitment: CommitmentConfig) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(url.clone(), commitment),
+            health_checker: HealthChecker::new(url.clone()),
+            url,
+            state: Mutex::new(EndpointState {
+                breaker: CircuitBreaker::new(BREAKER_FAILURE_THRESHOLD, BREAKER_TIMEOUT),
+                latency_history: VecDeque::new(),
+                success_count: 0,
+                failure_count: 0,
+                last_health: None,
+            }),
+        }
+    }
+
+    /// 节点URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 节点对应的RPC客户端
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    /// 记录一次成功调用
+    pub fn record_success(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.breaker.record_success();
+        state.success_count += 1;
+        state.latency_history.push_back(latency.as_secs_f64() * 1000.0);
+        if state.latency_history.len() > LATENCY_HISTORY_SIZE {
+            state.latency_history.pop_front();
+        }
+    }
+
+    /// 记录一次节点故障
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.breaker.record_failure();
+        state.failure_count += 1;
+    }
+
+    /// 断路器是否允许请求；开启状态超时后会进入半开状态并占用唯一的试探名额
+    fn allow_request(&self) -> bool {
+        self.state.lock().unwrap().breaker.allow_request()
+    }
+
+    /// 断路器现在是否可以尝试，只读，不改变断路器状态
+    fn is_available(&self) -> bool {
+        self.state.lock().unwrap().breaker.is_available()
+    }
+
+    /// 综合评分，越低越好
+    fn score(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        let mut latency = if state.latency_history.is_empty() {
+            state
+                .last_health
+                .as_ref()
+                .map(|health| health.latency.as_secs_f64() * 1000.0)
+                .unwrap_or(500.0)
+        } else {
+            state.latency_history.iter().sum::<f64>() / state.latency_history.len() as f64
+        };
+
+        let total = state.success_count + state.failure_count;
+        let failure_rate = if total == 0 {
+            0.0
+        } else {
+            state.failure_count as f64 / total as f64
+        };
+        latency *= 1.0 + failure_rate * 10.0;
+
+        if matches!(state.last_health, Some(ref health) if !health.is_healthy) {
+            latency += 10_000.0;
+        }
+        match state.breaker.get_state() {
+            CircuitState::Open => latency + 100_000.0,
+            CircuitState::HalfOpen => latency + 1_000.0,
+            CircuitState::Closed => latency,
+        }
+    }
+
+    fn status(&self) -> EndpointStatus {
+        let score = self.score();
+        let state = self.state.lock().unwrap();
+        let total = state.success_count + state.failure_count;
+        EndpointStatus {
+            url: self.url.clone(),
+            score,
+            circuit_state: format!("{:?}", state.breaker.get_state()),
+            average_latency_ms: if state.latency_history.is_empty() {
+                0.0
+            } else {
+                state.latency_history.iter().sum::<f64>() / state.latency_history.len() as f64
+            },
+            success_rate: if total == 0 {
+                1.0
+            } else {
+                state.success_count as f64 / total as f64
+            },
+            healthy: state.last_health.as_ref().map(|health| health.is_healthy),
+        }
+    }
+}
+
+/// 多节点RPC池，按健康度路由请求并在节点故障时切换
+#[derive(Clone)]
+pub struct RpcPool {
+    endpoints: Arc<Vec<Arc<RpcEndpoint>>>,
+    limiter: RpcLimiter,
+}
+
+impl RpcPool {
+    /// 创建RPC池，重复的URL会被忽略
+    pub fn new(urls: Vec<String>, commitment: CommitmentConfig) -> Self {
+        let mut unique: Vec<String> = Vec::new();
+        for url in urls {
+            if !unique.contains(&url) {
+                unique.push(url);
+            }
+        }
+        Self {
+            endpoints: Arc::new(
+                unique
+                    .into_iter()
+                    .map(|url| Arc::new(RpcEndpoint::new(url, commitment)))
+                    .collect(),
+            ),
+            limiter: RpcLimiter::new(RateLimitConfig::default()),
+        }
+    }
+
+    /// 使用指定的限速器，所有经过该池的RPC调用共享
+    pub fn with_limiter(mut self, limiter: RpcLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// 池共享的限速器
+    pub fn limiter(&self) -> &RpcLimiter {
+        &self.limiter
+    }
+
+    /// 单节点RPC池
+    pub fn single(url: String) -> Self {
+        Self::new(vec![url], CommitmentConfig::confirmed())
+    }
+
+    /// 所有节点
+    pub fn endpoints(&self) -> Vec<Arc<RpcEndpoint>> {
+        self.endpoints.iter().cloned().collect()
+    }
+
+    /// 按评分排序的节点
+    pub fn ranked(&self) -> Vec<Arc<RpcEndpoint>> {
+        let mut scored: Vec<(f64, Arc<RpcEndpoint>)> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| (endpoint.score(), endpoint.clone()))
+            .collect();
+        scored.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        scored.into_iter().map(|(_, endpoint)| endpoint).collect()
+    }
+
+    /// 当前最健康的节点
+    pub fn primary(&self) -> Arc<RpcEndpoint> {
+        self.ranked()
+            .into_iter()
+            .next()
+            .expect("RPC池至少需要一个节点")
+    }
+
+    /// 当前最健康节点的URL
+    pub fn primary_url(&self) -> String {
+        self.primary().url.clone()
+    }
+
+    /// 对所有节点执行健康检查
+    pub async fn refresh_health(&self) {
+        for endpoint in self.endpoints.iter() {
+            self.limiter.acquire_async().await;
+            match endpoint.health_checker.check_health().await {
+                Ok(health) => {
+                    let mut state = endpoint.state.lock().unwrap();
+                    if !health.is_healthy {
+                        state.breaker.record_failure();
+                    }
+                    state.last_health = Some(health);
+                }
+                Err(e) => tracing::warn!("节点 {} 健康检查失败: {}", endpoint.url, e),
+            }
+        }
+    }
+
+    /// 在后台定期刷新节点健康状态
+    pub fn spawn_health_monitor(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                pool.refresh_health().await;
+            }
+        })
+    }
+
+    /// 在最健康的节点上执行调用，节点故障时依次切换到下一个节点
+    pub fn call<T, F>(&self, operation: F) -> Result<T>
+    where
+        F: Fn(&RpcClient) -> std::result::Result<T, ClientError>,
+    {
+        let ranked = self.ranked();
+        let available: Vec<Arc<RpcEndpoint>> = ranked
+            .iter()
+            .filter(|endpoint| endpoint.is_available())
+            .cloned()
+            .collect();
+        // 所有节点都被断路时仍然尝试，避免整体不可用
+        let forced = available.is_empty();
+        let candidates = if forced { ranked } else { available };
+
+        let mut last_error = None;
+        for endpoint in candidates.iter() {
+            // 只有真正要发送请求的节点才经过断路器，半开状态的试探名额不会被挑选过程占用
+            if !forced && !endpoint.allow_request() {
+                continue;
+            }
+            self.limiter.acquire();
+            let start = Instant::now();
+            match operation(&endpoint.client) {
+                Ok(value) => {
+                    endpoint.record_success(start.elapsed());
+                    self.limiter.record_success();
+                    return Ok(value);
+                }
+                Err(e) if is_endpoint_failure(&e) => {
+                    tracing::warn!("RPC节点 {} 调用失败，尝试切换节点: {}", endpoint.url, e);
+                    endpoint.record_failure();
+                    last_error = Some(e);
+                }
+                Err(e) => {
+                    // 请求本身的错误，换节点也无济于事
+                    endpoint.record_success(start.elapsed());
+                    return Err(DeployError::from_client_error(&e).into());
+                }
+            }
+        }
+
+        Err(match last_error {
+            // 所有节点都在限流时保留限流信息，由调用方退避
+            Some(e) if matches!(DeployError::from_client_error(&e), DeployError::RateLimited { .. }) => {
+                DeployError::from_client_error(&e).into()
+            }
+            Some(e) => DeployError::Network(format!("所有RPC节点均不可用: {}", e)).into(),
+            None => DeployError::Network("没有可用的RPC节点".to_string()).into(),
+        })
+    }
+
+    /// 在阻塞线程池中执行 [`RpcPool::call`]，供异步路径使用，避免同步RPC请求占住运行时的工作线程
+    pub async fn call_blocking<T, F>(&self, operation: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> std::result::Result<T, ClientError> + Send + 'static,
+    {
+        let pool = self.clone();
+        tokio::task::spawn_blocking(move || pool.call(operation)).await?
+    }
+
+    /// 所有节点的状态快照，按评分排序
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        self.ranked().iter().map(|endpoint| endpoint.status()).collect()
+    }
+}
+
+/// 判断错误是否由节点本身引起（网络故障、限流、节点不健康）
+pub(crate) fn is_endpoint_failure(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,306 @@
+use crate::core::types::*;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// 当前持久化记录的schema版本，只在需要注册迁移的结构变化时递增
+pub const CURRENT_SCHEMA_VERSION: u32 = 7;
+
+/// 带版本号的记录封装
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordEnvelope {
+    pub schema_version: u32,
+    pub record: Value,
+}
+
+/// 无法解析而被隔离的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedRecord {
+    pub key: String,
+    pub reason: String,
+    pub raw: String,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// 单步迁移：把 `from` 版本的记录升级到 `from + 1`。
+/// 只在持久化的结构真正变化（字段改名、改类型、拆分等）时需要注册；
+/// 新增带默认值的可选字段可以直接从旧记录反序列化，无需迁移
+pub type Migration = fn(Value) -> Result<Value>;
+
+/// 解码结果
+pub enum DecodedRecord {
+    /// 记录可用，`migrated` 表示是否需要写回新版本
+    Ready {
+        state: Box<DeploymentState>,
+        migrated: bool,
+    },
+    /// 记录来自更新版本的工具，保持原样不动
+    FromNewerVersion(u32),
+}
+
+/// 迁移注册表
+pub struct MigrationRegistry {
+    migrations: BTreeMap<u32, Migration>,
+}
+
+impl MigrationRegistry {
+    /// 创建空的注册表
+    pub fn empty() -> Self {
+        Self {
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    /// 注册从 `from_version` 升级的迁移
+    pub fn register(&mut self, from_version: u32, migration: Migration) {
+        self.migrations.insert(from_version, migration);
+    }
+
+    /// 把记录逐步升级到当前版本，没有注册迁移的版本之间结构不变
+    pub fn upgrade(&self, version: u32, mut record: Value) -> Result<Value> {
+        for (_, migration) in self.migrations.range(version..CURRENT_SCHEMA_VERSION) {
+            record = migration(record)?;
+        }
+        Ok(record)
+    }
+
+    /// 解码数据库中的原始字节
+    pub fn decode(&self, bytes: &[u8]) -> Result<DecodedRecord> {
+        let value: Value = serde_json::from_slice(bytes)?;
+
+        // 没有封装的记录是版本0（引入版本号之前写入的）
+        let (version, record) = match serde_json::from_value::<RecordEnvelope>(value.clone()) {
+            Ok(envelope) => (envelope.schema_version, envelope.record),
+            Err(_) => (0, value),
+        };
+
+        if version > CURRENT_SCHEMA_VERSION {
+            return Ok(DecodedRecord::FromNewerVersion(version));
+        }
+
+        let record = self.upgrade(version, record)?;
+        let state: DeploymentState = serde_json::from_value(record)?;
+
+        Ok(DecodedRecord::Ready {
+            state: Box::new(state),
+            migrated: version != CURRENT_SCHEMA_VERSION,
+        })
+    }
+
+    /// 解码导出文件，其中的部署记录按导出时的schema版本迁移
+    pub fn decode_bundle(&self, bytes: &[u8]) -> Result<DeploymentBundle> {
+        let mut value: Value = serde_json::from_slice(bytes)?;
+        let version = value
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("导出文件缺少 schema_version"))? as u32;
+
+        if version > CURRENT_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "导出文件的schema版本 {} 高于当前工具支持的版本 {}",
+                version,
+                CURRENT_SCHEMA_VERSION
+            ));
+        }
+
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("导出文件不是JSON对象"))?;
+        let deployment = object
+            .remove("deployment")
+            .ok_or_else(|| anyhow!("导出文件缺少部署记录"))?;
+        object.insert("deployment".to_string(), self.upgrade(version, deployment)?);
+        object.insert("schema_version".to_string(), Value::from(CURRENT_SCHEMA_VERSION));
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// 以当前版本封装并序列化
+    pub fn encode(deployment: &DeploymentState) -> Result<Vec<u8>> {
+        let envelope = RecordEnvelope {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            record: serde_json::to_value(deployment)?,
+        };
+        Ok(serde_json::to_vec(&envelope)?)
+    }
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        // v0 -> v7 之间只增加了版本封装和带默认值的可选字段
+        // （程序指纹、nonce账户、计划条件、自动续传、升级目标、签名角色公钥），
+        // 旧记录可以直接反序列化，目前没有需要转换结构的迁移
+        Self::empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn sample_state() -> DeploymentState {
+        let now = Utc::now();
+        DeploymentState {
+            id: Uuid::new_v4(),
+            program_id: None,
+            program_path: "program.so".to_string(),
+            loader_version: LoaderVersion::V3,
+            total_size: 1024,
+            uploaded_bytes: 512,
+            buffer_accounts: Vec::new(),
+            status: DeploymentStatus::Uploading,
+            created_at: now,
+            updated_at: now,
+            failure_count: 0,
+            last_error: None,
+            network_stats: NetworkStats::default(),
+            cost_stats: CostStats::default(),
+            program_fingerprint: None,
+            nonce_account: None,
+            schedule: None,
+            auto_resume: None,
+            upgrade: None,
+            keys: None,
+        }
+    }
+
+    /// 去掉版本0之后增加的字段，模拟引入版本号之前写入的记录
+    fn legacy_record(state: &DeploymentState) -> Value {
+        let mut record = serde_json::to_value(state).unwrap();
+        let object = record.as_object_mut().unwrap();
+        for field in ["program_fingerprint", "nonce_account", "schedule", "auto_resume", "upgrade", "keys"] {
+            object.remove(field);
+        }
+        record
+    }
+
+    #[test]
+    fn encoded_record_decodes_without_migration() {
+        let state = sample_state();
+        let bytes = MigrationRegistry::encode(&state).unwrap();
+        match MigrationRegistry::default().decode(&bytes).unwrap() {
+            DecodedRecord::Ready { state: decoded, migrated } => {
+                assert!(!migrated);
+                assert_eq!(decoded.id, state.id);
+                assert_eq!(decoded.uploaded_bytes, 512);
+            }
+            DecodedRecord::FromNewerVersion(_) => panic!("当前版本的记录不应被视为更新版本"),
+        }
+    }
+
+    #[test]
+    fn unversioned_record_is_migrated_from_v0() {
+        let state = sample_state();
+        let bytes = serde_json::to_vec(&legacy_record(&state)).unwrap();
+        match MigrationRegistry::default().decode(&bytes).unwrap() {
+            DecodedRecord::Ready { state: decoded, migrated } => {
+                assert!(migrated);
+                assert_eq!(decoded.id, state.id);
+                assert!(decoded.keys.is_none());
+                assert!(decoded.schedule.is_none());
+            }
+            DecodedRecord::FromNewerVersion(_) => panic!("版本0的记录不应被视为更新版本"),
+        }
+    }
+
+    #[test]
+    fn record_from_newer_version_is_left_alone() {
+        let envelope = json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1, "record": { "future": true } });
+        let bytes = serde_json::to_vec(&envelope).unwrap();
+        match MigrationRegistry::default().decode(&bytes).unwrap() {
+            DecodedRecord::FromNewerVersion(version) => assert_eq!(version, CURRENT_SCHEMA_VERSION + 1),
+            DecodedRecord::Ready { .. } => panic!("更新版本的记录不应被解码"),
+        }
+    }
+
+    /// 模拟一次结构变化：上一版本把程序路径存为 `path`，总大小存为字符串
+    fn registry_with_reshape() -> MigrationRegistry {
+        let mut registry = MigrationRegistry::empty();
+        registry.register(CURRENT_SCHEMA_VERSION - 1, |mut record| {
+            let object = record
+                .as_object_mut()
+                .ok_or_else(|| anyhow!("部署记录不是JSON对象"))?;
+            let path = object.remove("path").ok_or_else(|| anyhow!("缺少 path"))?;
+            object.insert("program_path".to_string(), path);
+            let total_size = object
+                .get("total_size")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("total_size 不是字符串"))?
+                .parse::<u64>()?;
+            object.insert("total_size".to_string(), Value::from(total_size));
+            Ok(record)
+        });
+        registry
+    }
+
+    fn reshaped_record(state: &DeploymentState) -> Vec<u8> {
+        let mut record = serde_json::to_value(state).unwrap();
+        let object = record.as_object_mut().unwrap();
+        let path = object.remove("program_path").unwrap();
+        object.insert("path".to_string(), path);
+        object.insert("total_size".to_string(), Value::from(state.total_size.to_string()));
+        serde_json::to_vec(&json!({ "schema_version": CURRENT_SCHEMA_VERSION - 1, "record": record })).unwrap()
+    }
+
+    #[test]
+    fn registered_migration_renames_and_retypes_fields() {
+        let state = sample_state();
+        match registry_with_reshape().decode(&reshaped_record(&state)).unwrap() {
+            DecodedRecord::Ready { state: decoded, migrated } => {
+                assert!(migrated);
+                assert_eq!(decoded.program_path, "program.so");
+                assert_eq!(decoded.total_size, 1024);
+            }
+            DecodedRecord::FromNewerVersion(_) => panic!("旧版本的记录不应被视为更新版本"),
+        }
+        // 不做转换时旧结构无法解析
+        assert!(MigrationRegistry::empty().decode(&reshaped_record(&state)).is_err());
+    }
+
+    #[test]
+    fn migrations_only_run_from_the_record_version() {
+        // 当前版本的记录不经过迁移，否则缺少 path 会报错
+        let bytes = MigrationRegistry::encode(&sample_state()).unwrap();
+        assert!(registry_with_reshape().decode(&bytes).is_ok());
+    }
+
+    fn bundle_json(schema_version: u32, deployment: Value) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "bundle_version": 1,
+            "schema_version": schema_version,
+            "exported_at": Utc::now(),
+            "deployment": deployment,
+            "chunk_ledger": [],
+            "events": [],
+            "program_fingerprint": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn bundle_deployment_is_migrated_to_current_version() {
+        let state = sample_state();
+        let bytes = bundle_json(0, legacy_record(&state));
+        let bundle = MigrationRegistry::default().decode_bundle(&bytes).unwrap();
+        assert_eq!(bundle.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(bundle.deployment.id, state.id);
+        assert!(bundle.deployment.upgrade.is_none());
+    }
+
+    #[test]
+    fn bundle_from_newer_version_is_rejected() {
+        let state = serde_json::to_value(sample_state()).unwrap();
+        let bytes = bundle_json(CURRENT_SCHEMA_VERSION + 1, state);
+        assert!(MigrationRegistry::default().decode_bundle(&bytes).is_err());
+    }
+
+    #[test]
+    fn bundle_without_schema_version_is_rejected() {
+        let bytes = serde_json::to_vec(&json!({ "deployment": {} })).unwrap();
+        assert!(MigrationRegistry::default().decode_bundle(&bytes).is_err());
+    }
+}
@@ -0,0 +1,474 @@
+use crate::core::blockhash::{BlockhashManager, TransactionLifetime};
+use crate::core::rpc_pool::{is_endpoint_failure, RpcPool};
+use crate::core::types::*;
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient as NonblockingRpcClient;
+use solana_client::nonblocking::tpu_client::TpuClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::rpc_request::MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS;
+use solana_client::tpu_client::{TpuClientConfig, DEFAULT_FANOUT_SLOTS};
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 轮询签名状态的间隔
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 交易发送配置
+#[derive(Debug, Clone)]
+pub struct SenderConfig {
+    /// 除RPC外，同时直接发送到即将出块的leader的TPU端口
+    pub use_tpu: bool,
+    /// 发送给未来多少个slot的leader
+    pub tpu_fanout_slots: u64,
+    /// 等待确认期间重新广播的间隔
+    pub rebroadcast_interval: Duration,
+    /// 等待确认的兜底时间，正常情况下在区块哈希过期时就会结束等待
+    pub confirm_timeout: Duration,
+}
+
+impl Default for SenderConfig {
+    fn default() -> Self {
+        Self {
+            use_tpu: false,
+            tpu_fanout_slots: DEFAULT_FANOUT_SLOTS,
+            rebroadcast_interval: Duration::from_secs(2),
+            confirm_timeout: Duration::from_secs(180),
+        }
+    }
+}
+
+/// 单次广播的结果
+#[derive(Debug, Clone)]
+pub struct BroadcastReport {
+    pub signature: Signature,
+    pub rpc_accepted: usize,
+    pub rpc_total: usize,
+    pub tpu_sent: bool,
+}
+
+/// 交易确认状态
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfirmationStatus {
+    Confirmed,
+    Failed(TransactionError),
+    /// 区块哈希已过期且交易未上链
+    Expired,
+    Pending,
+}
+
+/// 保留的确认用时样本数
+const CONFIRMATION_LATENCY_SAMPLES: usize = 100;
+
+/// 本工具交易从首次广播到确认的用时记录；克隆后共享同一组样本
+#[derive(Clone, Default)]
+pub struct ConfirmationTracker {
+    samples: Arc<Mutex<VecDeque<Duration>>>,
+}
+
+impl ConfirmationTracker {
+    /// 记录一笔交易的确认用时
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back(latency);
+        while samples.len() > CONFIRMATION_LATENCY_SAMPLES {
+            samples.pop_front();
+        }
+    }
+
+    /// 平均确认用时，没有样本时返回 None
+    pub fn average(&self) -> Option<Duration> {
+        let samples = self.samples.lock().unwrap();
+        if samples.is_empty() {
+            return None;
+        }
+        Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
+}
+
+/// 交易发送器：把每笔交易广播到RPC池中的所有节点，可选地直接发送到leader的TPU，
+/// 并按签名去重确认结果
+pub struct TransactionSender {
+    rpc_pool: RpcPool,
+    config: SenderConfig,
+    commitment: CommitmentConfig,
+    blockhash_manager: BlockhashManager,
+    tpu_client: Option<TpuClient<QuicPool, QuicConnectionManager, QuicConfig>>,
+    confirmed: Mutex<HashSet<Signature>>,
+    confirmation_tracker: ConfirmationTracker,
+}
+
+impl TransactionSender {
+    /// 创建交易发送器
+    pub fn new(rpc_pool: RpcPool, config: SenderConfig) -> Self {
+        let commitment = CommitmentConfig::confirmed();
+        Self {
+            blockhash_manager: BlockhashManager::new(rpc_pool.clone(), commitment),
+            rpc_pool,
+            config,
+            commitment,
+            tpu_client: None,
+            confirmed: Mutex::new(HashSet::new()),
+            confirmation_tracker: ConfirmationTracker::default(),
+        }
+    }
+
+    /// 使用共享的确认用时记录，供拥堵判定使用
+    pub fn with_confirmation_tracker(mut self, tracker: ConfirmationTracker) -> Self {
+        self.confirmation_tracker = tracker;
+        self
+    }
+
+    /// 签名使用的区块哈希管理器
+    pub fn blockhash_manager(&self) -> &BlockhashManager {
+        &self.blockhash_manager
+    }
+
+    /// 连接leader的TPU端口，未启用TPU发送时不做任何事
+    pub async fn connect_tpu(&mut self) -> Result<()> {
+        if !self.config.use_tpu || self.tpu_client.is_some() {
+            return Ok(());
+        }
+
+        let rpc_url = self.rpc_pool.primary_url();
+        let websocket_url = websocket_url_for(&rpc_url)?;
+        let rpc_client = Arc::new(NonblockingRpcClient::new_with_commitment(
+            rpc_url,
+            self.commitment,
+        ));
+        let tpu_client = TpuClient::new(
+            "sol-deploy-resume-tpu",
+            rpc_client,
+            &websocket_url,
+            TpuClientConfig {
+                fanout_slots: self.config.tpu_fanout_slots,
+            },
+        )
+        .await
+        .map_err(|e| DeployError::Network(format!("连接leader TPU失败: {}", e)))?;
+
+        self.tpu_client = Some(tpu_client);
+        Ok(())
+    }
+
+    /// 把已签名的交易广播到所有RPC节点（以及leader TPU）
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<BroadcastReport> {
+        let signature = *transaction
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("交易尚未签名"))?;
+        let send_config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..RpcSendTransactionConfig::default()
+        };
+
+        let endpoints = self.rpc_pool.endpoints();
+        let rpc_total = endpoints.len();
+        let sends = endpoints.into_iter().map(|endpoint| {
+            let transaction = transaction.clone();
+            let limiter = self.rpc_pool.limiter().clone();
+            tokio::task::spawn_blocking(move || {
+                limiter.acquire();
+                let start = Instant::now();
+                match endpoint
+                    .client()
+                    .send_transaction_with_config(&transaction, send_config)
+                {
+                    Ok(_) => {
+                        endpoint.record_success(start.elapsed());
+                        limiter.record_success();
+                        Ok(())
+                    }
+                    Err(e) => {
+                        if is_endpoint_failure(&e) {
+                            endpoint.record_failure();
+                        } else {
+                            endpoint.record_success(start.elapsed());
+                        }
+                        Err(format!("{}: {}", endpoint.url(), e))
+                    }
+                }
+            })
+        });
+
+        let mut rpc_accepted = 0;
+        let mut last_error = None;
+        for result in futures::future::join_all(sends).await {
+            match result {
+                Ok(Ok(())) => rpc_accepted += 1,
+                Ok(Err(e)) => last_error = Some(e),
+                Err(e) => last_error = Some(e.to_string()),
+            }
+        }
+
+        let tpu_sent = match &self.tpu_client {
+            Some(tpu_client) => tpu_client.send_transaction(transaction).await,
+            None => false,
+        };
+
+        if rpc_accepted == 0 && !tpu_sent {
+            return Err(DeployError::Network(format!(
+                "交易 {} 未被任何节点接受: {}",
+                signature,
+                last_error.unwrap_or_default()
+            ))
+            .into());
+        }
+
+        if let Some(e) = last_error {
+            tracing::debug!("交易 {} 部分节点发送失败: {}", signature, e);
+        }
+
+        Ok(BroadcastReport {
+            signature,
+            rpc_accepted,
+            rpc_total,
+            tpu_sent,
+        })
+    }
+
+    /// 查询一批签名的确认状态，同一签名只会被确认一次；
+    /// `search_history` 为真时也查询早已不在近期状态缓存中的交易
+    pub async fn confirm(
+        &self,
+        signatures: &[Signature],
+        search_history: bool,
+    ) -> Result<HashMap<Signature, ConfirmationStatus>> {
+        let mut results = HashMap::new();
+        let mut pending = Vec::new();
+        {
+            let confirmed = self.confirmed.lock().unwrap();
+            for signature in signatures {
+                if confirmed.contains(signature) {
+                    results.insert(*signature, ConfirmationStatus::Confirmed);
+                } else if !pending.contains(signature) {
+                    pending.push(*signature);
+                }
+            }
+        }
+
+        for batch in pending.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS) {
+            let query = batch.to_vec();
+            let statuses = self
+                .rpc_pool
+                .call_blocking(move |client| {
+                    if search_history {
+                        client.get_signature_statuses_with_history(&query)
+                    } else {
+                        client.get_signature_statuses(&query)
+                    }
+                })
+                .await?
+                .value;
+            for (signature, status) in batch.iter().zip(statuses) {
+                let status = match status {
+                    Some(status) => match status.err {
+                        Some(err) => ConfirmationStatus::Failed(err),
+                        None if status.satisfies_commitment(self.commitment) => {
+                            self.confirmed.lock().unwrap().insert(*signature);
+                            ConfirmationStatus::Confirmed
+                        }
+                        None => ConfirmationStatus::Pending,
+                    },
+                    None => ConfirmationStatus::Pending,
+                };
+                results.insert(*signature, status);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// 广播一批使用同一区块哈希（或nonce）签名的交易并等待确认，等待期间按间隔重新广播尚未确认的交易。
+    /// 返回的状态与传入的交易一一对应：`lifetime` 失效后仍未上链的为 `Expired`，
+    /// 可以安全地重新签名；超过 `confirm_timeout` 时仍可能上链的为 `Pending`
+    pub async fn send_and_confirm_batch(
+        &self,
+        transactions: &[Transaction],
+        lifetime: TransactionLifetime,
+        confirm_timeout: Duration,
+    ) -> Result<Vec<ConfirmationStatus>> {
+        let signatures: Vec<Signature> = transactions
+            .iter()
+            .map(|transaction| transaction.signatures.first().copied().unwrap_or_default())
+            .collect();
+        let mut statuses: HashMap<Signature, ConfirmationStatus> = HashMap::new();
+
+        let start = Instant::now();
+        let mut last_broadcast: Option<Instant> = None;
+        let mut expired = false;
+        // 有效期检查时确认已经上链的交易，即使之后判定过期也不能重新签名
+        let mut landed: HashSet<Signature> = HashSet::new();
+        loop {
+            let unresolved: Vec<usize> = (0..transactions.len())
+                .filter(|&i| {
+                    matches!(
+                        statuses.get(&signatures[i]),
+                        None | Some(ConfirmationStatus::Pending)
+                    )
+                })
+                .collect();
+            if unresolved.is_empty() || expired || start.elapsed() >= confirm_timeout {
+                break;
+            }
+            let query: Vec<Signature> = unresolved.iter().map(|&i| signatures[i]).collect();
+
+            if last_broadcast.map_or(true, |at| at.elapsed() >= self.config.rebroadcast_interval) {
+                // 先检查是否过期再查询状态，保证判定过期之后至少还查询过一次
+                match self.check_lifetime(lifetime, &query).await {
+                    Ok((is_expired, landed_statuses)) => {
+                        expired = is_expired;
+                        for (signature, status) in landed_statuses {
+                            if status == ConfirmationStatus::Confirmed
+                                && statuses.get(&signature) != Some(&ConfirmationStatus::Confirmed)
+                            {
+                                self.confirmation_tracker.record(start.elapsed());
+                            }
+                            landed.insert(signature);
+                            statuses.insert(signature, status);
+                        }
+                    }
+                    Err(e) => tracing::warn!("检查交易有效期失败: {}", e),
+                }
+                // 重新广播算作重试，超出重试预算时本轮只查询状态
+                let within_budget = last_broadcast.is_none() || self.rpc_pool.limiter().try_retry();
+                if !within_budget {
+                    tracing::debug!("重试预算已用尽，推迟重新广播");
+                    last_broadcast = Some(Instant::now());
+                } else if !expired {
+                    for &i in &unresolved {
+                        match self.broadcast(&transactions[i]).await {
+                            Ok(report) => tracing::debug!(
+                                "交易 {} 已广播: RPC {}/{}，TPU {}",
+                                report.signature,
+                                report.rpc_accepted,
+                                report.rpc_total,
+                                report.tpu_sent
+                            ),
+                            Err(e) => tracing::warn!("广播交易失败: {}", e),
+                        }
+                    }
+                    last_broadcast = Some(Instant::now());
+                }
+            }
+
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+            match self.confirm(&query, false).await {
+                Ok(results) => {
+                    for (signature, status) in results {
+                        if status == ConfirmationStatus::Confirmed
+                            && statuses.get(&signature) != Some(&ConfirmationStatus::Confirmed)
+                        {
+                            self.confirmation_tracker.record(start.elapsed());
+                        }
+                        statuses.insert(signature, status);
+                    }
+                }
+                Err(e) => tracing::warn!("查询交易状态失败: {}", e),
+            }
+        }
+
+        Ok(signatures
+            .iter()
+            .map(|signature| match statuses.get(signature) {
+                Some(ConfirmationStatus::Pending) | None if expired && !landed.contains(signature) => {
+                    ConfirmationStatus::Expired
+                }
+                Some(status) => status.clone(),
+                None => ConfirmationStatus::Pending,
+            })
+            .collect())
+    }
+
+    /// 检查一批交易的有效期是否已过，同时返回检查中确认已上链的交易的状态。
+    /// nonce 值变化也可能是本批交易自己上链推进的，而nonce账户与签名状态可能来自不同节点，
+    /// 因此在读取nonce的同一节点上带历史再查询一次签名状态，查到的交易不算过期。
+    /// 这些都是同步RPC请求，在阻塞线程池中执行
+    async fn check_lifetime(
+        &self,
+        lifetime: TransactionLifetime,
+        signatures: &[Signature],
+    ) -> Result<(bool, HashMap<Signature, ConfirmationStatus>)> {
+        let rpc_pool = self.rpc_pool.clone();
+        let blockhash_manager = self.blockhash_manager.clone();
+        let commitment = self.commitment;
+        let signatures = signatures.to_vec();
+        let (expired, landed) = tokio::task::spawn_blocking(move || {
+            let TransactionLifetime::DurableNonce { account, nonce } = lifetime else {
+                return Ok((blockhash_manager.is_lifetime_expired(&lifetime)?, HashMap::new()));
+            };
+            let endpoint = rpc_pool.primary();
+            if blockhash_manager.durable_nonce_from(endpoint.client(), &account)?.blockhash() == nonce {
+                return Ok((false, HashMap::new()));
+            }
+
+            let mut landed = HashMap::new();
+            for batch in signatures.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS) {
+                rpc_pool.limiter().acquire();
+                let statuses = endpoint.client().get_signature_statuses_with_history(batch)?.value;
+                for (signature, status) in batch.iter().zip(statuses) {
+                    let Some(status) = status else {
+                        continue;
+                    };
+                    let status = match status.err {
+                        Some(err) => ConfirmationStatus::Failed(err),
+                        None if status.satisfies_commitment(commitment) => ConfirmationStatus::Confirmed,
+                        None => ConfirmationStatus::Pending,
+                    };
+                    landed.insert(*signature, status);
+                }
+            }
+            Ok::<_, anyhow::Error>((true, landed))
+        })
+        .await??;
+
+        let mut confirmed = self.confirmed.lock().unwrap();
+        for (signature, status) in &landed {
+            if *status == ConfirmationStatus::Confirmed {
+                confirmed.insert(*signature);
+            }
+        }
+        Ok((expired, landed))
+    }
+
+    /// 广播单笔交易并等待确认
+    pub async fn send_and_confirm(&self, transaction: &Transaction, lifetime: TransactionLifetime) -> Result<Signature> {
+        let signature = *transaction
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("交易尚未签名"))?;
+        let statuses = self
+            .send_and_confirm_batch(std::slice::from_ref(transaction), lifetime, self.config.confirm_timeout)
+            .await?;
+        match statuses.into_iter().next() {
+            Some(ConfirmationStatus::Confirmed) => Ok(signature),
+            Some(ConfirmationStatus::Failed(err)) => {
+                tracing::warn!("交易 {} 执行失败: {}", signature, err);
+                Err(DeployError::from_transaction_error(&err).into())
+            }
+            Some(ConfirmationStatus::Expired) => Err(DeployError::BlockhashExpired(format!(
+                "交易 {} 未在有效期内上链",
+                signature
+            ))
+            .into()),
+            _ => Err(DeployError::Network(format!("交易 {} 在超时前未被确认", signature)).into()),
+        }
+    }
+}
+
+/// 由RPC地址推导websocket地址（与 Solana CLI 相同：协议换成 ws/wss，显式端口加一）
+fn websocket_url_for(rpc_url: &str) -> Result<String> {
+    let mut url = url::Url::parse(rpc_url)?;
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow!("无法为 {} 推导websocket地址", rpc_url))?;
+    if let Some(port) = url.port() {
+        url.set_port(Some(port + 1))
+            .map_err(|_| anyhow!("无法为 {} 推导websocket地址", rpc_url))?;
+    }
+    Ok(url.to_string())
+}
@@ -0,0 +1,311 @@
+use crate::core::types::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::fmt;
+
+/// 部署中需要签名的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRole {
+    FeePayer,
+    BufferAuthority,
+    UpgradeAuthority,
+    Program,
+}
+
+impl KeyRole {
+    /// 提供该角色密钥对的命令行参数
+    pub fn flag(&self) -> &'static str {
+        match self {
+            KeyRole::FeePayer => "--fee-payer",
+            KeyRole::BufferAuthority => "--buffer-authority",
+            KeyRole::UpgradeAuthority => "--upgrade-authority",
+            KeyRole::Program => "--program-keypair",
+        }
+    }
+
+    /// clap 中对应参数的名称
+    pub fn arg_name(&self) -> &'static str {
+        match self {
+            KeyRole::FeePayer => "fee_payer",
+            KeyRole::BufferAuthority => "buffer_authority",
+            KeyRole::UpgradeAuthority => "upgrade_authority",
+            KeyRole::Program => "program_keypair",
+        }
+    }
+}
+
+impl fmt::Display for KeyRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeyRole::FeePayer => "付款账户",
+            KeyRole::BufferAuthority => "buffer授权",
+            KeyRole::UpgradeAuthority => "升级授权",
+            KeyRole::Program => "程序密钥对",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 一次部署操作持有的密钥对，没有单独提供的授权角色由付款账户兼任
+pub struct DeploymentSigners {
+    fee_payer: Keypair,
+    buffer_authority: Option<Keypair>,
+    upgrade_authority: Option<Keypair>,
+    program: Option<Keypair>,
+}
+
+impl DeploymentSigners {
+    pub fn new(fee_payer: Keypair) -> Self {
+        Self {
+            fee_payer,
+            buffer_authority: None,
+            upgrade_authority: None,
+            program: None,
+        }
+    }
+
+    pub fn with_buffer_authority(mut self, keypair: Keypair) -> Self {
+        self.buffer_authority = Some(keypair);
+        self
+    }
+
+    pub fn with_upgrade_authority(mut self, keypair: Keypair) -> Self {
+        self.upgrade_authority = Some(keypair);
+        self
+    }
+
+    pub fn with_program(mut self, keypair: Keypair) -> Self {
+        self.program = Some(keypair);
+        self
+    }
+
+    pub fn fee_payer(&self) -> &Keypair {
+        &self.fee_payer
+    }
+
+    pub fn buffer_authority(&self) -> &Keypair {
+        self.buffer_authority.as_ref().unwrap_or(&self.fee_payer)
+    }
+
+    pub fn upgrade_authority(&self) -> &Keypair {
+        self.upgrade_authority.as_ref().unwrap_or(&self.fee_payer)
+    }
+
+    pub fn program(&self) -> Option<&Keypair> {
+        self.program.as_ref()
+    }
+
+    /// 某个角色当前持有的密钥对
+    pub fn get(&self, role: KeyRole) -> Option<&Keypair> {
+        match role {
+            KeyRole::FeePayer => Some(self.fee_payer()),
+            KeyRole::BufferAuthority => Some(self.buffer_authority()),
+            KeyRole::UpgradeAuthority => Some(self.upgrade_authority()),
+            KeyRole::Program => self.program(),
+        }
+    }
+
+    /// 新部署记录的各角色公钥
+    pub fn keys(&self) -> DeploymentKeys {
+        DeploymentKeys {
+            fee_payer: self.fee_payer.pubkey(),
+            buffer_authority: self.buffer_authority().pubkey(),
+            upgrade_authority: self.upgrade_authority().pubkey(),
+            program: self.program.as_ref().map(|keypair| keypair.pubkey()),
+        }
+    }
+
+    /// 部署剩余步骤需要、但当前没有对应密钥对的角色
+    pub fn missing(&self, deployment: &DeploymentState) -> Vec<(KeyRole, Pubkey)> {
+        required_keys(deployment)
+            .into_iter()
+            .filter(|(role, pubkey)| self.get(*role).map(|keypair| keypair.pubkey()) != Some(*pubkey))
+            .collect()
+    }
+}
+
+/// 部署剩余步骤需要签名的角色及其公钥：
+/// 上传未完成时需要buffer授权写入，完成部署需要升级授权，buffer授权与升级授权不同时
+/// 还需要buffer授权在部署前移交buffer；指定了程序地址且程序账户尚未创建时需要程序密钥对
+pub fn required_keys(deployment: &DeploymentState) -> Vec<(KeyRole, Pubkey)> {
+    let Some(ref keys) = deployment.keys else {
+        return Vec::new();
+    };
+    let mut required = vec![(KeyRole::FeePayer, keys.fee_payer)];
+
+    let target = deployment
+        .buffer_accounts
+        .last()
+        .filter(|buffer| !buffer.status.is_abandoned());
+    let upload_done = target.is_some_and(|buffer| {
+        buffer.status == BufferStatus::Completed || deployment.uploaded_bytes >= deployment.total_size
+    });
+    let buffer_authority = target
+        .and_then(|buffer| buffer.authority)
+        .unwrap_or(keys.buffer_authority);
+    if !upload_done || buffer_authority != keys.upgrade_authority {
+        required.push((KeyRole::BufferAuthority, buffer_authority));
+    }
+    required.push((KeyRole::UpgradeAuthority, keys.upgrade_authority));
+
+    let creates_program = match deployment.loader_version {
+        LoaderVersion::V3 => deployment.upgrade.is_none(),
+        LoaderVersion::V4 => target.is_none() && deployment.upgrade.is_none(),
+    };
+    if let Some(program) = keys.program.filter(|_| creates_program) {
+        required.push((KeyRole::Program, program));
+    }
+    required
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn deployment(loader_version: LoaderVersion, keys: Option<DeploymentKeys>) -> DeploymentState {
+        let now = Utc::now();
+        DeploymentState {
+            id: Uuid::new_v4(),
+            program_id: None,
+            program_path: "program.so".to_string(),
+            loader_version,
+            total_size: 1024,
+            uploaded_bytes: 0,
+            buffer_accounts: Vec::new(),
+            status: DeploymentStatus::Uploading,
+            created_at: now,
+            updated_at: now,
+            failure_count: 0,
+            last_error: None,
+            network_stats: NetworkStats::default(),
+            cost_stats: CostStats::default(),
+            program_fingerprint: None,
+            nonce_account: None,
+            schedule: None,
+            auto_resume: None,
+            upgrade: None,
+            keys,
+        }
+    }
+
+    fn separate_keys() -> DeploymentKeys {
+        DeploymentKeys {
+            fee_payer: Pubkey::new_unique(),
+            buffer_authority: Pubkey::new_unique(),
+            upgrade_authority: Pubkey::new_unique(),
+            program: Some(Pubkey::new_unique()),
+        }
+    }
+
+    fn buffer(status: BufferStatus, authority: Option<Pubkey>) -> BufferInfo {
+        BufferInfo {
+            pubkey: Pubkey::new_unique(),
+            size: 1024,
+            uploaded_size: 0,
+            offset: 0,
+            status,
+            created_at: Utc::now(),
+            authority,
+        }
+    }
+
+    fn roles(required: &[(KeyRole, Pubkey)]) -> Vec<KeyRole> {
+        required.iter().map(|(role, _)| *role).collect()
+    }
+
+    #[test]
+    fn legacy_deployment_requires_no_extra_keys() {
+        assert!(required_keys(&deployment(LoaderVersion::V3, None)).is_empty());
+    }
+
+    #[test]
+    fn fresh_deployment_requires_every_role() {
+        let keys = separate_keys();
+        let required = required_keys(&deployment(LoaderVersion::V3, Some(keys.clone())));
+        assert_eq!(
+            required,
+            vec![
+                (KeyRole::FeePayer, keys.fee_payer),
+                (KeyRole::BufferAuthority, keys.buffer_authority),
+                (KeyRole::UpgradeAuthority, keys.upgrade_authority),
+                (KeyRole::Program, keys.program.unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn finished_upload_needs_buffer_authority_only_to_hand_over() {
+        let keys = separate_keys();
+        let mut state = deployment(LoaderVersion::V3, Some(keys.clone()));
+        state.buffer_accounts.push(buffer(BufferStatus::Completed, Some(keys.buffer_authority)));
+        assert!(roles(&required_keys(&state)).contains(&KeyRole::BufferAuthority));
+
+        // buffer已经移交给升级授权，不再需要buffer授权
+        state.buffer_accounts[0].authority = Some(keys.upgrade_authority);
+        assert!(!roles(&required_keys(&state)).contains(&KeyRole::BufferAuthority));
+    }
+
+    #[test]
+    fn buffer_authority_comes_from_buffer_record() {
+        let keys = separate_keys();
+        let recorded = Pubkey::new_unique();
+        let mut state = deployment(LoaderVersion::V3, Some(keys));
+        state.buffer_accounts.push(buffer(BufferStatus::Uploading, Some(recorded)));
+        assert!(required_keys(&state).contains(&(KeyRole::BufferAuthority, recorded)));
+    }
+
+    #[test]
+    fn abandoned_buffer_is_not_the_target() {
+        let keys = separate_keys();
+        for status in [BufferStatus::Failed, BufferStatus::Transferred] {
+            let mut state = deployment(LoaderVersion::V4, Some(keys.clone()));
+            state.uploaded_bytes = state.total_size;
+            state.buffer_accounts.push(buffer(status, Some(Pubkey::new_unique())));
+            let required = required_keys(&state);
+            assert!(required.contains(&(KeyRole::BufferAuthority, keys.buffer_authority)));
+            // 没有可用的程序账户，Loader v4 仍需创建程序
+            assert!(roles(&required).contains(&KeyRole::Program));
+        }
+    }
+
+    #[test]
+    fn program_keypair_only_needed_before_program_account_exists() {
+        let keys = separate_keys();
+        let mut state = deployment(LoaderVersion::V4, Some(keys.clone()));
+        state.buffer_accounts.push(buffer(BufferStatus::Uploading, None));
+        assert!(!roles(&required_keys(&state)).contains(&KeyRole::Program));
+
+        let mut upgrade = deployment(LoaderVersion::V3, Some(keys));
+        upgrade.upgrade = Some(ProgramUpgrade {
+            program_id: Pubkey::new_unique(),
+            delta: false,
+            previous_size: 0,
+            unchanged_bytes: 0,
+        });
+        assert!(!roles(&required_keys(&upgrade)).contains(&KeyRole::Program));
+    }
+
+    #[test]
+    fn missing_lists_roles_without_matching_keypair() {
+        let fee_payer = Keypair::new();
+        let buffer_authority = Keypair::new();
+        let upgrade_authority = Keypair::new();
+        let keys = DeploymentKeys {
+            fee_payer: fee_payer.pubkey(),
+            buffer_authority: buffer_authority.pubkey(),
+            upgrade_authority: upgrade_authority.pubkey(),
+            program: None,
+        };
+        let state = deployment(LoaderVersion::V3, Some(keys));
+
+        let signers = DeploymentSigners::new(fee_payer).with_buffer_authority(buffer_authority);
+        assert_eq!(
+            signers.missing(&state),
+            vec![(KeyRole::UpgradeAuthority, upgrade_authority.pubkey())]
+        );
+        let signers = signers.with_upgrade_authority(upgrade_authority);
+        assert!(signers.missing(&state).is_empty());
+    }
+}
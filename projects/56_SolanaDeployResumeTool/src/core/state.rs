@@ -1,33 +1,161 @@
+use crate::core::schema::{DecodedRecord, MigrationRegistry, QuarantinedRecord, CURRENT_SCHEMA_VERSION};
 use crate::core::types::*;
+use crate::core::tuning::UploadTuning;
 use anyhow::Result;
-use chrono::Utc;
-// use serde::{Deserialize, Serialize};
-use sled::Db;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+use solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+/// 默认租约有效期（秒）
+pub const DEFAULT_LEASE_TTL_SECS: i64 = 300;
+
+/// 网络状况样本保留天数
+pub const NETWORK_HISTORY_RETENTION_DAYS: i64 = 28;
+
 /// 状态管理器
 pub struct StateManager {
     db: Db,
+    leases: Tree,
+    quarantine: Tree,
+    chunks: Tree,
+    events: Tree,
+    network_samples: Tree,
+    upload_tuning: Tree,
+    buffer_registry: Tree,
     deployments: HashMap<Uuid, DeploymentState>,
+    process_id: String,
+    owner_file: PathBuf,
+}
+
+/// 持有数据库的进程信息，写在数据库旁边，便于在加锁失败时给出提示
+#[derive(Debug, Serialize, Deserialize)]
+struct ProcessOwner {
+    pid: u32,
+    command: String,
+    started_at: DateTime<Utc>,
 }
 
 impl StateManager {
     /// 创建新的状态管理器
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let db = sled::open(db_path)?;
+        let db_path = db_path.as_ref();
+        let owner_file = PathBuf::from(format!("{}.owner", db_path.display()));
+        
+        // sled 会对数据库文件加排他锁，被占用时转换为明确的错误
+        let db = match sled::open(db_path) {
+            Ok(db) => db,
+            Err(sled::Error::Io(e)) if e.to_string().contains("could not acquire lock") => {
+                return Err(DeployError::DatabaseLocked {
+                    path: db_path.display().to_string(),
+                    holder: Self::describe_owner(&owner_file),
+                }
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        
+        let owner = ProcessOwner {
+            pid: std::process::id(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            started_at: Utc::now(),
+        };
+        std::fs::write(&owner_file, serde_json::to_vec(&owner)?)?;
+        let process_id = format!("pid-{}-{}", owner.pid, &Uuid::new_v4().simple().to_string()[..8]);
+        
+        let leases = db.open_tree("leases")?;
+        let quarantine = db.open_tree("quarantine")?;
+        let chunks = db.open_tree("chunks")?;
+        let events = db.open_tree("events")?;
+        let network_samples = db.open_tree("network_samples")?;
+        let upload_tuning = db.open_tree("upload_tuning")?;
+        let buffer_registry = db.open_tree("buffer_registry")?;
+        let migrations = MigrationRegistry::default();
         let mut deployments = HashMap::new();
         
-        // 从数据库加载现有部署状态
+        // 从数据库加载现有部署状态，旧版本记录会被迁移，无法解析的记录会被隔离
         for item in db.iter() {
             let (key, value) = item?;
-            let deployment_id = Uuid::from_slice(&key)?;
-            let deployment_state: DeploymentState = serde_json::from_slice(&value)?;
-            deployments.insert(deployment_id, deployment_state);
+            let decoded = Uuid::from_slice(&key)
+                .map_err(anyhow::Error::from)
+                .and_then(|id| migrations.decode(&value).map(|record| (id, record)));
+            
+            match decoded {
+                Ok((deployment_id, DecodedRecord::Ready { state, migrated })) => {
+                    if migrated {
+                        db.insert(&key, MigrationRegistry::encode(&state)?)?;
+                        tracing::info!("部署记录 {} 已迁移到当前schema版本", deployment_id);
+                    }
+                    deployments.insert(deployment_id, *state);
+                }
+                Ok((deployment_id, DecodedRecord::FromNewerVersion(version))) => {
+                    tracing::warn!(
+                        "部署记录 {} 的schema版本 {} 高于当前工具支持的版本，已跳过",
+                        deployment_id,
+                        version
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!("无法解析部署记录 {}，已隔离: {}", hex::encode(&key), e);
+                    let record = QuarantinedRecord {
+                        key: hex::encode(&key),
+                        reason: e.to_string(),
+                        raw: String::from_utf8_lossy(&value).to_string(),
+                        quarantined_at: Utc::now(),
+                    };
+                    quarantine.insert(&key, serde_json::to_vec(&record)?)?;
+                    db.remove(&key)?;
+                }
+            }
         }
+        db.flush()?;
         
-        Ok(Self { db, deployments })
+        Ok(Self {
+            db,
+            leases,
+            quarantine,
+            chunks,
+            events,
+            network_samples,
+            upload_tuning,
+            buffer_registry,
+            deployments,
+            process_id,
+            owner_file,
+        })
+    }
+    
+    /// 读取占用数据库的进程信息
+    fn describe_owner(owner_file: &Path) -> String {
+        std::fs::read(owner_file)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<ProcessOwner>(&bytes).ok())
+            .map(|owner| {
+                format!(
+                    "（进程 {}，命令 `{}`，启动于 {}）",
+                    owner.pid,
+                    owner.command,
+                    owner.started_at.format("%Y-%m-%d %H:%M:%S")
+                )
+            })
+            .unwrap_or_default()
+    }
+    
+    /// 只读视图，可交给与本管理器同时运行的任务（如Web服务器）使用
+    pub fn reader(&self) -> StateReader {
+        StateReader {
+            db: self.db.clone(),
+            chunks: self.chunks.clone(),
+            upload_tuning: self.upload_tuning.clone(),
+        }
+    }
+    
+    /// 本次打开数据库的进程标识，本进程获取的租约都带有该标识
+    pub fn process_id(&self) -> &str {
+        &self.process_id
     }
     
     /// 创建新的部署状态
@@ -50,6 +178,12 @@ impl StateManager {
             last_error: None,
             network_stats: NetworkStats::default(),
             cost_stats: CostStats::default(),
+            program_fingerprint: None,
+            nonce_account: None,
+            schedule: None,
+            auto_resume: None,
+            upgrade: None,
+            keys: None,
         };
         
         self.deployments.insert(deployment_id, deployment_state.clone());
@@ -138,6 +272,36 @@ impl StateManager {
         Ok(())
     }
     
+    /// 排队一个计划部署，由 watch 守护循环在条件满足时开始
+    pub fn schedule_deployment(
+        &mut self,
+        program_path: String,
+        loader_version: LoaderVersion,
+        fingerprint: ProgramFingerprint,
+        schedule: DeploymentSchedule,
+    ) -> Result<Uuid> {
+        let deployment_id = self.create_deployment(program_path, loader_version)?;
+        if let Some(mut deployment) = self.deployments.get(&deployment_id).cloned() {
+            deployment.total_size = fingerprint.size;
+            deployment.program_fingerprint = Some(fingerprint);
+            deployment.status = DeploymentStatus::Scheduled;
+            deployment.schedule = Some(schedule);
+            self.update_deployment(deployment)?;
+        }
+        Ok(deployment_id)
+    }
+
+    /// 等待执行的计划部署，按创建时间排序
+    pub fn get_scheduled_deployments(&self) -> Vec<&DeploymentState> {
+        let mut scheduled: Vec<&DeploymentState> = self
+            .deployments
+            .values()
+            .filter(|deployment| deployment.status == DeploymentStatus::Scheduled)
+            .collect();
+        scheduled.sort_by_key(|deployment| deployment.created_at);
+        scheduled
+    }
+
     /// 查找可续传的部署
     pub fn find_resumable_deployments(&self) -> Vec<&DeploymentState> {
         self.deployments
@@ -168,6 +332,7 @@ impl StateManager {
         for id in to_remove {
             self.deployments.remove(&id);
             self.db.remove(id.as_bytes())?;
+            self.remove_related_records(&id)?;
             removed_count += 1;
         }
         
@@ -219,7 +384,7 @@ impl StateManager {
     
     /// 保存部署状态到数据库
     fn save_deployment(&self, deployment: &DeploymentState) -> Result<()> {
-        let value = serde_json::to_vec(deployment)?;
+        let value = MigrationRegistry::encode(deployment)?;
         self.db.insert(deployment.id.as_bytes(), value)?;
         Ok(())
     }
@@ -229,7 +394,595 @@ impl StateManager {
         let existed = self.deployments.remove(id).is_some();
         if existed {
             self.db.remove(id.as_bytes())?;
+            self.remove_related_records(id)?;
         }
         Ok(existed)
     }
-} 
\ No newline at end of file
+    
+    /// 删除部署关联的数据块账本、事件和租约
+    fn remove_related_records(&self, id: &Uuid) -> Result<()> {
+        for tree in [&self.chunks, &self.events] {
+            for item in tree.scan_prefix(id.as_bytes()) {
+                let (key, _) = item?;
+                tree.remove(key)?;
+            }
+        }
+        self.leases.remove(id.as_bytes())?;
+        Ok(())
+    }
+    
+    /// 记录数据块状态（按块序号覆盖）
+    pub fn record_chunk(&self, id: &Uuid, chunk: &ChunkRecord) -> Result<()> {
+        let mut key = id.as_bytes().to_vec();
+        key.extend_from_slice(&chunk.index.to_be_bytes());
+        self.chunks.insert(key, serde_json::to_vec(chunk)?)?;
+        Ok(())
+    }
+    
+    /// 获取部署的数据块账本，按块序号排列
+    pub fn get_chunk_ledger(&self, id: &Uuid) -> Vec<ChunkRecord> {
+        read_chunk_ledger(&self.chunks, id)
+    }
+    
+    /// 用重新分块后的记录覆盖账本，并删除多出来的旧记录
+    pub fn rewrite_chunk_ledger(&self, id: &Uuid, records: &[ChunkRecord]) -> Result<()> {
+        for record in records {
+            self.record_chunk(id, record)?;
+        }
+        for item in self.chunks.scan_prefix(id.as_bytes()) {
+            let (key, _) = item?;
+            let index = key[id.as_bytes().len()..]
+                .try_into()
+                .map(u32::from_be_bytes)
+                .unwrap_or(u32::MAX);
+            if index as usize >= records.len() {
+                self.chunks.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// 清空部署的数据块账本（上传目标账户重建后旧记录不再有效）
+    pub fn reset_chunk_ledger(&self, id: &Uuid) -> Result<()> {
+        for item in self.chunks.scan_prefix(id.as_bytes()) {
+            let (key, _) = item?;
+            self.chunks.remove(key)?;
+        }
+        Ok(())
+    }
+    
+    /// 记录部署事件
+    pub fn log_event(
+        &self,
+        id: &Uuid,
+        event_type: EventType,
+        message: String,
+        data: Option<serde_json::Value>,
+    ) -> Result<DeploymentEvent> {
+        let event = DeploymentEvent {
+            id: Uuid::new_v4(),
+            deployment_id: *id,
+            event_type,
+            message,
+            data,
+            timestamp: Utc::now(),
+        };
+        self.insert_event(&event)?;
+        Ok(event)
+    }
+    
+    /// 写入事件，键按时间排序
+    fn insert_event(&self, event: &DeploymentEvent) -> Result<()> {
+        let mut key = event.deployment_id.as_bytes().to_vec();
+        key.extend_from_slice(&event.timestamp.timestamp_micros().to_be_bytes());
+        key.extend_from_slice(event.id.as_bytes());
+        self.events.insert(key, serde_json::to_vec(event)?)?;
+        Ok(())
+    }
+    
+    /// 获取部署的事件记录，按时间排列
+    pub fn get_events(&self, id: &Uuid) -> Vec<DeploymentEvent> {
+        self.events
+            .scan_prefix(id.as_bytes())
+            .filter_map(|item| item.ok())
+            .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+            .collect()
+    }
+    
+    /// 保存网络状况样本，并清理超过保留期的旧样本，键按集群和时间排序
+    pub fn record_network_sample(&self, sample: &NetworkSample) -> Result<()> {
+        self.network_samples
+            .insert(Self::network_sample_key(&sample.cluster, &sample.timestamp), serde_json::to_vec(sample)?)?;
+        
+        let cutoff = Utc::now() - chrono::Duration::days(NETWORK_HISTORY_RETENTION_DAYS);
+        let mut start = sample.cluster.as_bytes().to_vec();
+        start.push(0);
+        let end = Self::network_sample_key(&sample.cluster, &cutoff);
+        for item in self.network_samples.range(start..end) {
+            let (key, _) = item?;
+            self.network_samples.remove(key)?;
+        }
+        Ok(())
+    }
+    
+    /// 获取集群的网络状况样本，按时间排列
+    pub fn get_network_samples(&self, cluster: &str) -> Vec<NetworkSample> {
+        let mut prefix = cluster.as_bytes().to_vec();
+        prefix.push(0);
+        self.network_samples
+            .scan_prefix(prefix)
+            .filter_map(|item| item.ok())
+            .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+            .collect()
+    }
+    
+    fn network_sample_key(cluster: &str, timestamp: &DateTime<Utc>) -> Vec<u8> {
+        let mut key = cluster.as_bytes().to_vec();
+        key.push(0);
+        // 偏移到非负数，保证大端字节序与时间顺序一致
+        key.extend_from_slice(&((timestamp.timestamp_micros() as u64) ^ (1 << 63)).to_be_bytes());
+        key
+    }
+    
+    /// 保存在某个RPC节点上学到的上传参数
+    pub fn save_upload_tuning(&self, tuning: &UploadTuning) -> Result<()> {
+        self.upload_tuning
+            .insert(tuning.endpoint.as_bytes(), serde_json::to_vec(tuning)?)?;
+        Ok(())
+    }
+    
+    /// 获取某个RPC节点上学到的上传参数
+    pub fn get_upload_tuning(&self, endpoint: &str) -> Option<UploadTuning> {
+        read_upload_tuning(&self.upload_tuning, endpoint)
+    }
+    
+    /// 登记写满程序数据的buffer，同一buffer重复登记时覆盖旧记录
+    pub fn register_reusable_buffer(&self, entry: &ReusableBuffer) -> Result<()> {
+        self.buffer_registry.insert(
+            Self::reusable_buffer_key(&entry.program_hash, &entry.buffer.pubkey),
+            serde_json::to_vec(entry)?,
+        )?;
+        Ok(())
+    }
+    
+    /// 获取为某个程序登记的可复用buffer，最近登记的在前
+    pub fn get_reusable_buffers(&self, program_hash: &str) -> Vec<ReusableBuffer> {
+        let mut prefix = program_hash.as_bytes().to_vec();
+        prefix.push(0);
+        let mut buffers: Vec<ReusableBuffer> = self
+            .buffer_registry
+            .scan_prefix(prefix)
+            .filter_map(|item| item.ok())
+            .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+            .collect();
+        buffers.sort_by(|a, b| b.registered_at.cmp(&a.registered_at));
+        buffers
+    }
+    
+    /// 移除已被部署消耗或已失效的buffer记录
+    pub fn remove_reusable_buffer(&self, program_hash: &str, buffer: &Pubkey) -> Result<bool> {
+        Ok(self
+            .buffer_registry
+            .remove(Self::reusable_buffer_key(program_hash, buffer))?
+            .is_some())
+    }
+    
+    /// 清理登记时间早于保留期的buffer记录
+    pub fn cleanup_expired_buffers(&self, hours_to_keep: i64) -> Result<usize> {
+        let cutoff_time = Utc::now() - chrono::Duration::hours(hours_to_keep);
+        let mut removed_count = 0;
+        for item in self.buffer_registry.iter() {
+            let (key, value) = item?;
+            let expired = serde_json::from_slice::<ReusableBuffer>(&value)
+                .map(|entry| entry.registered_at < cutoff_time)
+                .unwrap_or(true);
+            if expired {
+                self.buffer_registry.remove(key)?;
+                removed_count += 1;
+            }
+        }
+        Ok(removed_count)
+    }
+    
+    fn reusable_buffer_key(program_hash: &str, buffer: &Pubkey) -> Vec<u8> {
+        let mut key = program_hash.as_bytes().to_vec();
+        key.push(0);
+        key.extend_from_slice(buffer.as_ref());
+        key
+    }
+    
+    /// 导出部署记录
+    pub fn export_bundle(&self, id: &Uuid) -> Result<DeploymentBundle> {
+        let deployment = self
+            .deployments
+            .get(id)
+            .cloned()
+            .ok_or_else(|| DeployError::StateManagement(format!("部署不存在: {}", id)))?;
+        
+        Ok(DeploymentBundle {
+            bundle_version: DEPLOYMENT_BUNDLE_VERSION,
+            schema_version: CURRENT_SCHEMA_VERSION,
+            exported_at: Utc::now(),
+            program_fingerprint: deployment.program_fingerprint.clone(),
+            chunk_ledger: self.get_chunk_ledger(id),
+            events: self.get_events(id),
+            deployment,
+        })
+    }
+    
+    /// 导入部署记录，`overwrite` 为 false 时拒绝覆盖已有部署
+    pub fn import_bundle(&mut self, bundle: DeploymentBundle, overwrite: bool) -> Result<Uuid> {
+        if bundle.bundle_version > DEPLOYMENT_BUNDLE_VERSION {
+            return Err(DeployError::StateManagement(format!(
+                "导出文件版本 {} 高于当前工具支持的版本 {}",
+                bundle.bundle_version, DEPLOYMENT_BUNDLE_VERSION
+            ))
+            .into());
+        }
+        
+        let id = bundle.deployment.id;
+        if self.deployments.contains_key(&id) {
+            if !overwrite {
+                return Err(DeployError::StateManagement(format!("部署 {} 已存在", id)).into());
+            }
+            self.delete_deployment(&id)?;
+        }
+        
+        let mut deployment = bundle.deployment;
+        deployment.program_fingerprint = bundle.program_fingerprint.or(deployment.program_fingerprint);
+        // 导出时仍在上传的部署在新机器上视为暂停，等待续传
+        if matches!(deployment.status, DeploymentStatus::Initializing | DeploymentStatus::Uploading) {
+            deployment.status = DeploymentStatus::Paused;
+        }
+        
+        for chunk in &bundle.chunk_ledger {
+            self.record_chunk(&id, chunk)?;
+        }
+        for event in &bundle.events {
+            self.insert_event(event)?;
+        }
+        self.deployments.insert(id, deployment.clone());
+        self.save_deployment(&deployment)?;
+        
+        Ok(id)
+    }
+    
+    /// 获取部署租约，每次获取都是一个新的持有者，同一进程内的其他工作者也不能再获取；
+    /// 已被其他未过期的持有者占用时返回错误。
+    /// 数据库由本进程独占打开，其他进程留下的租约说明其持有者已退出（包括崩溃），直接接管
+    pub fn acquire_lease(&self, id: &Uuid, ttl_secs: i64) -> Result<DeploymentLease> {
+        let owner = format!("{}/{}", self.process_id, &Uuid::new_v4().simple().to_string()[..8]);
+        self.write_lease(id, owner, ttl_secs)
+    }
+    
+    /// 以指定持有者写入租约
+    fn write_lease(&self, id: &Uuid, owner: String, ttl_secs: i64) -> Result<DeploymentLease> {
+        let now = Utc::now();
+        let lease = DeploymentLease {
+            deployment_id: *id,
+            owner,
+            process: self.process_id.clone(),
+            acquired_at: now,
+            expires_at: now + chrono::Duration::seconds(ttl_secs),
+        };
+        let new_value = serde_json::to_vec(&lease)?;
+        
+        loop {
+            let current = self.leases.get(id.as_bytes())?;
+            if let Some(ref bytes) = current {
+                if let Ok(existing) = serde_json::from_slice::<DeploymentLease>(bytes) {
+                    if existing.owner != lease.owner && self.is_live(&existing) {
+                        return Err(DeployError::LeaseHeld {
+                            deployment_id: *id,
+                            owner: existing.owner,
+                            expires_at: existing.expires_at,
+                        }
+                        .into());
+                    }
+                }
+            }
+            
+            // 使用CAS写入，避免并发获取同一租约
+            match self
+                .leases
+                .compare_and_swap(id.as_bytes(), current, Some(new_value.clone()))?
+            {
+                Ok(()) => {
+                    self.leases.flush()?;
+                    return Ok(lease);
+                }
+                Err(_) => continue,
+            }
+        }
+    }
+    
+    /// 续期部署租约，持有者不变
+    pub fn renew_lease(&self, lease: &DeploymentLease, ttl_secs: i64) -> Result<DeploymentLease> {
+        self.write_lease(&lease.deployment_id, lease.owner.clone(), ttl_secs)
+    }
+    
+    /// 释放部署租约，只会删除自己持有的租约
+    pub fn release_lease(&self, lease: &DeploymentLease) -> Result<()> {
+        release_owned_lease(&self.leases, lease)
+    }
+    
+    /// 为已获取的租约创建守卫：守卫存在期间在后台定期续期，
+    /// 区块哈希过期、断路器冷却、v4 冷却期等长时间等待不会让租约过期；
+    /// 守卫离开作用域时释放租约，提前返回的错误路径也不会让租约一直占用到过期
+    pub fn guard_lease(&self, lease: &DeploymentLease, ttl_secs: i64) -> LeaseGuard {
+        let heartbeat = tokio::runtime::Handle::try_current().ok().map(|handle| {
+            let leases = self.leases.clone();
+            let lease = lease.clone();
+            let interval = std::time::Duration::from_secs((ttl_secs / 3).max(1) as u64);
+            handle.spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    match extend_owned_lease(&leases, &lease, ttl_secs) {
+                        Ok(true) => {}
+                        // 租约已释放或被其他持有者接管
+                        Ok(false) => break,
+                        Err(e) => tracing::warn!("续期部署 {} 的租约失败: {}", lease.deployment_id, e),
+                    }
+                }
+            })
+        });
+        LeaseGuard {
+            leases: self.leases.clone(),
+            lease: lease.clone(),
+            heartbeat,
+        }
+    }
+    
+    /// 获取被隔离的记录
+    pub fn quarantined_records(&self) -> Vec<QuarantinedRecord> {
+        self.quarantine
+            .iter()
+            .filter_map(|item| item.ok())
+            .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+            .collect()
+    }
+    
+    /// 租约是否仍在生效：由本进程获取且未过期
+    fn is_live(&self, lease: &DeploymentLease) -> bool {
+        lease.process == self.process_id && !lease.is_expired()
+    }
+    
+    /// 查询部署当前仍在生效的租约，已退出进程留下的租约不算
+    pub fn active_lease(&self, id: &Uuid) -> Option<DeploymentLease> {
+        self.get_lease(id).filter(|lease| self.is_live(lease))
+    }
+    
+    /// 查询部署当前的租约
+    pub fn get_lease(&self, id: &Uuid) -> Option<DeploymentLease> {
+        self.leases
+            .get(id.as_bytes())
+            .ok()
+            .flatten()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    }
+}
+
+/// 部署租约守卫，由 [`StateManager::guard_lease`] 创建
+pub struct LeaseGuard {
+    leases: Tree,
+    lease: DeploymentLease,
+    heartbeat: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        if let Some(heartbeat) = self.heartbeat.take() {
+            heartbeat.abort();
+        }
+        // 已显式释放或已被他人接管时不做任何事
+        if let Err(e) = release_owned_lease(&self.leases, &self.lease) {
+            tracing::warn!("释放部署 {} 的租约失败: {}", self.lease.deployment_id, e);
+        }
+    }
+}
+
+/// 删除同一持有者的租约（续期后的租约持有者不变）
+fn release_owned_lease(leases: &Tree, lease: &DeploymentLease) -> Result<()> {
+    let current = leases.get(lease.deployment_id.as_bytes())?;
+    if let Some(bytes) = current {
+        let owned = serde_json::from_slice::<DeploymentLease>(&bytes)
+            .map(|existing| existing.owner == lease.owner)
+            .unwrap_or(false);
+        if owned {
+            let _ = leases.compare_and_swap(lease.deployment_id.as_bytes(), Some(bytes), None as Option<&[u8]>)?;
+            leases.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// 仅当租约仍由同一持有者持有时延长有效期，返回是否延长成功；不会重新创建已释放的租约
+fn extend_owned_lease(leases: &Tree, lease: &DeploymentLease, ttl_secs: i64) -> Result<bool> {
+    loop {
+        let Some(bytes) = leases.get(lease.deployment_id.as_bytes())? else {
+            return Ok(false);
+        };
+        let mut current: DeploymentLease = match serde_json::from_slice(&bytes) {
+            Ok(current) => current,
+            Err(_) => return Ok(false),
+        };
+        if current.owner != lease.owner {
+            return Ok(false);
+        }
+        current.expires_at = Utc::now() + chrono::Duration::seconds(ttl_secs);
+        let new_value = serde_json::to_vec(&current)?;
+        if leases
+            .compare_and_swap(lease.deployment_id.as_bytes(), Some(bytes), Some(new_value))?
+            .is_ok()
+        {
+            leases.flush()?;
+            return Ok(true);
+        }
+    }
+}
+
+impl Drop for StateManager {
+    fn drop(&mut self) {
+        let _ = self.db.flush();
+        let _ = std::fs::remove_file(&self.owner_file);
+    }
+}
+
+/// 部署记录的只读视图，每次都从数据库读取，能看到同一进程中 StateManager 写入的最新状态
+#[derive(Clone)]
+pub struct StateReader {
+    db: Db,
+    chunks: Tree,
+    upload_tuning: Tree,
+}
+
+impl StateReader {
+    /// 获取部署状态
+    pub fn get_deployment(&self, id: &Uuid) -> Option<DeploymentState> {
+        let value = self.db.get(id.as_bytes()).ok().flatten()?;
+        match MigrationRegistry::default().decode(&value) {
+            Ok(DecodedRecord::Ready { state, .. }) => Some(*state),
+            _ => None,
+        }
+    }
+    
+    /// 获取部署的数据块账本，按块序号排列
+    pub fn get_chunk_ledger(&self, id: &Uuid) -> Vec<ChunkRecord> {
+        read_chunk_ledger(&self.chunks, id)
+    }
+    
+    /// 获取某个RPC节点上学到的上传参数
+    pub fn get_upload_tuning(&self, endpoint: &str) -> Option<UploadTuning> {
+        read_upload_tuning(&self.upload_tuning, endpoint)
+    }
+}
+
+fn read_chunk_ledger(chunks: &Tree, id: &Uuid) -> Vec<ChunkRecord> {
+    chunks
+        .scan_prefix(id.as_bytes())
+        .filter_map(|item| item.ok())
+        .filter_map(|(_, value)| serde_json::from_slice(&value).ok())
+        .collect()
+}
+
+fn read_upload_tuning(upload_tuning: &Tree, endpoint: &str) -> Option<UploadTuning> {
+    upload_tuning
+        .get(endpoint.as_bytes())
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_slice(&value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> (tempfile::TempDir, StateManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = StateManager::new(dir.path().join("state.db")).unwrap();
+        (dir, manager)
+    }
+
+    /// 以本进程中另一个工作者的身份写入租约
+    fn insert_foreign_lease(manager: &StateManager, id: &Uuid, ttl_secs: i64) -> DeploymentLease {
+        let now = Utc::now();
+        let lease = DeploymentLease {
+            deployment_id: *id,
+            owner: format!("{}/other", manager.process_id()),
+            process: manager.process_id().to_string(),
+            acquired_at: now,
+            expires_at: now + chrono::Duration::seconds(ttl_secs),
+        };
+        manager
+            .leases
+            .insert(id.as_bytes(), serde_json::to_vec(&lease).unwrap())
+            .unwrap();
+        lease
+    }
+
+    #[test]
+    fn lease_held_by_another_owner_is_rejected_until_expired() {
+        let (_dir, manager) = manager();
+        let id = Uuid::new_v4();
+        insert_foreign_lease(&manager, &id, 60);
+        assert!(manager.acquire_lease(&id, 60).is_err());
+
+        insert_foreign_lease(&manager, &id, -1);
+        let lease = manager.acquire_lease(&id, 60).unwrap();
+        assert!(lease.owner.starts_with(manager.process_id()));
+    }
+
+    #[test]
+    fn workers_in_one_process_exclude_each_other() {
+        let (_dir, manager) = manager();
+        let id = Uuid::new_v4();
+        let lease = manager.acquire_lease(&id, 60).unwrap();
+        assert!(manager.acquire_lease(&id, 60).is_err());
+
+        let renewed = manager.renew_lease(&lease, 120).unwrap();
+        assert_eq!(renewed.owner, lease.owner);
+        assert!(renewed.expires_at > lease.expires_at);
+
+        manager.release_lease(&renewed).unwrap();
+        assert!(manager.acquire_lease(&id, 60).is_ok());
+    }
+
+    #[test]
+    fn lease_left_by_crashed_process_is_taken_over_immediately() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let id = Uuid::new_v4();
+        let stale = {
+            // 进程崩溃时守卫不会运行，租约留在数据库中且尚未过期
+            let crashed = StateManager::new(&path).unwrap();
+            crashed.acquire_lease(&id, DEFAULT_LEASE_TTL_SECS).unwrap()
+        };
+        assert!(!stale.is_expired());
+
+        let manager = StateManager::new(&path).unwrap();
+        let lease = manager.acquire_lease(&id, DEFAULT_LEASE_TTL_SECS).unwrap();
+        assert_ne!(lease.owner, stale.owner);
+        assert_eq!(lease.process, manager.process_id());
+    }
+
+    #[test]
+    fn release_keeps_lease_taken_over_by_another_owner() {
+        let (_dir, manager) = manager();
+        let id = Uuid::new_v4();
+        let lease = manager.acquire_lease(&id, 60).unwrap();
+        let foreign = insert_foreign_lease(&manager, &id, 60);
+        manager.release_lease(&lease).unwrap();
+        assert_eq!(manager.get_lease(&id).map(|current| current.owner), Some(foreign.owner));
+    }
+
+    #[test]
+    fn guard_releases_lease_when_dropped() {
+        let (_dir, manager) = manager();
+        let id = Uuid::new_v4();
+        let lease = manager.acquire_lease(&id, 60).unwrap();
+        {
+            let _guard = manager.guard_lease(&lease, 60);
+            assert!(manager.get_lease(&id).is_some());
+        }
+        assert!(manager.get_lease(&id).is_none());
+    }
+
+    #[test]
+    fn extend_only_applies_to_lease_still_owned() {
+        let (_dir, manager) = manager();
+        let id = Uuid::new_v4();
+        let lease = manager.acquire_lease(&id, 10).unwrap();
+        assert!(extend_owned_lease(&manager.leases, &lease, 600).unwrap());
+        assert!(manager.get_lease(&id).unwrap().expires_at > lease.expires_at);
+
+        // 已释放的租约不会被续期重新创建
+        manager.release_lease(&lease).unwrap();
+        assert!(!extend_owned_lease(&manager.leases, &lease, 600).unwrap());
+        assert!(manager.get_lease(&id).is_none());
+
+        insert_foreign_lease(&manager, &id, 60);
+        assert!(!extend_owned_lease(&manager.leases, &lease, 600).unwrap());
+    }
+}
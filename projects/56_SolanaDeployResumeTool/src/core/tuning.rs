@@ -0,0 +1,235 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// 自适应调整允许的最小块大小（字节），配置的块大小上限更小时以上限为准
+pub const MIN_ADAPTIVE_CHUNK_SIZE: usize = 128;
+
+/// 每次加性增加的块大小（字节）
+const CHUNK_SIZE_STEP: usize = 64;
+
+/// 批次用时超过基线的倍数时视为变慢，不再增加
+const LATENCY_TOLERANCE: f64 = 1.5;
+
+/// 某个RPC节点上学到的上传参数，下次上传时作为起点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadTuning {
+    pub endpoint: String,
+    pub chunk_size: usize,
+    pub parallelism: usize,
+    /// 学习时经历的上传批次数
+    pub rounds: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 一次调整的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuningAction {
+    Increase,
+    Hold,
+    Decrease,
+}
+
+/// AIMD控制器：批次全部按时确认时加性增加并发数（并发数到顶后增加块大小），
+/// 出现未确认的交易时并发数减半（并发数已为1时块大小减半），批次变慢时保持不变
+#[derive(Debug, Clone)]
+pub struct AimdController {
+    chunk_size: usize,
+    max_chunk_size: usize,
+    parallelism: usize,
+    max_parallelism: usize,
+    /// 按时确认的批次中最短的用时
+    baseline_latency: Option<Duration>,
+    rounds: u64,
+}
+
+impl AimdController {
+    /// 从能装进一笔交易的最大块大小和给定的最大并发数开始
+    pub fn new(max_chunk_size: usize, max_parallelism: usize) -> Self {
+        let max_chunk_size = max_chunk_size.max(1);
+        let max_parallelism = max_parallelism.max(1);
+        Self {
+            chunk_size: max_chunk_size,
+            max_chunk_size,
+            parallelism: max_parallelism,
+            max_parallelism,
+            baseline_latency: None,
+            rounds: 0,
+        }
+    }
+
+    /// 以之前在同一节点上学到的参数为起点，超出当前上限的部分被截断
+    pub fn with_learned(mut self, tuning: &UploadTuning) -> Self {
+        self.chunk_size = tuning.chunk_size.clamp(self.min_chunk_size(), self.max_chunk_size);
+        self.parallelism = tuning.parallelism.clamp(1, self.max_parallelism);
+        self.rounds = tuning.rounds;
+        self
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// 块大小下限，不超过块大小上限
+    fn min_chunk_size(&self) -> usize {
+        MIN_ADAPTIVE_CHUNK_SIZE.min(self.max_chunk_size)
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    /// 根据一轮发送的确认结果调整参数：`sent` 笔中有 `confirmed` 笔在 `elapsed` 内确认
+    pub fn record_round(&mut self, sent: usize, confirmed: usize, elapsed: Duration) -> TuningAction {
+        if sent == 0 {
+            return TuningAction::Hold;
+        }
+        self.rounds += 1;
+
+        if confirmed < sent {
+            if self.parallelism > 1 {
+                self.parallelism = (self.parallelism / 2).max(1);
+            } else {
+                self.chunk_size = (self.chunk_size / 2).max(self.min_chunk_size());
+            }
+            return TuningAction::Decrease;
+        }
+
+        let baseline = *self.baseline_latency.get_or_insert(elapsed);
+        if elapsed < baseline {
+            self.baseline_latency = Some(elapsed);
+        } else if elapsed.as_secs_f64() > baseline.as_secs_f64() * LATENCY_TOLERANCE {
+            return TuningAction::Hold;
+        }
+
+        if self.parallelism < self.max_parallelism {
+            self.parallelism += 1;
+        } else if self.chunk_size < self.max_chunk_size {
+            self.chunk_size = (self.chunk_size + CHUNK_SIZE_STEP).min(self.max_chunk_size);
+        } else {
+            return TuningAction::Hold;
+        }
+        TuningAction::Increase
+    }
+
+    /// 当前参数，用于持久化
+    pub fn tuning(&self, endpoint: &str) -> UploadTuning {
+        UploadTuning {
+            endpoint: endpoint.to_string(),
+            chunk_size: self.chunk_size,
+            parallelism: self.parallelism,
+            rounds: self.rounds,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUND: Duration = Duration::from_millis(800);
+
+    #[test]
+    fn starts_at_limits() {
+        let controller = AimdController::new(1000, 4);
+        assert_eq!(controller.chunk_size(), 1000);
+        assert_eq!(controller.parallelism(), 4);
+        // 块大小上限低于自适应下限时仍以上限为准
+        let controller = AimdController::new(10, 0);
+        assert_eq!(controller.chunk_size(), 10);
+        assert_eq!(controller.parallelism(), 1);
+    }
+
+    #[test]
+    fn empty_round_holds() {
+        let mut controller = AimdController::new(1000, 4);
+        assert_eq!(controller.record_round(0, 0, ROUND), TuningAction::Hold);
+        assert_eq!(controller.tuning("rpc").rounds, 0);
+    }
+
+    #[test]
+    fn unconfirmed_round_halves_parallelism_then_chunk_size() {
+        let mut controller = AimdController::new(1000, 4);
+        assert_eq!(controller.record_round(4, 3, ROUND), TuningAction::Decrease);
+        assert_eq!(controller.parallelism(), 2);
+        controller.record_round(2, 0, ROUND);
+        assert_eq!(controller.parallelism(), 1);
+        assert_eq!(controller.chunk_size(), 1000);
+        controller.record_round(1, 0, ROUND);
+        assert_eq!(controller.parallelism(), 1);
+        assert_eq!(controller.chunk_size(), 500);
+        for _ in 0..10 {
+            controller.record_round(1, 0, ROUND);
+        }
+        assert_eq!(controller.chunk_size(), MIN_ADAPTIVE_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn confirmed_round_grows_parallelism_before_chunk_size() {
+        let learned = UploadTuning {
+            endpoint: "rpc".to_string(),
+            chunk_size: 500,
+            parallelism: 1,
+            rounds: 7,
+            updated_at: Utc::now(),
+        };
+        let mut controller = AimdController::new(1000, 2).with_learned(&learned);
+        assert_eq!(controller.record_round(1, 1, ROUND), TuningAction::Increase);
+        assert_eq!((controller.parallelism(), controller.chunk_size()), (2, 500));
+        assert_eq!(controller.record_round(2, 2, ROUND), TuningAction::Increase);
+        assert_eq!((controller.parallelism(), controller.chunk_size()), (2, 500 + CHUNK_SIZE_STEP));
+        assert_eq!(controller.tuning("rpc").rounds, 9);
+    }
+
+    #[test]
+    fn holds_at_limits() {
+        let mut controller = AimdController::new(1000, 2);
+        assert_eq!(controller.record_round(2, 2, ROUND), TuningAction::Hold);
+        assert_eq!((controller.parallelism(), controller.chunk_size()), (2, 1000));
+    }
+
+    #[test]
+    fn slow_round_holds() {
+        let mut controller = AimdController::new(1000, 4);
+        controller.record_round(4, 3, ROUND);
+        assert_eq!(controller.record_round(2, 2, ROUND), TuningAction::Increase);
+        // 超过基线用时的 1.5 倍视为变慢
+        assert_eq!(controller.record_round(3, 3, ROUND * 2), TuningAction::Hold);
+        assert_eq!(controller.parallelism(), 3);
+        // 更快的批次成为新的基线
+        assert_eq!(controller.record_round(3, 3, ROUND / 2), TuningAction::Increase);
+        assert_eq!(controller.record_round(4, 4, ROUND), TuningAction::Hold);
+    }
+
+    #[test]
+    fn chunk_size_cap_below_minimum_is_never_exceeded() {
+        let learned = UploadTuning {
+            endpoint: "rpc".to_string(),
+            chunk_size: 1000,
+            parallelism: 1,
+            rounds: 3,
+            updated_at: Utc::now(),
+        };
+        let mut controller = AimdController::new(100, 1).with_learned(&learned);
+        assert_eq!(controller.chunk_size(), 100);
+        controller.record_round(1, 0, ROUND);
+        assert_eq!(controller.chunk_size(), 100);
+        assert_eq!(controller.record_round(1, 1, ROUND), TuningAction::Hold);
+        assert_eq!(controller.chunk_size(), 100);
+    }
+
+    #[test]
+    fn learned_values_are_clamped_to_limits() {
+        let learned = UploadTuning {
+            endpoint: "rpc".to_string(),
+            chunk_size: 5000,
+            parallelism: 0,
+            rounds: 0,
+            updated_at: Utc::now(),
+        };
+        let controller = AimdController::new(1000, 4).with_learned(&learned);
+        assert_eq!(controller.chunk_size(), 1000);
+        assert_eq!(controller.parallelism(), 1);
+    }
+}
@@ -1,6 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::RpcError;
+use solana_sdk::instruction::InstructionError;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+use std::time::Duration;
 // use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -21,6 +27,148 @@ pub struct DeploymentState {
     pub last_error: Option<String>,
     pub network_stats: NetworkStats,
     pub cost_stats: CostStats,
+    pub program_fingerprint: Option<ProgramFingerprint>,
+    /// 持久nonce账户，设置后所有写入交易都以 AdvanceNonceAccount 开头并使用其中的nonce值签名
+    pub nonce_account: Option<Pubkey>,
+    /// 计划部署的触发条件，由 watch 守护循环在条件满足时自动开始
+    pub schedule: Option<DeploymentSchedule>,
+    /// 守护进程自动续传失败部署的记录
+    pub auto_resume: Option<AutoResumeState>,
+    /// 升级已部署的程序时的目标，为空表示部署新程序
+    pub upgrade: Option<ProgramUpgrade>,
+    /// 各签名角色的公钥，为空表示所有步骤都由付款账户签名
+    pub keys: Option<DeploymentKeys>,
+}
+
+/// 部署中各签名角色的公钥，续传时据此要求对应的密钥对
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeploymentKeys {
+    /// 支付交易费用和租金
+    pub fee_payer: Pubkey,
+    /// 签名写入交易；完成部署前把buffer交给升级授权
+    pub buffer_authority: Pubkey,
+    /// 签名部署或升级指令，部署后成为程序的升级授权
+    pub upgrade_authority: Pubkey,
+    /// 指定的程序地址，为空时完成部署时随机生成
+    pub program: Option<Pubkey>,
+}
+
+/// 升级已部署程序的目标和增量比较结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramUpgrade {
+    pub program_id: Pubkey,
+    /// 增量模式：与链上程序相同的块不再写入（仅 Loader v4 支持原地写入）
+    pub delta: bool,
+    /// 升级前链上程序数据的大小
+    pub previous_size: u64,
+    /// 与链上程序相同、无需重新写入的字节数
+    pub unchanged_bytes: u64,
+}
+
+/// 守护进程自动续传的记录
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AutoResumeState {
+    /// 已自动续传的次数
+    pub attempts: u32,
+    /// 最近一次自动续传的时间
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// 下次允许自动续传的时间
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// 达到 max_auto_resume_attempts 后不再自动续传
+    pub exhausted: bool,
+}
+
+impl AutoResumeState {
+    /// 现在是否可以自动续传：尚未停止，且已过退避时间
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        !self.exhausted && self.next_attempt_at.filter(|at| *at > now).is_none()
+    }
+
+    /// 记录开始一次自动续传
+    pub fn begin_attempt(&mut self, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.last_attempt_at = Some(now);
+        self.next_attempt_at = None;
+    }
+
+    /// 记录自动续传失败：已达到 `max_attempts` 次时停止自动续传并返回 false，
+    /// 否则安排在 `delay` 之后重试
+    pub fn record_failure(&mut self, max_attempts: u32, now: DateTime<Utc>, delay: chrono::Duration) -> bool {
+        if self.attempts >= max_attempts {
+            self.exhausted = true;
+            false
+        } else {
+            self.next_attempt_at = Some(now + delay);
+            true
+        }
+    }
+}
+
+/// 计划部署的触发条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentSchedule {
+    /// 最早开始时间
+    pub not_before: Option<DateTime<Utc>>,
+    /// 允许开始的最高拥堵等级
+    pub max_congestion: CongestionLevel,
+    /// 优先费 p50 上限（micro-lamports/CU），只有低于该值时才开始
+    pub max_priority_fee_p50: Option<u64>,
+    /// 开始时创建持久nonce账户
+    #[serde(default)]
+    pub use_nonce: bool,
+    /// 开始时使用的已有持久nonce账户
+    #[serde(default)]
+    pub nonce_account: Option<Pubkey>,
+    /// 守护循环已经执行的次数
+    #[serde(default)]
+    pub attempts: u32,
+    /// 最近一次检查时未满足的条件
+    #[serde(default)]
+    pub last_check: Option<String>,
+}
+
+impl DeploymentSchedule {
+    /// 按当前时间和网络状况检查触发条件，返回未满足的条件；全部满足时返回空列表
+    pub fn unmet_conditions(&self, now: DateTime<Utc>, stats: &NetworkStats) -> Vec<String> {
+        let mut unmet = Vec::new();
+        if let Some(not_before) = self.not_before {
+            if now < not_before {
+                unmet.push(format!("未到开始时间 {}", not_before.to_rfc3339()));
+            }
+        }
+        if stats.congestion_level > self.max_congestion {
+            unmet.push(format!(
+                "拥堵等级 {:?} 高于 {:?}",
+                stats.congestion_level, self.max_congestion
+            ));
+        }
+        if let Some(max_fee) = self.max_priority_fee_p50 {
+            match stats.congestion_signals.priority_fee_p50 {
+                Some(fee) if fee < max_fee => {}
+                Some(fee) => unmet.push(format!("优先费 p50 {} 不低于 {}", fee, max_fee)),
+                None => unmet.push("无法获取优先费数据".to_string()),
+            }
+        }
+        unmet
+    }
+}
+
+/// 程序文件指纹，用于确认续传时使用的是同一个 .so 文件
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProgramFingerprint {
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl ProgramFingerprint {
+    /// 计算程序数据的指纹
+    pub fn compute(data: &[u8]) -> Self {
+        use sha2::{Digest, Sha256};
+        Self {
+            sha256: hex::encode(Sha256::digest(data)),
+            size: data.len() as u64,
+        }
+    }
 }
 
 /// 加载器版本
@@ -33,6 +181,8 @@ pub enum LoaderVersion {
 /// 部署状态枚举
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum DeploymentStatus {
+    /// 已排队，等待计划条件满足
+    Scheduled,
     Initializing,
     Uploading,
     Paused,
@@ -50,6 +200,9 @@ pub struct BufferInfo {
     pub offset: u64,
     pub status: BufferStatus,
     pub created_at: DateTime<Utc>,
+    /// 写入和部署该buffer需要的授权账户，为空表示创建时的付款账户
+    #[serde(default)]
+    pub authority: Option<Pubkey>,
 }
 
 /// Buffer状态
@@ -59,20 +212,183 @@ pub enum BufferStatus {
     Uploading,
     Completed,
     Failed,
+    /// 已由另一个部署复用，本部署不再使用也不负责关闭它
+    Transferred,
+}
+
+impl BufferStatus {
+    /// 本部署是否已不能再使用该buffer
+    pub fn is_abandoned(&self) -> bool {
+        matches!(self, BufferStatus::Failed | BufferStatus::Transferred)
+    }
+}
+
+/// 已写满程序数据的buffer，相同程序的新部署可以直接用它完成部署
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReusableBuffer {
+    /// 程序数据的sha256
+    pub program_hash: String,
+    pub loader_version: LoaderVersion,
+    /// 写满该buffer（或最近复用它）的部署
+    pub deployment_id: Uuid,
+    pub buffer: BufferInfo,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// 数据块账本记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    pub index: u32,
+    pub offset: u64,
+    pub size: u32,
+    pub checksum: String,
+    pub status: ChunkStatus,
+    pub signature: Option<String>,
+    /// 最近一次签名所用区块哈希的有效高度，超过后该签名不可能再上链
+    #[serde(default)]
+    pub last_valid_block_height: Option<u64>,
+    pub attempts: u32,
+    pub updated_at: DateTime<Utc>,
+    /// 上链交易实际支付的费用，取自交易元数据；无法获取元数据时为 None
+    #[serde(default)]
+    pub fee: Option<u64>,
+    /// 该块的费用已在某次续传中计入 `saved_fees`，之后的续传不再重复计入
+    #[serde(default)]
+    pub savings_counted: bool,
+}
+
+/// 数据块状态
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ChunkStatus {
+    Pending,
+    Sent,
+    Confirmed,
+    Failed,
 }
 
 /// 网络统计信息
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct NetworkStats {
     pub latency_ms: f64,
+    /// 吞吐量（字节/秒），0 表示尚未测量
     pub throughput_bps: f64,
     pub packet_loss_rate: f64,
     pub congestion_level: CongestionLevel,
     pub optimal_chunk_size: usize,
+    /// 判定拥堵等级的依据
+    #[serde(default)]
+    pub congestion_reasons: Vec<String>,
+    /// 判定拥堵等级时采集的各项指标
+    #[serde(default)]
+    pub congestion_signals: CongestionSignals,
 }
 
-/// 网络拥堵等级
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// 持久化的网络状况样本，用于按时段统计历史网络状况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkSample {
+    pub cluster: String,
+    pub timestamp: DateTime<Utc>,
+    pub congestion_level: CongestionLevel,
+    pub latency_ms: f64,
+    pub success_rate: f64,
+    pub slot_time_ms: Option<f64>,
+    pub priority_fee_p50: Option<u64>,
+    pub confirmation_latency_ms: Option<f64>,
+}
+
+impl NetworkSample {
+    pub fn from_stats(cluster: &str, stats: &NetworkStats) -> Self {
+        Self {
+            cluster: cluster.to_string(),
+            timestamp: Utc::now(),
+            congestion_level: stats.congestion_level.clone(),
+            latency_ms: stats.latency_ms,
+            success_rate: stats.congestion_signals.success_rate,
+            slot_time_ms: stats.congestion_signals.slot_time_ms,
+            priority_fee_p50: stats.congestion_signals.priority_fee_p50,
+            confirmation_latency_ms: stats.congestion_signals.confirmation_latency_ms,
+        }
+    }
+}
+
+/// 拥堵判定所用的指标，链上指标获取失败时为 None
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CongestionSignals {
+    /// 本机到RPC节点的HTTP延迟（毫秒）
+    pub http_latency_ms: f64,
+    /// RPC请求成功率
+    pub success_rate: f64,
+    /// 最近性能样本的平均出块时间（毫秒）
+    pub slot_time_ms: Option<f64>,
+    /// 最近性能样本的每秒交易数（含投票交易）
+    pub tps: Option<f64>,
+    /// 最近性能样本的每秒非投票交易数
+    pub non_vote_tps: Option<f64>,
+    /// 最近区块优先费分位数（micro-lamports/CU）
+    pub priority_fee_p50: Option<u64>,
+    pub priority_fee_p75: Option<u64>,
+    pub priority_fee_p90: Option<u64>,
+    /// 本工具交易从首次广播到确认的平均用时（毫秒）
+    pub confirmation_latency_ms: Option<f64>,
+}
+
+/// 单项指标的拥堵阈值，指标达到对应值即判定为该等级
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LevelThresholds {
+    pub medium: f64,
+    pub high: f64,
+    pub critical: f64,
+}
+
+impl LevelThresholds {
+    pub fn new(medium: f64, high: f64, critical: f64) -> Self {
+        Self { medium, high, critical }
+    }
+
+    /// 指标值对应的拥堵等级，返回等级和触发的阈值
+    pub fn classify(&self, value: f64) -> (CongestionLevel, f64) {
+        if value >= self.critical {
+            (CongestionLevel::Critical, self.critical)
+        } else if value >= self.high {
+            (CongestionLevel::High, self.high)
+        } else if value >= self.medium {
+            (CongestionLevel::Medium, self.medium)
+        } else {
+            (CongestionLevel::Low, self.medium)
+        }
+    }
+}
+
+/// 拥堵判定阈值，综合等级取各项指标中最严重的一项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CongestionThresholds {
+    /// 本机到RPC节点的HTTP延迟（毫秒），反映本地网络而非集群状况
+    pub http_latency_ms: LevelThresholds,
+    /// RPC请求失败率
+    pub failure_rate: LevelThresholds,
+    /// 平均出块时间（毫秒），正常约400ms
+    pub slot_time_ms: LevelThresholds,
+    /// 最近区块优先费的 p75（micro-lamports/CU）
+    pub priority_fee_p75: LevelThresholds,
+    /// 本工具交易的平均确认用时（毫秒）
+    pub confirmation_latency_ms: LevelThresholds,
+}
+
+impl Default for CongestionThresholds {
+    fn default() -> Self {
+        Self {
+            http_latency_ms: LevelThresholds::new(300.0, 1000.0, 3000.0),
+            failure_rate: LevelThresholds::new(0.05, 0.10, 0.20),
+            slot_time_ms: LevelThresholds::new(500.0, 650.0, 900.0),
+            priority_fee_p75: LevelThresholds::new(5_000.0, 50_000.0, 500_000.0),
+            confirmation_latency_ms: LevelThresholds::new(3_000.0, 8_000.0, 20_000.0),
+        }
+    }
+}
+
+/// 网络拥堵等级，按严重程度递增排序
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CongestionLevel {
     Low,
     Medium,
@@ -91,20 +407,119 @@ impl Default for CongestionLevel {
 pub struct CostStats {
     pub total_fees_paid: u64,
     pub estimated_remaining_fees: u64,
+    /// 续传时无需重新发送的已确认块当初实际支付的费用之和，只计入有交易元数据的块，每块只计一次
     pub saved_fees: u64,
     pub transaction_count: u32,
     pub retry_count: u32,
+    /// 按签名数收取的基础费用
+    #[serde(default)]
+    pub base_fees_paid: u64,
+    #[serde(default)]
+    pub priority_fees_paid: u64,
+    /// 新建账户时存入的租金
+    #[serde(default)]
+    pub rent_deposited: u64,
+    /// 关闭账户时退还的租金
+    #[serde(default)]
+    pub rent_refunded: u64,
+    /// 费用取自链上交易元数据的交易数，其余交易按查询到的单笔费用记账
+    #[serde(default)]
+    pub audited_transactions: u32,
+}
+
+impl CostStats {
+    /// 记录一笔已上链交易（包括执行失败但已扣费的交易）：
+    /// 有交易元数据时按实际明细记账，否则按估算的单笔费用记账
+    pub fn record_transaction(&mut self, cost: Option<&TransactionCost>, estimated_fee: u64) {
+        self.transaction_count += 1;
+        match cost {
+            Some(cost) => {
+                self.total_fees_paid += cost.fee;
+                self.base_fees_paid += cost.base_fee;
+                self.priority_fees_paid += cost.priority_fee;
+                self.rent_deposited += cost.rent_deposited;
+                self.rent_refunded += cost.rent_refunded;
+                self.audited_transactions += 1;
+            }
+            None => self.total_fees_paid += estimated_fee,
+        }
+        self.estimated_remaining_fees = self
+            .estimated_remaining_fees
+            .saturating_sub(cost.map_or(estimated_fee, |cost| cost.fee));
+    }
+
+    /// 累加一轮上传的统计
+    pub fn accumulate(&mut self, round: &CostStats) {
+        self.total_fees_paid += round.total_fees_paid;
+        self.estimated_remaining_fees = self.estimated_remaining_fees.saturating_sub(round.total_fees_paid);
+        self.saved_fees += round.saved_fees;
+        self.transaction_count += round.transaction_count;
+        self.retry_count += round.retry_count;
+        self.base_fees_paid += round.base_fees_paid;
+        self.priority_fees_paid += round.priority_fees_paid;
+        self.rent_deposited += round.rent_deposited;
+        self.rent_refunded += round.rent_refunded;
+        self.audited_transactions += round.audited_transactions;
+    }
+}
+
+/// 从已上链交易的元数据中得到的费用明细（lamports）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TransactionCost {
+    /// 元数据中的总交易费
+    pub fee: u64,
+    pub base_fee: u64,
+    pub priority_fee: u64,
+    pub rent_deposited: u64,
+    pub rent_refunded: u64,
+}
+
+impl TransactionCost {
+    /// 由交易费和付款账户的余额变化计算：`base_fee` 以外的交易费为优先费。
+    /// `rent_deposited` 为交易中新建或扩容账户时存入的租金，由发送方按指令计算；
+    /// 余额在扣除交易费和这部分租金后仍增加的部分为关闭账户退还的租金，其他转出不计入租金
+    pub fn from_balances(
+        fee: u64,
+        base_fee: u64,
+        payer_pre_balance: u64,
+        payer_post_balance: u64,
+        rent_deposited: u64,
+    ) -> Self {
+        let refund = payer_post_balance as i128 - payer_pre_balance as i128 + fee as i128 + rent_deposited as i128;
+        Self {
+            fee,
+            base_fee: base_fee.min(fee),
+            priority_fee: fee.saturating_sub(base_fee),
+            rent_deposited,
+            rent_refunded: refund.max(0) as u64,
+        }
+    }
 }
 
 /// 续传配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ResumeConfig {
+    /// 单个块写入失败后的最大重试次数
     pub max_retries: u32,
     pub retry_delay_ms: u64,
+    /// 块大小上限（字节），0 表示按交易包长自动计算
     pub chunk_size: usize,
     pub parallel_uploads: usize,
     pub auto_resume: bool,
+    /// 守护进程对同一个失败部署最多自动续传的次数，计划部署失败后最多重新排队的次数
+    pub max_auto_resume_attempts: u32,
     pub fee_optimization: bool,
+    /// 每笔交易优先费上限（lamports）
+    pub priority_fee_cap_lamports: u64,
+    /// 整个部署的费用上限（lamports），超过时拒绝开始部署
+    pub max_total_fee_lamports: Option<u64>,
+    /// 除RPC外，同时把写入交易直接发送到即将出块的leader的TPU端口
+    pub use_tpu: bool,
+    /// 新部署默认创建持久nonce账户，写入交易不再受区块哈希有效期限制
+    pub durable_nonce: bool,
+    /// 还没有真实上传样本时，用 simulateTransaction 提交满载写入交易主动探测吞吐量
+    pub throughput_probe: bool,
 }
 
 impl Default for ResumeConfig {
@@ -112,10 +527,44 @@ impl Default for ResumeConfig {
         Self {
             max_retries: 3,
             retry_delay_ms: 1000,
-            chunk_size: 1024,
+            chunk_size: 0,
             parallel_uploads: 4,
             auto_resume: true,
+            max_auto_resume_attempts: 3,
             fee_optimization: true,
+            priority_fee_cap_lamports: 10000,
+            max_total_fee_lamports: None,
+            use_tpu: false,
+            durable_nonce: false,
+            throughput_probe: false,
+        }
+    }
+}
+
+/// RPC限速配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// 所有RPC调用共享的每秒请求数上限，0 表示不限速
+    pub requests_per_second: f64,
+    /// 令牌桶容量，允许的瞬时突发请求数
+    pub burst: u32,
+    /// 窗口内重试次数最多占成功调用次数的比例
+    pub retry_budget_ratio: f64,
+    /// 重试预算的统计窗口（秒）
+    pub retry_budget_window_secs: u64,
+    /// 窗口内至少允许的重试次数，避免刚启动时没有成功调用就无法重试
+    pub min_retry_budget: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 20,
+            retry_budget_ratio: 0.2,
+            retry_budget_window_secs: 60,
+            min_retry_budget: 5,
         }
     }
 }
@@ -141,6 +590,21 @@ pub struct DeploymentEvent {
     pub timestamp: DateTime<Utc>,
 }
 
+/// 导出文件格式版本
+pub const DEPLOYMENT_BUNDLE_VERSION: u32 = 1;
+
+/// 可在机器之间迁移的部署记录（不包含任何私钥）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentBundle {
+    pub bundle_version: u32,
+    pub schema_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub deployment: DeploymentState,
+    pub chunk_ledger: Vec<ChunkRecord>,
+    pub events: Vec<DeploymentEvent>,
+    pub program_fingerprint: Option<ProgramFingerprint>,
+}
+
 /// 事件类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType {
@@ -155,6 +619,26 @@ pub enum EventType {
     Info,
 }
 
+/// 部署租约，保证同一时间只有一个工作者驱动某个部署
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentLease {
+    pub deployment_id: Uuid,
+    /// 持有者标识，每次获取租约都不同
+    pub owner: String,
+    /// 获取租约的进程标识，为空表示旧版本写入的租约
+    #[serde(default)]
+    pub process: String,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl DeploymentLease {
+    /// 租约是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
 /// 错误类型
 #[derive(Debug, thiserror::Error)]
 pub enum DeployError {
@@ -173,9 +657,343 @@ pub enum DeployError {
     #[error("配置错误: {0}")]
     Configuration(String),
     
+    #[error("数据库 {path} 已被其他进程占用{holder}；如果 server 正在运行，请通过其 API 操作，或使用不同的 --data-dir")]
+    DatabaseLocked { path: String, holder: String },
+    
+    #[error("部署 {deployment_id} 正由 {owner} 处理（租约到期时间 {expires_at}）")]
+    LeaseHeld {
+        deployment_id: Uuid,
+        owner: String,
+        expires_at: DateTime<Utc>,
+    },
+    
+    #[error("余额不足: {0}")]
+    InsufficientFunds(String),
+    
+    #[error("账户被其他交易占用: {0}")]
+    AccountInUse(String),
+    
+    #[error("区块哈希已过期: {0}")]
+    BlockhashExpired(String),
+    
+    #[error("RPC请求被限流 (HTTP 429){}", .retry_after.map(|d| format!("，建议 {} 秒后重试", d.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+    
+    #[error("请求超时: {0}")]
+    Timeout(String),
+    
+    #[error("交易执行失败: {message}")]
+    TransactionFailed { message: String, retryable: bool },
+    
+    #[error("第 {index} 条指令执行失败: {message}")]
+    InstructionFailed { index: u8, message: String },
+    
     #[error("部署被取消")]
     Cancelled,
     
     #[error("未知错误: {0}")]
     Unknown(String),
-} 
\ No newline at end of file
+}
+
+impl DeployError {
+    /// 将RPC客户端错误映射为结构化错误
+    pub fn from_client_error(error: &ClientError) -> Self {
+        // 预检失败或链上执行失败时按交易错误归类
+        if let Some(transaction_error) = error.get_transaction_error() {
+            return Self::from_transaction_error(&transaction_error);
+        }
+        match error.kind() {
+            ClientErrorKind::Reqwest(e) if e.status().map(|status| status.as_u16()) == Some(429) => {
+                // HttpSender 已按 Retry-After 自行重试过，最终错误中不再携带该响应头
+                DeployError::RateLimited { retry_after: None }
+            }
+            ClientErrorKind::Reqwest(e) if e.is_timeout() => DeployError::Timeout(e.to_string()),
+            ClientErrorKind::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                DeployError::Timeout(e.to_string())
+            }
+            ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => DeployError::Network(error.to_string()),
+            ClientErrorKind::RpcError(RpcError::RpcRequestError(message)) => {
+                DeployError::Network(message.clone())
+            }
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => match *code {
+                429 => DeployError::RateLimited { retry_after: None },
+                JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => DeployError::Network(message.clone()),
+                _ => DeployError::SolanaRpc(error.to_string()),
+            },
+            _ => DeployError::SolanaRpc(error.to_string()),
+        }
+    }
+
+    /// 将交易错误映射为结构化错误
+    pub fn from_transaction_error(error: &TransactionError) -> Self {
+        match error {
+            TransactionError::InsufficientFundsForFee | TransactionError::InsufficientFundsForRent { .. } => {
+                DeployError::InsufficientFunds(error.to_string())
+            }
+            TransactionError::AccountInUse => DeployError::AccountInUse(error.to_string()),
+            TransactionError::BlockhashNotFound => DeployError::BlockhashExpired(error.to_string()),
+            TransactionError::InstructionError(index, instruction_error) => {
+                Self::from_instruction_error(*index, instruction_error)
+            }
+            // 区块容量不足属于拥堵，稍后重发即可
+            TransactionError::WouldExceedMaxBlockCostLimit
+            | TransactionError::WouldExceedMaxAccountCostLimit
+            | TransactionError::WouldExceedMaxVoteCostLimit
+            | TransactionError::WouldExceedAccountDataBlockLimit
+            | TransactionError::ClusterMaintenance => DeployError::TransactionFailed {
+                message: error.to_string(),
+                retryable: true,
+            },
+            _ => DeployError::TransactionFailed {
+                message: error.to_string(),
+                retryable: false,
+            },
+        }
+    }
+
+    /// 将指令错误映射为结构化错误
+    pub fn from_instruction_error(index: u8, error: &InstructionError) -> Self {
+        match error {
+            InstructionError::InsufficientFunds => {
+                DeployError::InsufficientFunds(format!("第 {} 条指令: {}", index, error))
+            }
+            InstructionError::AccountBorrowFailed | InstructionError::AccountBorrowOutstanding => {
+                DeployError::AccountInUse(format!("第 {} 条指令: {}", index, error))
+            }
+            _ => DeployError::InstructionFailed {
+                index,
+                message: error.to_string(),
+            },
+        }
+    }
+
+    /// 根据HTTP 429响应的 Retry-After 头构造限流错误，只支持秒数形式
+    pub fn rate_limited(headers: &reqwest::header::HeaderMap) -> Self {
+        let retry_after = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        DeployError::RateLimited { retry_after }
+    }
+
+    /// 错误是否是暂时性的，重试可能成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DeployError::Network(_)
+            | DeployError::SolanaRpc(_)
+            | DeployError::AccountInUse(_)
+            | DeployError::BlockhashExpired(_)
+            | DeployError::RateLimited { .. }
+            | DeployError::Timeout(_)
+            | DeployError::Unknown(_) => true,
+            DeployError::TransactionFailed { retryable, .. } => *retryable,
+            DeployError::InsufficientFunds(_)
+            | DeployError::InstructionFailed { .. }
+            | DeployError::StateManagement(_)
+            | DeployError::FileSystem(_)
+            | DeployError::Configuration(_)
+            | DeployError::DatabaseLocked { .. }
+            | DeployError::LeaseHeld { .. }
+            | DeployError::Cancelled => false,
+        }
+    }
+
+    /// 服务端要求的最短重试间隔
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            DeployError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> DeploymentSchedule {
+        DeploymentSchedule {
+            not_before: None,
+            max_congestion: CongestionLevel::Medium,
+            max_priority_fee_p50: None,
+            use_nonce: false,
+            nonce_account: None,
+            attempts: 0,
+            last_check: None,
+        }
+    }
+
+    fn stats(level: CongestionLevel, priority_fee_p50: Option<u64>) -> NetworkStats {
+        NetworkStats {
+            congestion_level: level,
+            congestion_signals: CongestionSignals {
+                priority_fee_p50,
+                ..CongestionSignals::default()
+            },
+            ..NetworkStats::default()
+        }
+    }
+
+    #[test]
+    fn schedule_without_limits_is_ready() {
+        let unmet = schedule().unmet_conditions(Utc::now(), &stats(CongestionLevel::Low, None));
+        assert!(unmet.is_empty());
+    }
+
+    #[test]
+    fn schedule_waits_until_not_before() {
+        let now = Utc::now();
+        let schedule = DeploymentSchedule {
+            not_before: Some(now + chrono::Duration::minutes(5)),
+            ..schedule()
+        };
+        let network = stats(CongestionLevel::Low, None);
+        assert_eq!(schedule.unmet_conditions(now, &network).len(), 1);
+        assert!(schedule
+            .unmet_conditions(now + chrono::Duration::minutes(5), &network)
+            .is_empty());
+    }
+
+    #[test]
+    fn schedule_allows_up_to_max_congestion() {
+        let schedule = schedule();
+        assert!(schedule
+            .unmet_conditions(Utc::now(), &stats(CongestionLevel::Medium, None))
+            .is_empty());
+        assert_eq!(
+            schedule
+                .unmet_conditions(Utc::now(), &stats(CongestionLevel::High, None))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn schedule_requires_priority_fee_below_limit() {
+        let schedule = DeploymentSchedule {
+            max_priority_fee_p50: Some(1_000),
+            ..schedule()
+        };
+        let now = Utc::now();
+        assert!(schedule
+            .unmet_conditions(now, &stats(CongestionLevel::Low, Some(999)))
+            .is_empty());
+        assert_eq!(
+            schedule
+                .unmet_conditions(now, &stats(CongestionLevel::Low, Some(1_000)))
+                .len(),
+            1
+        );
+        // 没有优先费数据时不能确认条件满足
+        assert_eq!(
+            schedule.unmet_conditions(now, &stats(CongestionLevel::Low, None)),
+            vec!["无法获取优先费数据".to_string()]
+        );
+    }
+
+    #[test]
+    fn schedule_reports_every_unmet_condition() {
+        let now = Utc::now();
+        let schedule = DeploymentSchedule {
+            not_before: Some(now + chrono::Duration::hours(1)),
+            max_congestion: CongestionLevel::Low,
+            max_priority_fee_p50: Some(10),
+            ..schedule()
+        };
+        let unmet = schedule.unmet_conditions(now, &stats(CongestionLevel::Critical, Some(50)));
+        assert_eq!(unmet.len(), 3);
+    }
+
+    #[test]
+    fn auto_resume_stops_after_max_attempts() {
+        let now = Utc::now();
+        let delay = chrono::Duration::minutes(1);
+        let mut state = AutoResumeState::default();
+        assert!(state.is_due(now));
+
+        state.begin_attempt(now);
+        assert!(state.record_failure(2, now, delay));
+        assert!(!state.exhausted);
+        assert!(!state.is_due(now));
+        assert!(state.is_due(now + delay));
+
+        state.begin_attempt(now + delay);
+        assert!(!state.record_failure(2, now + delay, delay));
+        assert!(state.exhausted);
+        assert_eq!(state.attempts, 2);
+        // 停止后不再自动续传
+        assert!(!state.is_due(now + chrono::Duration::days(1)));
+    }
+
+    #[test]
+    fn auto_resume_limit_is_independent_of_chunk_retries() {
+        let config = ResumeConfig {
+            max_retries: 10,
+            max_auto_resume_attempts: 1,
+            ..ResumeConfig::default()
+        };
+        let now = Utc::now();
+        let mut state = AutoResumeState::default();
+        state.begin_attempt(now);
+        assert!(!state.record_failure(config.max_auto_resume_attempts, now, chrono::Duration::minutes(1)));
+        assert!(state.exhausted);
+    }
+
+    #[test]
+    fn cost_splits_priority_fee_from_base_fee() {
+        let cost = TransactionCost::from_balances(15_000, 5_000, 1_000_000, 985_000, 0);
+        assert_eq!(cost.base_fee, 5_000);
+        assert_eq!(cost.priority_fee, 10_000);
+        assert_eq!(cost.rent_deposited, 0);
+        assert_eq!(cost.rent_refunded, 0);
+    }
+
+    #[test]
+    fn cost_books_only_created_accounts_as_rent() {
+        // 创建账户：存入的租金由指令给出
+        let deposit = TransactionCost::from_balances(5_000, 5_000, 10_000_000, 7_995_000, 2_000_000);
+        assert_eq!(deposit.rent_deposited, 2_000_000);
+        assert_eq!(deposit.rent_refunded, 0);
+        // 普通转账不是租金
+        let transfer = TransactionCost::from_balances(5_000, 5_000, 10_000_000, 7_995_000, 0);
+        assert_eq!(transfer.rent_deposited, 0);
+        assert_eq!(transfer.rent_refunded, 0);
+        // 关闭账户：退还的租金使余额增加
+        let refund = TransactionCost::from_balances(5_000, 5_000, 10_000_000, 11_995_000, 0);
+        assert_eq!(refund.rent_deposited, 0);
+        assert_eq!(refund.rent_refunded, 2_000_000);
+    }
+
+    #[test]
+    fn cost_separates_rent_deposit_from_refund_in_one_transaction() {
+        // v3 部署：为程序账户存入 3_000_000，同时buffer关闭退还 1_000_000
+        let cost = TransactionCost::from_balances(5_000, 5_000, 10_000_000, 7_995_000, 3_000_000);
+        assert_eq!(cost.rent_deposited, 3_000_000);
+        assert_eq!(cost.rent_refunded, 1_000_000);
+    }
+
+    #[test]
+    fn cost_never_reports_base_fee_above_total() {
+        let cost = TransactionCost::from_balances(5_000, 10_000, 100_000, 95_000, 0);
+        assert_eq!(cost.base_fee, 5_000);
+        assert_eq!(cost.priority_fee, 0);
+    }
+
+    #[test]
+    fn record_transaction_uses_metadata_when_available() {
+        let mut stats = CostStats {
+            estimated_remaining_fees: 20_000,
+            ..CostStats::default()
+        };
+        let cost = TransactionCost::from_balances(7_000, 5_000, 10_000_000, 7_993_000, 2_000_000);
+        stats.record_transaction(Some(&cost), 5_000);
+        stats.record_transaction(None, 5_000);
+        assert_eq!(stats.transaction_count, 2);
+        assert_eq!(stats.audited_transactions, 1);
+        assert_eq!(stats.total_fees_paid, 12_000);
+        assert_eq!(stats.priority_fees_paid, 2_000);
+        assert_eq!(stats.rent_deposited, 2_000_000);
+        assert_eq!(stats.estimated_remaining_fees, 8_000);
+    }
+}
@@ -8,9 +8,14 @@ mod api;
 mod cli;
 
 use core::{
-    types::*,
-    StateManager, ResumeEngine, NetworkAnalyzer, FeeOptimizer,
+    config::{ConfigOptions, ToolConfig},
+    blockhash::BLOCKHASH_REFRESH_INTERVAL,
+    performance::BandwidthMonitor,
+    sender::{ConfirmationTracker, SenderConfig, TransactionSender},
+    StateManager, ResumeEngine, NetworkAnalyzer, FeeOptimizer, RpcPool, RpcLimiter,
 };
+use api::routes::ApiContext;
+use solana_sdk::commitment_config::CommitmentConfig;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -24,8 +29,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::with_name("rpc_url")
                 .long("rpc-url")
                 .value_name("URL")
-                .help("Solana RPC节点URL")
-                .default_value("https://api.devnet.solana.com")
+                .help("Solana RPC节点URL (默认读取配置)")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("fallback_rpc_url")
+                .long("fallback-rpc-url")
+                .value_name("URL")
+                .help("备用RPC节点，主节点故障时自动切换 (可多次指定)")
+                .multiple(true)
+                .number_of_values(1)
                 .global(true),
         )
         .arg(
@@ -33,13 +46,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .long("keypair")
                 .short("k")
                 .value_name("PATH")
-                .help("密钥对文件路径")
-                .default_value("~/.config/solana/id.json")
+                .help("密钥对文件路径 (默认读取配置)")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("data_dir")
+                .long("data-dir")
+                .value_name("DIR")
+                .help("部署状态数据目录 (默认 ./data)")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .value_name("PATH")
+                .help("项目配置文件路径 (默认 ./sol-deploy-resume.toml)")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("cluster")
+                .long("cluster")
+                .value_name("CLUSTER")
+                .help("目标集群，同时将RPC切换为该集群的默认节点 (可用 --rpc-url 覆盖)；未指定时根据RPC节点识别")
+                .possible_values(&["mainnet", "mainnet-beta", "devnet", "testnet", "localnet"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .long("yes")
+                .short("y")
+                .help("跳过主网操作确认")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .short("p")
+                .value_name("NAME")
+                .help("使用配置文件中的profile (如 devnet、mainnet)")
                 .global(true),
         )
         .subcommand(
             SubCommand::with_name("deploy")
-                .about("部署新程序")
+                .about("部署新程序或升级已部署的程序")
                 .arg(
                     Arg::with_name("program_file")
                         .long("program-file")
@@ -54,6 +104,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .help("加载器版本 (v3/v4)")
                         .possible_values(&["v3", "v4"])
                         .default_value("v4"),
+                )
+                .arg(
+                    Arg::with_name("use_tpu")
+                        .long("use-tpu")
+                        .help("同时直接发送到即将出块的leader的TPU端口"),
+                )
+                .arg(
+                    Arg::with_name("use_nonce")
+                        .long("use-nonce")
+                        .help("创建持久nonce账户，写入交易不受区块哈希有效期限制"),
+                )
+                .arg(
+                    Arg::with_name("nonce_account")
+                        .long("nonce-account")
+                        .value_name("PUBKEY")
+                        .help("使用已有的持久nonce账户（授权账户须为付款账户）"),
+                )
+                .arg(
+                    Arg::with_name("upgrade")
+                        .long("upgrade")
+                        .value_name("PROGRAM_ID")
+                        .help("升级已部署的程序（须提供其升级授权）"),
+                )
+                .arg(
+                    Arg::with_name("fee_payer")
+                        .long("fee-payer")
+                        .value_name("KEYPAIR")
+                        .help("支付交易费用和租金的密钥对，默认使用 --keypair"),
+                )
+                .arg(
+                    Arg::with_name("buffer_authority")
+                        .long("buffer-authority")
+                        .value_name("KEYPAIR")
+                        .help("buffer写入授权的密钥对，默认由付款账户兼任"),
+                )
+                .arg(
+                    Arg::with_name("upgrade_authority")
+                        .long("upgrade-authority")
+                        .value_name("KEYPAIR")
+                        .help("程序升级授权的密钥对，默认由付款账户兼任"),
+                )
+                .arg(
+                    Arg::with_name("program_keypair")
+                        .long("program-keypair")
+                        .value_name("KEYPAIR")
+                        .help("新程序地址的密钥对，默认随机生成"),
+                )
+                .arg(
+                    Arg::with_name("delta")
+                        .long("delta")
+                        .requires("upgrade")
+                        .help("增量升级：与链上程序相同的块不再写入（Loader v4），Loader v3 只报告冗余量"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schedule")
+                .about("排队计划部署，由 watch 守护进程在条件满足时自动开始")
+                .arg(
+                    Arg::with_name("program_file")
+                        .long("program-file")
+                        .value_name("PATH")
+                        .help("程序.so文件路径")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("loader_version")
+                        .long("loader-version")
+                        .value_name("VERSION")
+                        .help("加载器版本 (v3/v4)")
+                        .possible_values(&["v3", "v4"])
+                        .default_value("v4"),
+                )
+                .arg(
+                    Arg::with_name("not_before")
+                        .long("not-before")
+                        .value_name("TIME")
+                        .help("最早开始时间 (RFC3339，如 2024-01-01T02:00:00Z)"),
+                )
+                .arg(
+                    Arg::with_name("max_congestion")
+                        .long("max-congestion")
+                        .value_name("LEVEL")
+                        .help("允许开始的最高拥堵等级")
+                        .possible_values(&["low", "medium", "high", "critical"])
+                        .default_value("medium"),
+                )
+                .arg(
+                    Arg::with_name("max_fee_p50")
+                        .long("max-fee-p50")
+                        .value_name("MICRO_LAMPORTS")
+                        .help("只在优先费 p50 低于该值时开始 (micro-lamports/CU)"),
+                )
+                .arg(
+                    Arg::with_name("use_nonce")
+                        .long("use-nonce")
+                        .help("开始时创建持久nonce账户"),
+                )
+                .arg(
+                    Arg::with_name("nonce_account")
+                        .long("nonce-account")
+                        .value_name("PUBKEY")
+                        .help("使用已有的持久nonce账户（授权账户须为付款账户）"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("守护进程：条件满足时自动执行计划部署，并自动续传失败的部署")
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .value_name("SECONDS")
+                        .help("检查间隔（秒）")
+                        .default_value("60"),
+                )
+                .arg(
+                    Arg::with_name("use_tpu")
+                        .long("use-tpu")
+                        .help("同时直接发送到即将出块的leader的TPU端口"),
                 ),
         )
         .subcommand(
@@ -65,6 +233,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .value_name("ID")
                         .help("部署ID")
                         .required(true),
+                )
+                .arg(
+                    Arg::with_name("use_tpu")
+                        .long("use-tpu")
+                        .help("同时直接发送到即将出块的leader的TPU端口"),
+                )
+                .arg(
+                    Arg::with_name("fee_payer")
+                        .long("fee-payer")
+                        .value_name("KEYPAIR")
+                        .help("支付交易费用和租金的密钥对，默认使用 --keypair"),
+                )
+                .arg(
+                    Arg::with_name("buffer_authority")
+                        .long("buffer-authority")
+                        .value_name("KEYPAIR")
+                        .help("buffer写入授权的密钥对，仅在剩余步骤需要时读取"),
+                )
+                .arg(
+                    Arg::with_name("upgrade_authority")
+                        .long("upgrade-authority")
+                        .value_name("KEYPAIR")
+                        .help("程序升级授权的密钥对，仅在剩余步骤需要时读取"),
+                )
+                .arg(
+                    Arg::with_name("program_keypair")
+                        .long("program-keypair")
+                        .value_name("KEYPAIR")
+                        .help("新程序地址的密钥对，仅在程序账户尚未创建时读取"),
                 ),
         )
         .subcommand(
@@ -78,6 +275,98 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .required(false),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("advise")
+                .about("比较续传与重新开始的费用，并给出配置建议")
+                .arg(
+                    Arg::with_name("deployment_id")
+                        .value_name("ID")
+                        .help("部署ID")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["table", "json"])
+                        .default_value("table")
+                        .help("输出格式"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set-buffer-authority")
+                .about("把部署当前buffer的写入授权转移给其他账户")
+                .arg(
+                    Arg::with_name("deployment_id")
+                        .value_name("ID")
+                        .help("部署ID")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("new_authority")
+                        .long("new-authority")
+                        .value_name("PUBKEY")
+                        .help("新的buffer授权账户")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("fee_payer")
+                        .long("fee-payer")
+                        .value_name("KEYPAIR")
+                        .help("支付交易费用和租金的密钥对，默认使用 --keypair"),
+                )
+                .arg(
+                    Arg::with_name("buffer_authority")
+                        .long("buffer-authority")
+                        .value_name("KEYPAIR")
+                        .help("当前buffer授权的密钥对，默认使用 --keypair"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set-upgrade-authority")
+                .about("修改已部署程序的升级授权，或使程序不可升级")
+                .arg(
+                    Arg::with_name("deployment_id")
+                        .value_name("ID")
+                        .help("部署ID")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("new_authority")
+                        .long("new-authority")
+                        .value_name("PUBKEY")
+                        .help("新的升级授权账户")
+                        .required_unless("final")
+                        .conflicts_with("final"),
+                )
+                .arg(
+                    Arg::with_name("final")
+                        .long("final")
+                        .help("移除升级授权，程序将永久不可升级"),
+                )
+                .arg(
+                    Arg::with_name("confirm_program_id")
+                        .long("confirm-program-id")
+                        .value_name("PROGRAM_ID")
+                        .requires("final")
+                        .help("以程序ID确认使程序不可升级，省略时交互输入"),
+                )
+                .arg(
+                    Arg::with_name("fee_payer")
+                        .long("fee-payer")
+                        .value_name("KEYPAIR")
+                        .help("支付交易费用和租金的密钥对，默认使用 --keypair"),
+                )
+                .arg(
+                    Arg::with_name("upgrade_authority")
+                        .long("upgrade-authority")
+                        .value_name("KEYPAIR")
+                        .help("当前升级授权的密钥对，默认使用 --keypair"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("list")
                 .about("列出所有部署")
@@ -107,6 +396,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .value_name("PORT")
                         .help("服务器端口")
                         .default_value("8080"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .help("同时运行守护循环，自动执行计划部署并续传失败的部署"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .value_name("SECONDS")
+                        .help("守护循环检查间隔（秒）")
+                        .default_value("60"),
                 ),
         )
         .subcommand(
@@ -118,19 +419,189 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .value_name("SECONDS")
                         .help("分析持续时间（秒）")
                         .default_value("60"),
+                )
+                .arg(
+                    Arg::with_name("probe_throughput")
+                        .long("probe-throughput")
+                        .help("用 simulateTransaction 提交满载写入交易主动探测吞吐量"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["table", "json"])
+                        .default_value("table")
+                        .help("输出格式"),
+                )
+                .arg(
+                    Arg::with_name("windows")
+                        .long("windows")
+                        .value_name("COUNT")
+                        .default_value("5")
+                        .help("推荐的部署时间窗口数量"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("导出部署记录，用于在其他机器上续传")
+                .arg(
+                    Arg::with_name("deployment_id")
+                        .value_name("ID")
+                        .help("部署ID")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("PATH")
+                        .help("导出文件路径 (默认 <ID>.bundle.json)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("导入部署记录")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("导出文件路径")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("program_file")
+                        .long("program-file")
+                        .value_name("PATH")
+                        .help("本机上的程序.so文件路径"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("覆盖已存在的同ID部署"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("配置管理")
+                .subcommand(SubCommand::with_name("show").about("显示合并后的生效配置")),
+        )
         .get_matches();
 
-    // 获取全局参数
-    let rpc_url = matches.value_of("rpc_url").unwrap().to_string();
-    let keypair_path = matches.value_of("keypair").unwrap();
+    // 加载分层配置
+    let mut config_options = ConfigOptions {
+        config_file: matches.value_of("config").map(std::path::PathBuf::from),
+        profile: matches.value_of("profile").map(String::from),
+        cluster: matches.value_of("cluster").map(str::parse).transpose()?,
+        rpc_url: matches.value_of("rpc_url").map(String::from),
+        fallback_rpc_urls: matches
+            .values_of("fallback_rpc_url")
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default(),
+        keypair_path: matches.value_of("keypair").map(String::from),
+        data_dir: matches.value_of("data_dir").map(String::from),
+        detected_cluster: None,
+    };
+    let mut tool_config = ToolConfig::load(&config_options)?;
+
+    // 配置命令不需要打开数据库
+    if let ("config", Some(sub_matches)) = matches.subcommand() {
+        cli::config::handle_config(sub_matches, &tool_config)?;
+        return Ok(());
+    }
+
+    // 所有RPC请求共享同一个限速器，包括下面的集群识别和校验
+    let mut rpc_limiter = RpcLimiter::new(tool_config.rate_limit.clone());
+    // 访问网络的命令需要确认所有RPC节点（包括备用节点）都属于所选集群，
+    // 节点池会切换到备用节点，写入交易也会广播到所有节点
+    if matches!(
+        matches.subcommand_name(),
+        Some(
+            "deploy"
+                | "resume"
+                | "import"
+                | "server"
+                | "analyze"
+                | "watch"
+                | "advise"
+                | "set-buffer-authority"
+                | "set-upgrade-authority"
+        )
+    ) {
+        // 未指定集群且无法从RPC地址判断时，按主节点的创世哈希识别，再以该集群的默认值重新加载配置
+        if !tool_config.cluster_explicit && core::cluster::Cluster::from_rpc_url(&tool_config.rpc_url).is_none() {
+            let detected = core::cluster::Cluster::detect(&tool_config.rpc_url, &rpc_limiter).await?;
+            if detected != tool_config.cluster {
+                println!("🔎 根据RPC节点识别集群: {}", detected);
+                config_options.detected_cluster = Some(detected);
+                tool_config = ToolConfig::load(&config_options)?;
+                rpc_limiter = rpc_limiter.with_config(tool_config.rate_limit.clone());
+            }
+        }
+        for url in tool_config.rpc_urls() {
+            tool_config.cluster.verify_rpc(&url, &rpc_limiter).await?;
+        }
+    }
+    let cluster = tool_config.cluster;
+    // 会发送上传交易的命令（包括带守护循环的服务器）
+    let uploads = match matches.subcommand() {
+        ("deploy" | "resume" | "watch", _) => true,
+        ("server", Some(sub_matches)) => sub_matches.is_present("watch"),
+        _ => false,
+    };
+    // 主网上的写操作需要确认
+    let authority_change = matches!(
+        matches.subcommand_name(),
+        Some("set-buffer-authority" | "set-upgrade-authority")
+    );
+    if uploads || authority_change {
+        cli::confirm::confirm_cluster_operation(cluster, matches.is_present("yes"))?;
+    }
+    let data_dir = std::path::Path::new(&tool_config.data_dir);
 
     // 初始化组件
-    let state_manager = StateManager::new("./data/deployments.db")?;
-    let resume_engine = ResumeEngine::new(rpc_url.clone());
-    let mut network_analyzer = NetworkAnalyzer::new(rpc_url.clone());
-    let mut fee_optimizer = FeeOptimizer::new(rpc_url.clone());
+    std::fs::create_dir_all(data_dir)?;
+    let state_manager = StateManager::new(data_dir.join("deployments.db"))?;
+    let rpc_pool = RpcPool::new(tool_config.rpc_urls(), CommitmentConfig::confirmed())
+        .with_limiter(rpc_limiter);
+    if uploads || authority_change || matches!(matches.subcommand_name(), Some("import" | "analyze" | "advise")) {
+        rpc_pool.refresh_health().await;
+    }
+    // 长时间运行的命令在后台持续检查节点健康
+    let _health_monitor = uploads
+        .then(|| rpc_pool.spawn_health_monitor(std::time::Duration::from_secs(30)));
+    let use_tpu = tool_config.resume.use_tpu
+        || matches.subcommand().1.map_or(false, |sub| sub.is_present("use_tpu"));
+    let confirmation_tracker = ConfirmationTracker::default();
+    let mut sender = TransactionSender::new(
+        rpc_pool.clone(),
+        SenderConfig {
+            use_tpu,
+            ..SenderConfig::default()
+        },
+    )
+    .with_confirmation_tracker(confirmation_tracker.clone());
+    if uploads {
+        if let Err(e) = sender.connect_tpu().await {
+            eprintln!("⚠️  {}，仅通过RPC发送交易", e);
+        }
+    }
+    let bandwidth_monitor = BandwidthMonitor::new();
+    let resume_engine = ResumeEngine::with_sender(rpc_pool.clone(), sender)
+        .with_bandwidth_monitor(bandwidth_monitor.clone());
+    let _blockhash_refresher = uploads
+        .then(|| resume_engine.blockhash_manager().spawn_refresher(BLOCKHASH_REFRESH_INTERVAL));
+    let mut network_analyzer = NetworkAnalyzer::with_pool(rpc_pool.clone())
+        .with_bandwidth_monitor(bandwidth_monitor)
+        .with_confirmation_tracker(confirmation_tracker);
+    network_analyzer.set_congestion_thresholds(tool_config.congestion.clone());
+    network_analyzer.set_throughput_probe(
+        tool_config.resume.throughput_probe
+            || matches.subcommand().1.map_or(false, |sub| sub.is_present("probe_throughput")),
+    );
+    let mut fee_optimizer = FeeOptimizer::with_pool(rpc_pool.clone());
+    fee_optimizer.set_priority_fee_cap(tool_config.resume.priority_fee_cap_lamports);
+    fee_optimizer.set_max_chunk_size(tool_config.resume.chunk_size);
 
     match matches.subcommand() {
         ("deploy", Some(sub_matches)) => {
@@ -140,7 +611,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 resume_engine,
                 &mut network_analyzer,
                 &mut fee_optimizer,
-                keypair_path,
+                &tool_config,
             )
             .await?;
         }
@@ -150,12 +621,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 state_manager,
                 resume_engine,
                 &mut network_analyzer,
-                keypair_path,
+                &tool_config,
+            )
+            .await?;
+        }
+        ("schedule", Some(sub_matches)) => {
+            cli::schedule::handle_schedule(sub_matches, state_manager, &tool_config).await?;
+        }
+        ("watch", Some(sub_matches)) => {
+            cli::watch::handle_watch(
+                sub_matches,
+                state_manager,
+                resume_engine,
+                &mut network_analyzer,
+                &mut fee_optimizer,
+                &tool_config,
             )
             .await?;
         }
         ("status", Some(sub_matches)) => {
-            cli::status::handle_status(sub_matches, &state_manager).await?;
+            cli::status::handle_status(sub_matches, &state_manager, &tool_config).await?;
+        }
+        ("set-buffer-authority", Some(sub_matches)) => {
+            cli::authority::handle_set_buffer_authority(sub_matches, state_manager, resume_engine, &tool_config).await?;
+        }
+        ("set-upgrade-authority", Some(sub_matches)) => {
+            cli::authority::handle_set_upgrade_authority(sub_matches, state_manager, resume_engine, &tool_config).await?;
+        }
+        ("advise", Some(sub_matches)) => {
+            cli::advise::handle_advise(
+                sub_matches,
+                &state_manager,
+                &mut network_analyzer,
+                &mut fee_optimizer,
+                &rpc_pool,
+                &tool_config,
+            )
+            .await?;
         }
         ("list", Some(sub_matches)) => {
             cli::list::handle_list(sub_matches, &state_manager).await?;
@@ -163,11 +665,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         ("cleanup", Some(sub_matches)) => {
             cli::cleanup::handle_cleanup(sub_matches, state_manager).await?;
         }
+        ("server", Some(sub_matches)) if sub_matches.is_present("watch") => {
+            let interval = cli::watch::watch_interval(sub_matches)?;
+            let api_context = ApiContext::new(state_manager.reader(), rpc_pool.clone(), tool_config.clone());
+            tokio::select! {
+                result = cli::server::handle_server(sub_matches, api_context) => result?,
+                result = cli::watch::run_watch(
+                    state_manager,
+                    &resume_engine,
+                    &mut network_analyzer,
+                    &mut fee_optimizer,
+                    &tool_config,
+                    interval,
+                ) => result?,
+            }
+        }
         ("server", Some(sub_matches)) => {
-            cli::server::handle_server(sub_matches, rpc_url).await?;
+            let api_context = ApiContext::new(state_manager.reader(), rpc_pool.clone(), tool_config.clone());
+            cli::server::handle_server(sub_matches, api_context).await?;
+        }
+        ("export", Some(sub_matches)) => {
+            cli::export::handle_export(sub_matches, &state_manager).await?;
+        }
+        ("import", Some(sub_matches)) => {
+            cli::import::handle_import(sub_matches, state_manager, resume_engine).await?;
         }
         ("analyze", Some(sub_matches)) => {
-            cli::analyze::handle_analyze(sub_matches, &mut network_analyzer).await?;
+            cli::analyze::handle_analyze(sub_matches, &mut network_analyzer, &rpc_pool, &state_manager, cluster)
+                .await?;
         }
         _ => {
             println!("使用 --help 查看可用命令");